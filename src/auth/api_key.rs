@@ -0,0 +1,97 @@
+//! API key authentication for service-to-service callers.
+//!
+//! Callers send a key in the `X-API-Key` header. Keys are looked up by their
+//! SHA-256 hash in the `ApiKeys` table, so a leaked table doesn't leak usable keys.
+
+use sha2::{ Digest, Sha256 };
+
+use crate::error::AppError;
+
+use super::{ credentials::CredentialStore, jwt::Claims };
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Hashes an API key for storage and lookup
+///
+/// # Arguments
+///
+/// * `key` - plain text API key
+///
+/// # Returns
+///
+/// Hex encoded SHA-256 hash of the key
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Validates an API key against the ApiKeys table
+///
+/// # Arguments
+///
+/// * `store` - where keys are looked up, the DynamoDB client outside of tests
+/// * `key` - plain text API key from the request
+///
+/// # Returns
+///
+/// Claims equivalent to a jwt, with `sub` set to `api_key:<name>` and the key's role
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) App error variant if the key is unknown or revoked
+///
+/// Returns Database Error (500) App error variant if the lookup fails
+pub async fn validate_api_key(
+    store: &dyn CredentialStore,
+    key: &str
+) -> Result<Claims, AppError> {
+    let item = store
+        .api_key(&hash_api_key(key)).await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+
+    let revoked = item
+        .get("revoked")
+        .and_then(|v| v.as_s().ok())
+        .map(|s| s == "true")
+        .unwrap_or(false);
+
+    if revoked {
+        return Err(AppError::Unauthorized("API key has been revoked".to_string()));
+    }
+
+    let name = item
+        .get("name")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+
+    let role = item
+        .get("role")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .ok_or_else(|| AppError::Unauthorized("API key has no role".to_string()))?;
+
+    Ok(Claims {
+        sub: format!("api_key:{}", name),
+        email: String::new(),
+        exp: 0,
+        iat: 0,
+        role,
+        pwd_changed_at: None,
+        token_version: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_keys_with_sha256_hex() {
+        assert_eq!(
+            hash_api_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash_api_key("abc"), hash_api_key("abc "));
+    }
+}
@@ -0,0 +1,121 @@
+//! Lookups behind request authentication.
+//!
+//! `middleware::request_claims` reads API keys and token states through a `CredentialStore`
+//! instead of a DynamoDB client, so which credential wins and when one is rejected can be
+//! tested without a table. The DynamoDB `Client` is the store outside of tests.
+
+use std::{ collections::HashMap, sync::Arc };
+
+use aws_sdk_dynamodb::{ types::AttributeValue, Client };
+use tracing::warn;
+
+use crate::{ db::users::{ get_token_state, TokenState }, error::AppError };
+
+/// Where the credentials of a request are looked up
+#[async_trait::async_trait]
+pub trait CredentialStore: Send + Sync {
+    /// Gets the ApiKeys item of a hashed key
+    ///
+    /// # Arguments
+    ///
+    /// * `key_hash` - the key hashed with `api_key::hash_api_key`
+    ///
+    /// # Returns
+    ///
+    /// 'some' item if a key has that hash, 'none' otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns Database Error (500) App error variant if the lookup fails
+    async fn api_key(
+        &self,
+        key_hash: &str
+    ) -> Result<Option<HashMap<String, AttributeValue>>, AppError>;
+
+    /// Gets what a user's tokens are checked against, see `db::users::get_token_state`
+    ///
+    /// # Returns
+    ///
+    /// 'some' TokenState if a user has that id, 'none' otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns Database Error (500) App error variant if the read fails
+    async fn token_state(&self, user_id: &str) -> Result<Option<TokenState>, AppError>;
+}
+
+/// Credential store shared with request handlers
+pub type SharedCredentials = Arc<dyn CredentialStore>;
+
+#[async_trait::async_trait]
+impl CredentialStore for Client {
+    async fn api_key(
+        &self,
+        key_hash: &str
+    ) -> Result<Option<HashMap<String, AttributeValue>>, AppError> {
+        let response = self
+            .get_item()
+            .table_name("ApiKeys")
+            .key("key_hash", AttributeValue::S(key_hash.to_string()))
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to look up api key: {:?}", e);
+                AppError::DatabaseError("Failed to look up api key".to_string())
+            })?;
+
+        Ok(response.item)
+    }
+
+    async fn token_state(&self, user_id: &str) -> Result<Option<TokenState>, AppError> {
+        get_token_state(self, user_id).await
+    }
+}
+
+/// Credentials kept in memory, for tests of authentication
+#[cfg(test)]
+#[derive(Default)]
+pub struct StubCredentials {
+    api_keys: HashMap<String, HashMap<String, AttributeValue>>,
+    token_states: HashMap<String, TokenState>,
+}
+
+#[cfg(test)]
+impl StubCredentials {
+    /// Adds an API key with the given name and role
+    pub fn with_api_key(mut self, key: &str, name: &str, role: &str, revoked: bool) -> Self {
+        let item = HashMap::from([
+            ("name".to_string(), AttributeValue::S(name.to_string())),
+            ("role".to_string(), AttributeValue::S(role.to_string())),
+            ("revoked".to_string(), AttributeValue::S(revoked.to_string())),
+        ]);
+        self.api_keys.insert(super::api_key::hash_api_key(key), item);
+        self
+    }
+
+    /// Adds an active user whose sessions were revoked `token_version` times
+    pub fn with_user(mut self, id: &str, token_version: i64) -> Self {
+        let state = TokenState {
+            password_changed_at: None,
+            token_version,
+            deleted_at: None,
+            is_active: true,
+        };
+        self.token_states.insert(id.to_string(), state);
+        self
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl CredentialStore for StubCredentials {
+    async fn api_key(
+        &self,
+        key_hash: &str
+    ) -> Result<Option<HashMap<String, AttributeValue>>, AppError> {
+        Ok(self.api_keys.get(key_hash).cloned())
+    }
+
+    async fn token_state(&self, user_id: &str) -> Result<Option<TokenState>, AppError> {
+        Ok(self.token_states.get(user_id).cloned())
+    }
+}
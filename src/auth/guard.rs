@@ -0,0 +1,114 @@
+//! Authorization checks for resolvers.
+//!
+//! Claims are attached to the GraphQL request by the handler when the caller
+//! authenticated. A caller whose own `role` is `Admin` can act on every pantry,
+//! everyone else needs a `PantryAccess` row of a sufficient level.
+
+use async_graphql::{ Context, Error };
+use aws_sdk_dynamodb::Client;
+
+use crate::{
+    db::pantry_access::get_pantry_access,
+    error::AppError,
+    models::pantry_access::AccessLevel,
+};
+
+use super::jwt::Claims;
+
+/// Gets the authenticated caller's claims
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) if the request carried no valid credentials
+pub fn require_claims<'a>(ctx: &Context<'a>) -> Result<&'a Claims, Error> {
+    ctx.data::<Claims>().map_err(|_| {
+        AppError::Unauthorized("Authentication required".to_string()).to_graphql_error()
+    })
+}
+
+/// Whether the caller's own role is Admin
+pub fn is_admin(claims: &Claims) -> bool {
+    AccessLevel::from_string(&claims.role) == Some(AccessLevel::Admin)
+}
+
+/// Ensures the caller's own role is Admin
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) if the caller isn't authenticated
+///
+/// Returns Forbidden (403) if the caller isn't an Admin
+pub fn require_admin<'a>(ctx: &Context<'a>) -> Result<&'a Claims, Error> {
+    let claims = require_claims(ctx)?;
+
+    if !is_admin(claims) {
+        return Err(AppError::Forbidden("Admin role is required".to_string()).to_graphql_error());
+    }
+
+    Ok(claims)
+}
+
+/// Ensures the caller's own role is at least `minimum`, regardless of pantry
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) if the caller isn't authenticated
+///
+/// Returns Forbidden (403) if the caller's role is too low
+pub fn require_role<'a>(ctx: &Context<'a>, minimum: AccessLevel) -> Result<&'a Claims, Error> {
+    let claims = require_claims(ctx)?;
+
+    let allowed = AccessLevel::from_string(&claims.role).is_some_and(|role| role.at_least(minimum));
+    if !allowed {
+        return Err(
+            AppError::Forbidden(format!("{} role is required", minimum.to_str())).to_graphql_error()
+        );
+    }
+
+    Ok(claims)
+}
+
+/// Ensures the caller has at least `minimum` access to a pantry
+///
+/// # Arguments
+///
+/// * `ctx` - async-graphql Context object, contains the caller's claims
+/// * `db_client` - DynamoDB client used to read the caller's access row
+/// * `pantry_id` - ID of the pantry being acted on
+/// * `minimum` - lowest access level allowed
+///
+/// # Returns
+///
+/// The caller's claims
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) if the caller isn't authenticated
+///
+/// Returns Forbidden (403) if the caller's access to the pantry is too low
+pub async fn require_pantry_access<'a>(
+    ctx: &Context<'a>,
+    db_client: &Client,
+    pantry_id: &str,
+    minimum: AccessLevel
+) -> Result<&'a Claims, Error> {
+    let claims = require_claims(ctx)?;
+
+    if is_admin(claims) {
+        return Ok(claims);
+    }
+
+    let access = get_pantry_access(db_client, pantry_id, &claims.sub).await.map_err(|e|
+        e.to_graphql_error()
+    )?;
+
+    match access {
+        Some(access) if access.access_level.at_least(minimum) => Ok(claims),
+        _ =>
+            Err(
+                AppError::Forbidden(
+                    format!("{} access to this pantry is required", minimum.to_str())
+                ).to_graphql_error()
+            ),
+    }
+}
@@ -1,53 +1,232 @@
-use std::{ env, time::{ SystemTime, UNIX_EPOCH } };
+use std::env;
 
+use async_graphql::SimpleObject;
+use chrono::{ DateTime, Utc };
 use serde::{ Deserialize, Serialize };
-use jsonwebtoken::{ decode, encode, DecodingKey, EncodingKey, Header, Validation };
+use jsonwebtoken::{ decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation };
 
 use crate::error::AppError;
-#[derive(Debug, Serialize, Deserialize, Clone)]
+
+/// How long an issued token stays valid, in seconds
+pub const TOKEN_LIFETIME_SECS: usize = 24 * 3600;
+
+#[derive(Debug, Serialize, Deserialize, Clone, SimpleObject)]
 pub struct Claims {
-    pub sub: String, // user ID
+    pub sub: String, // user ID, or `api_key:<name>` for api key callers
     pub email: String,
     pub exp: usize,
+    #[serde(default)]
+    pub iat: usize,
+    #[serde(default)]
+    pub role: String,
+    // unix time of the user's last password change when the token was issued, see
+    // `middleware::ensure_token_current`
+    #[serde(default)]
+    pub pwd_changed_at: Option<i64>,
+    // the user's `token_version` when the token was issued, tokens from before it existed are 0
+    #[serde(default)]
+    pub token_version: i64,
+}
+
+/// A freshly signed jwt along with the moment it stops being valid
+#[derive(Debug, Clone)]
+pub struct IssuedToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Gets the secret tokens are signed and validated with, from the `JWT_SECRET` env var
+///
+/// # Returns
+///
+/// 'some' secret if the var is set and not blank, 'none' otherwise
+pub fn jwt_secret() -> Option<String> {
+    env::var("JWT_SECRET")
+        .ok()
+        .filter(|secret| !secret.trim().is_empty())
+}
+
+/// Builds the error for a server without a usable `JWT_SECRET`, see `jwt_secret`
+pub fn misconfigured() -> AppError {
+    AppError::InternalServerError("Authentication is misconfigured on the server".to_string())
 }
 
-// Create jwt from user id and email
-pub fn create_token(user_id: &str, email: &str) -> Result<String, AppError> {
-    // Load secret from ENV
-    let jwt_secret = env::var("JWT_SECRET").map_err(|e| AppError::EnvError(e))?;
+// Create jwt from user id, email and role, shared by login and any refresh flow
+// `password_changed_at` and `token_version` are the user's, so the token stops working once
+// the password changes or the user's sessions are revoked
+// `now` is the issue time, read from the schema's clock so expiry can be tested
+pub fn create_token(
+    user_id: &str,
+    email: &str,
+    role: &str,
+    password_changed_at: Option<DateTime<Utc>>,
+    token_version: i64,
+    now: DateTime<Utc>
+) -> Result<IssuedToken, AppError> {
+    let jwt_secret = jwt_secret().ok_or_else(misconfigured)?;
     let secret_as_bytes = jwt_secret.as_bytes();
 
-    let expiration =
-        (
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| AppError::InternalServerError(e.to_string()))?
-                .as_secs() as usize
-        ) +
-        24 * 3600;
+    let issued_at = usize::try_from(now.timestamp()).map_err(|_| {
+        AppError::InternalServerError("Token issue time is before the epoch".to_string())
+    })?;
+
+    let expiration = issued_at + TOKEN_LIFETIME_SECS;
 
     let claims = Claims {
         sub: user_id.to_string(),
         email: email.to_string(),
         exp: expiration,
+        iat: issued_at,
+        role: role.to_string(),
+        pwd_changed_at: password_changed_at.map(|at| at.timestamp()),
+        token_version,
     };
 
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret_as_bytes)).map_err(|e|
-        AppError::Unauthorized(e.to_string())
-    )
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret_as_bytes)
+    ).map_err(|e| AppError::Unauthorized(e.to_string()))?;
+
+    let expires_at = DateTime::<Utc>
+        ::from_timestamp(expiration as i64, 0)
+        .ok_or_else(|| AppError::InternalServerError("Invalid token expiry".to_string()))?;
+
+    Ok(IssuedToken { token, expires_at })
 }
 
-// Validate token against jwt secret
-pub fn validate_token(token: &str) -> Result<Claims, AppError> {
-    // Load secret from ENV
-    let jwt_secret = env::var("JWT_SECRET").map_err(|e| AppError::EnvError(e))?;
-    let secret_as_bytes = jwt_secret.as_bytes();
+// Validate token against jwt secret, see `jwt_secret`
+// `now` is checked against the token's expiry, read from a clock so expiry can be tested
+pub fn validate_token(token: &str, secret: &str, now: DateTime<Utc>) -> Result<Claims, AppError> {
+    let secret_as_bytes = secret.as_bytes();
+
+    // a token without a subject must not decode to a caller with an empty user id
+    // exp is still required, but checked below against `now` instead of the system time
+    let mut validation = Validation::default();
+    validation.set_required_spec_claims(&["exp", "sub"]);
+    validation.validate_exp = false;
 
     let token_data = decode::<Claims>(
         token,
         &DecodingKey::from_secret(secret_as_bytes),
-        &Validation::default()
-    ).map_err(|e| AppError::Unauthorized(e.to_string()))?;
+        &validation
+    ).map_err(|e| {
+        match e.kind() {
+            ErrorKind::ExpiredSignature => AppError::Unauthorized("Token has expired".to_string()),
+            _ => AppError::Unauthorized(format!("Invalid token: {}", e)),
+        }
+    })?;
+
+    if i64::try_from(token_data.claims.exp).is_ok_and(|exp| exp <= now.timestamp()) {
+        return Err(AppError::Unauthorized("Token has expired".to_string()));
+    }
+
+    if token_data.claims.sub.trim().is_empty() {
+        return Err(AppError::Unauthorized("Invalid token: empty subject".to_string()));
+    }
 
     Ok(token_data.claims)
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::{ json, Value };
+
+    use crate::clock::{ Clock, FixedClock };
+
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    // every test sets the same secret, so running them in parallel is fine
+    fn use_test_secret() {
+        env::set_var("JWT_SECRET", SECRET);
+    }
+
+    // the moment tokens are issued at, far from the system time so expiry can't depend on it
+    fn clock() -> FixedClock {
+        FixedClock("2020-06-02T12:00:00Z".parse().unwrap())
+    }
+
+    fn sign(claims: Value, secret: &str) -> String {
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    fn in_an_hour() -> i64 {
+        (clock().now() + Duration::hours(1)).timestamp()
+    }
+
+    fn unauthorized(result: Result<Claims, AppError>) -> String {
+        match result {
+            Err(AppError::Unauthorized(message)) => message,
+            other => panic!("expected an unauthorized error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn issued_tokens_validate() {
+        use_test_secret();
+        let now = clock().now();
+
+        let issued = create_token("user-1", "ana@example.com", "Admin", None, 3, now).unwrap();
+        let claims = validate_token(&issued.token, SECRET, now).unwrap();
+
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.role, "Admin");
+        assert_eq!(claims.token_version, 3);
+        assert_eq!(claims.iat as i64, now.timestamp());
+        assert_eq!(issued.expires_at.timestamp(), now.timestamp() + (TOKEN_LIFETIME_SECS as i64));
+    }
+
+    #[test]
+    fn tokens_expire_after_their_lifetime() {
+        use_test_secret();
+        let issued_at = clock().now();
+        let issued = create_token("user-1", "ana@example.com", "User", None, 0, issued_at).unwrap();
+
+        let just_before = issued.expires_at - Duration::seconds(1);
+        assert!(validate_token(&issued.token, SECRET, just_before).is_ok());
+
+        let at_expiry = validate_token(&issued.token, SECRET, issued.expires_at);
+        assert_eq!(unauthorized(at_expiry), "Token has expired");
+        let just_after = issued.expires_at + Duration::seconds(1);
+        let after_expiry = validate_token(&issued.token, SECRET, just_after);
+        assert_eq!(unauthorized(after_expiry), "Token has expired");
+    }
+
+    #[test]
+    fn requires_sub_and_exp() {
+        let now = clock().now();
+
+        let no_sub = sign(json!({ "email": "ana@example.com", "exp": in_an_hour() }), SECRET);
+        assert!(unauthorized(validate_token(&no_sub, SECRET, now)).starts_with("Invalid token"));
+
+        let no_exp = sign(json!({ "sub": "user-1", "email": "ana@example.com" }), SECRET);
+        assert!(unauthorized(validate_token(&no_exp, SECRET, now)).starts_with("Invalid token"));
+    }
+
+    #[test]
+    fn rejects_an_empty_subject() {
+        let token = sign(
+            json!({ "sub": "  ", "email": "ana@example.com", "exp": in_an_hour() }),
+            SECRET
+        );
+
+        assert_eq!(
+            unauthorized(validate_token(&token, SECRET, clock().now())),
+            "Invalid token: empty subject"
+        );
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_secret() {
+        let token = sign(
+            json!({ "sub": "user-1", "email": "ana@example.com", "exp": in_an_hour() }),
+            "another-secret"
+        );
+
+        let result = validate_token(&token, SECRET, clock().now());
+        assert!(unauthorized(result).starts_with("Invalid token"));
+    }
+}
@@ -1,34 +1,275 @@
+use aws_sdk_dynamodb::Client;
+use chrono::{ DateTime, Utc };
 use axum::{
     body::Body,
+    extract::Extension,
     http::{ header::AUTHORIZATION, HeaderMap, Request },
     middleware::Next,
     response::Response,
 };
 
+use tracing::error;
+
 use crate::error::AppError;
 
-use super::jwt::validate_token;
+use super::{
+    api_key::{ validate_api_key, API_KEY_HEADER },
+    credentials::CredentialStore,
+    jwt::{ jwt_secret, misconfigured, validate_token, Claims },
+};
 
-pub async fn auth_middleware<B>(
+pub async fn auth_middleware(
+    Extension(db_client): Extension<Client>,
     headers: HeaderMap,
     request: Request<Body>,
     next: Next
 ) -> Result<Response, AppError> {
-    let auth_header = headers
-        .get(AUTHORIZATION)
-        .and_then(|value| value.to_str().ok())
-        .ok_or_else(|| AppError::Unauthorized("No authorization header".into()))?;
+    let claims = request_claims(&headers, &db_client, Utc::now()).await?.ok_or_else(||
+        AppError::Unauthorized("No authorization header or API key".into())
+    )?;
+
+    let mut request = request;
+    request.extensions_mut().insert(claims);
+
+    Ok(next.run(request).await)
+}
 
-    if !auth_header.starts_with("Bearer ") {
-        return Err(AppError::Unauthorized("Invalid token format".into()));
+/// Resolves the caller's claims from either a bearer token or an API key
+///
+/// When both an `Authorization` header and an `X-API-Key` header are sent, the
+/// bearer token wins and the API key is ignored, so a user acting through a
+/// service keeps their own identity.
+///
+/// # Arguments
+///
+/// * `headers` - request headers
+/// * `store` - where API keys and token states are looked up, the DynamoDB client outside
+///   of tests
+/// * `now` - the current time, bearer tokens expiring by then are rejected
+///
+/// # Returns
+///
+/// 'some' Claims if the caller authenticated, 'none' if neither header was sent
+///
+/// # Errors
+///
+/// Returns the error of whichever credential was checked if it is invalid
+pub async fn request_claims(
+    headers: &HeaderMap,
+    store: &dyn CredentialStore,
+    now: DateTime<Utc>
+) -> Result<Option<Claims>, AppError> {
+    if let Some(claims) = bearer_claims(headers, now)? {
+        ensure_token_current(store, &claims).await?;
+        return Ok(Some(claims));
     }
 
-    let token = &auth_header[7..];
+    let api_key = match headers.get(API_KEY_HEADER) {
+        Some(value) =>
+            value.to_str().map_err(|_| AppError::Unauthorized("Invalid API key header".into()))?,
+        None => {
+            return Ok(None);
+        }
+    };
 
-    let claims = validate_token(token)?;
+    validate_api_key(store, api_key).await.map(Some)
+}
 
-    let mut request = request;
-    request.extensions_mut().insert(claims);
+/// Rejects a user token issued before the user's latest password change or session revocation,
+/// or whose user no longer may sign in
+///
+/// Tokens carry the user's `password_changed_at` from when they were issued. A token whose
+/// value is older than the stored one, or missing while the user has changed their password,
+/// belongs to a session from before the change. Tokens also carry the user's `token_version`,
+/// which `revokeAllSessions` bumps, and one below the stored version was revoked. Tokens of
+/// users that were deleted, merged away or deactivated are rejected outright.
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) App error variant if the user is gone, deleted or deactivated,
+/// or the token predates the password change or its sessions were revoked
+///
+/// Returns Database Error (500) App error variant if the user can't be read
+pub async fn ensure_token_current(
+    store: &dyn CredentialStore,
+    claims: &Claims
+) -> Result<(), AppError> {
+    let state = store.token_state(&claims.sub).await?.ok_or_else(||
+        AppError::Unauthorized("Token belongs to a user that no longer exists".to_string())
+    )?;
 
-    Ok(next.run(request).await)
+    if state.deleted_at.is_some() {
+        return Err(AppError::Unauthorized("Token belongs to a deleted user".to_string()));
+    }
+
+    if !state.is_active {
+        return Err(AppError::Unauthorized("This account has been deactivated".to_string()));
+    }
+
+    if claims.token_version < state.token_version {
+        return Err(AppError::Unauthorized("Token has been revoked".to_string()));
+    }
+
+    let changed_at = match state.password_changed_at {
+        Some(changed_at) => changed_at.timestamp(),
+        None => {
+            return Ok(());
+        }
+    };
+
+    if claims.pwd_changed_at.is_none_or(|token_changed_at| token_changed_at < changed_at) {
+        return Err(
+            AppError::Unauthorized("Token was issued before a password change".to_string())
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads and validates an optional bearer token from request headers
+///
+/// Used by routes such as `/graphql` where some operations are public, so a
+/// missing header is not an error but a present, invalid one is.
+///
+/// # Arguments
+///
+/// * `headers` - request headers
+/// * `now` - the current time, a token expiring by then is rejected
+///
+/// # Returns
+///
+/// 'some' Claims if a valid bearer token is present, 'none' if there is no authorization header
+///
+/// # Errors
+///
+/// Returns Unauthorized (401) App error variant if the header is malformed or the token is invalid
+///
+/// Returns Internal Server Error (500) App error variant if the server is missing its jwt configuration
+pub fn bearer_claims(headers: &HeaderMap, now: DateTime<Utc>) -> Result<Option<Claims>, AppError> {
+    bearer_claims_with_secret(headers, jwt_secret().as_deref(), now)
+}
+
+/// Reads and validates an optional bearer token with the given secret, see `bearer_claims`
+///
+/// `secret` is 'none' when the server has no usable `JWT_SECRET`, see `jwt::jwt_secret`.
+fn bearer_claims_with_secret(
+    headers: &HeaderMap,
+    secret: Option<&str>,
+    now: DateTime<Utc>
+) -> Result<Option<Claims>, AppError> {
+    let auth_header = match headers.get(AUTHORIZATION) {
+        Some(value) =>
+            value
+                .to_str()
+                .map_err(|_| AppError::Unauthorized("Invalid authorization header".into()))?,
+        None => {
+            return Ok(None);
+        }
+    };
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| AppError::Unauthorized("Invalid token format".into()))?;
+
+    // A missing secret is our fault, not the caller's, so don't report it as a bad token
+    let secret = secret.ok_or_else(|| {
+        error!("JWT_SECRET is not configured, cannot validate tokens");
+        misconfigured()
+    })?;
+
+    validate_token(token, secret, now).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{ HeaderValue, StatusCode };
+
+    use crate::auth::{ credentials::StubCredentials, jwt::create_token };
+
+    use super::*;
+
+    // the same secret as the jwt tests, so they can run in parallel
+    fn user_token(id: &str, role: &str, token_version: i64) -> String {
+        std::env::set_var("JWT_SECRET", "test-secret");
+        create_token(id, "ana@example.com", role, None, token_version, Utc::now()).unwrap().token
+    }
+
+    fn with_api_key(mut headers: HeaderMap, key: &str) -> HeaderMap {
+        headers.insert(API_KEY_HEADER, HeaderValue::from_str(key).unwrap());
+        headers
+    }
+
+    fn store() -> StubCredentials {
+        StubCredentials::default()
+            .with_api_key("live-key", "reports", "Admin", false)
+            .with_api_key("old-key", "legacy", "Admin", true)
+            .with_user("user-1", 1)
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", token)).unwrap());
+        headers
+    }
+
+    #[test]
+    fn a_missing_secret_is_a_server_error() {
+        let headers = bearer("header.payload.signature");
+        let result = bearer_claims_with_secret(&headers, None, Utc::now());
+
+        let error = result.expect_err("a token can't be checked without a secret");
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.message(), "Authentication is misconfigured on the server");
+    }
+
+    #[test]
+    fn requests_without_a_token_need_no_secret() {
+        assert!(bearer_claims_with_secret(&HeaderMap::new(), None, Utc::now()).unwrap().is_none());
+    }
+
+    #[test]
+    fn bad_tokens_are_unauthorized_when_the_secret_is_set() {
+        let result = bearer_claims_with_secret(&bearer("not-a-jwt"), Some("secret"), Utc::now());
+
+        assert!(matches!(result, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn api_keys_authenticate_until_revoked() {
+        let store = store();
+
+        let headers = with_api_key(HeaderMap::new(), "live-key");
+        let claims = request_claims(&headers, &store, Utc::now()).await.unwrap().unwrap();
+        assert_eq!(claims.sub, "api_key:reports");
+        assert_eq!(claims.role, "Admin");
+
+        let headers = with_api_key(HeaderMap::new(), "old-key");
+        let error = request_claims(&headers, &store, Utc::now()).await.unwrap_err();
+        assert_eq!(error.message(), "API key has been revoked");
+
+        let headers = with_api_key(HeaderMap::new(), "unknown-key");
+        let error = request_claims(&headers, &store, Utc::now()).await.unwrap_err();
+        assert_eq!(error.message(), "Invalid API key");
+    }
+
+    #[tokio::test]
+    async fn bearer_tokens_win_over_api_keys() {
+        let store = store();
+
+        // a valid token is used even next to a revoked key
+        let headers = with_api_key(bearer(&user_token("user-1", "User", 1)), "old-key");
+        let claims = request_claims(&headers, &store, Utc::now()).await.unwrap().unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.role, "User");
+
+        // a revoked token is rejected even next to a valid key
+        let headers = with_api_key(bearer(&user_token("user-1", "User", 0)), "live-key");
+        let error = request_claims(&headers, &store, Utc::now()).await.unwrap_err();
+        assert_eq!(error.message(), "Token has been revoked");
+    }
+
+    #[tokio::test]
+    async fn requests_without_credentials_have_no_claims() {
+        assert!(request_claims(&HeaderMap::new(), &store(), Utc::now()).await.unwrap().is_none());
+    }
 }
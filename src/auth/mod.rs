@@ -1,2 +1,5 @@
 pub mod middleware;
-pub mod jwt;
\ No newline at end of file
+pub mod jwt;
+pub mod api_key;
+pub mod credentials;
+pub mod guard;
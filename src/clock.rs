@@ -0,0 +1,39 @@
+//! Source of the current time for time-dependent logic.
+//!
+//! Token expiry and opening hours read the time through a `Clock` attached to the
+//! schema instead of calling `Utc::now()` directly, so tests can pin the time.
+//! Resolvers get it with `schema::context::now`.
+
+use std::{ fmt::Debug, sync::Arc };
+
+use chrono::{ DateTime, Utc };
+
+/// Tells the current time
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock attached to the schema as data
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Reads the system clock, used outside of tests
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Always tells the same time, for tests of time-dependent logic
+#[cfg(test)]
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
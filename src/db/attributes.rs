@@ -0,0 +1,224 @@
+//! Typed reads of item attributes.
+//!
+//! Each helper names the attribute in its error, so a model that fails to read an item
+//! says which attribute was missing or had the wrong type instead of just giving up.
+//! Optional helpers treat an absent attribute as unset, but still fail on one of the
+//! wrong type.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{ DateTime, Utc };
+
+use crate::error::AppError;
+
+/// Builds the error for an attribute the item doesn't have
+fn missing(name: &str) -> AppError {
+    AppError::DatabaseError(format!("Attribute {} is missing", name))
+}
+
+/// Builds the error for an attribute holding a value that can't be read
+fn invalid(name: &str, expected: &str) -> AppError {
+    AppError::DatabaseError(format!("Attribute {} is not {}", name, expected))
+}
+
+/// Gets a required attribute
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the item has no such attribute
+pub fn get_attribute<'a>(
+    item: &'a HashMap<String, AttributeValue>,
+    name: &str
+) -> Result<&'a AttributeValue, AppError> {
+    item.get(name).ok_or_else(|| missing(name))
+}
+
+/// Gets a required string attribute
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is missing or not a string
+pub fn get_str(item: &HashMap<String, AttributeValue>, name: &str) -> Result<String, AppError> {
+    get_attribute(item, name)?
+        .as_s()
+        .cloned()
+        .map_err(|_| invalid(name, "a string"))
+}
+
+/// Gets an optional string attribute
+///
+/// # Returns
+///
+/// 'some' string if the attribute is set, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is not a string
+pub fn get_opt_str(
+    item: &HashMap<String, AttributeValue>,
+    name: &str
+) -> Result<Option<String>, AppError> {
+    item.get(name)
+        .map(|value| value.as_s().cloned().map_err(|_| invalid(name, "a string")))
+        .transpose()
+}
+
+/// Gets an optional integer attribute, stored as a Number so filters and sort keys compare
+/// it numerically
+///
+/// # Returns
+///
+/// 'some' integer if the attribute is set, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is not an integer Number
+pub fn get_opt_i64(
+    item: &HashMap<String, AttributeValue>,
+    name: &str
+) -> Result<Option<i64>, AppError> {
+    item.get(name)
+        .map(|value| {
+            value
+                .as_n()
+                .ok()
+                .and_then(|n| n.parse::<i64>().ok())
+                .ok_or_else(|| invalid(name, "an integer Number"))
+        })
+        .transpose()
+}
+
+/// Gets a flag stored as the string `true` or `false`
+///
+/// # Arguments
+///
+/// * `item` - the item to read
+/// * `name` - the attribute to read
+/// * `default` - value of the flag for items saved before it existed
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is neither `true` nor `false`
+pub fn get_bool(
+    item: &HashMap<String, AttributeValue>,
+    name: &str,
+    default: bool
+) -> Result<bool, AppError> {
+    match get_opt_str(item, name)?.as_deref() {
+        None => Ok(default),
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(_) => Err(invalid(name, "true or false")),
+    }
+}
+
+/// Gets an optional timestamp attribute, as written by `DateTime::to_string` or RFC 3339
+///
+/// # Returns
+///
+/// 'some' timestamp if the attribute is set, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is not a timestamp
+pub fn get_opt_datetime(
+    item: &HashMap<String, AttributeValue>,
+    name: &str
+) -> Result<Option<DateTime<Utc>>, AppError> {
+    get_opt_str(item, name)?
+        .map(|value| value.parse::<DateTime<Utc>>().map_err(|_| invalid(name, "a timestamp")))
+        .transpose()
+}
+
+/// Gets a required timestamp attribute, see `get_opt_datetime`
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the attribute is missing or not a
+/// timestamp
+pub fn get_datetime(
+    item: &HashMap<String, AttributeValue>,
+    name: &str
+) -> Result<DateTime<Utc>, AppError> {
+    get_opt_datetime(item, name)?.ok_or_else(|| missing(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item() -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            ("name".to_string(), AttributeValue::S("Food Bank".to_string())),
+            ("count".to_string(), AttributeValue::N("3".to_string())),
+            ("is_active".to_string(), AttributeValue::S("false".to_string())),
+            ("created_at".to_string(), AttributeValue::S("2024-05-01T12:00:00Z".to_string())),
+        ])
+    }
+
+    /// Gets the message of a Database Error
+    fn message(error: AppError) -> String {
+        match error {
+            AppError::DatabaseError(message) => message,
+            other => panic!("expected a Database Error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_strings() {
+        let item = item();
+
+        assert_eq!(get_str(&item, "name").unwrap(), "Food Bank");
+        assert_eq!(message(get_str(&item, "email").unwrap_err()), "Attribute email is missing");
+        assert_eq!(message(get_str(&item, "count").unwrap_err()), "Attribute count is not a string");
+
+        assert_eq!(get_opt_str(&item, "name").unwrap().as_deref(), Some("Food Bank"));
+        assert_eq!(get_opt_str(&item, "email").unwrap(), None);
+        assert!(get_opt_str(&item, "count").is_err());
+    }
+
+    #[test]
+    fn reads_flags() {
+        let item = item();
+
+        assert!(!get_bool(&item, "is_active", true).unwrap());
+        assert!(get_bool(&item, "is_verified", true).unwrap());
+        assert_eq!(
+            message(get_bool(&item, "name", true).unwrap_err()),
+            "Attribute name is not true or false"
+        );
+    }
+
+    #[test]
+    fn reads_timestamps() {
+        let item = item();
+        let created_at = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        assert_eq!(get_datetime(&item, "created_at").unwrap(), created_at);
+        assert_eq!(get_opt_datetime(&item, "deleted_at").unwrap(), None);
+        assert_eq!(
+            message(get_datetime(&item, "deleted_at").unwrap_err()),
+            "Attribute deleted_at is missing"
+        );
+        assert_eq!(
+            message(get_opt_datetime(&item, "name").unwrap_err()),
+            "Attribute name is not a timestamp"
+        );
+        assert!(get_opt_datetime(&item, "count").is_err());
+    }
+
+    #[test]
+    fn reads_integer_numbers() {
+        let mut item = item();
+        item.insert("ratio".to_string(), AttributeValue::N("1.5".to_string()));
+
+        assert_eq!(get_opt_i64(&item, "count").unwrap(), Some(3));
+        assert_eq!(get_opt_i64(&item, "quantity").unwrap(), None);
+        assert_eq!(
+            message(get_opt_i64(&item, "name").unwrap_err()),
+            "Attribute name is not an integer Number"
+        );
+        assert!(get_opt_i64(&item, "ratio").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! Batch read helpers shared by resolvers.
+//!
+//! DynamoDB caps `BatchGetItem` at 100 keys per request, so larger key sets
+//! are split into chunks and the results concatenated. Under throttling a batch
+//! can also come back partially processed, in which case the leftover keys are
+//! retried with exponential backoff.
+
+use std::{ collections::HashMap, future::Future, time::Duration };
+
+use aws_sdk_dynamodb::{
+    operation::batch_get_item::BatchGetItemOutput,
+    types::{ AttributeValue, KeysAndAttributes },
+    Client,
+};
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Maximum number of keys DynamoDB accepts in one `BatchGetItem` request
+pub const BATCH_GET_LIMIT: usize = 100;
+
+/// Number of requests made for a chunk before giving up on its unprocessed keys
+const MAX_BATCH_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry of unprocessed keys, doubled on each further retry
+const BATCH_RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+/// Gets how long to wait after the `attempt`th request of a chunk left keys unprocessed
+fn batch_retry_delay(attempt: u32) -> Duration {
+    BATCH_RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1))
+}
+
+/// Fetches many items from a single table by key
+///
+/// Items are returned in no particular order, and keys with no matching item
+/// are simply absent from the result. Keys DynamoDB reports as unprocessed are
+/// retried until every key has been read or `MAX_BATCH_ATTEMPTS` is reached.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `table_name` - table to read from
+/// * `keys` - primary keys of the items to fetch
+///
+/// # Returns
+///
+/// All items found for the given keys
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if a batch request fails, or if
+/// keys are still unprocessed after `MAX_BATCH_ATTEMPTS` requests
+pub async fn batch_get_items(
+    client: &Client,
+    table_name: &str,
+    keys: Vec<HashMap<String, AttributeValue>>
+) -> Result<Vec<HashMap<String, AttributeValue>>, AppError> {
+    batch_get_items_with(table_name, keys, |pending| {
+        let request = client.batch_get_item().request_items(table_name, pending);
+        async move {
+            request.send().await.map_err(|e| {
+                warn!("Failed to batch get items from {}: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to batch get items from {}", table_name))
+            })
+        }
+    }).await
+}
+
+/// Fetches many items from a single table by key, sending each request with `send`
+///
+/// See `batch_get_items`, which sends the requests with a DynamoDB client.
+///
+/// # Arguments
+///
+/// * `table_name` - table to read from
+/// * `keys` - primary keys of the items to fetch
+/// * `send` - makes one `BatchGetItem` request for the given keys of `table_name`
+///
+/// # Errors
+///
+/// Returns the error of `send`, or Database Error (500) App error variant if keys are
+/// still unprocessed after `MAX_BATCH_ATTEMPTS` requests
+async fn batch_get_items_with<F, Fut>(
+    table_name: &str,
+    keys: Vec<HashMap<String, AttributeValue>>,
+    send: F
+) -> Result<Vec<HashMap<String, AttributeValue>>, AppError>
+    where
+        F: Fn(KeysAndAttributes) -> Fut,
+        Fut: Future<Output = Result<BatchGetItemOutput, AppError>>
+{
+    let mut items = Vec::new();
+
+    for chunk in keys.chunks(BATCH_GET_LIMIT) {
+        let mut pending = KeysAndAttributes::builder()
+            .set_keys(Some(chunk.to_vec()))
+            .build()
+            .map_err(|e| AppError::DatabaseError(format!("Failed to build batch keys: {}", e)))?;
+
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let response = send(pending).await?;
+
+            if let Some(mut responses) = response.responses {
+                items.extend(responses.remove(table_name).unwrap_or_default());
+            }
+
+            // Anything DynamoDB couldn't read this round comes back here
+            let unprocessed = response.unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(table_name))
+                .filter(|keys| !keys.keys().is_empty());
+
+            pending = match unprocessed {
+                Some(keys) => keys,
+                None => {
+                    break;
+                }
+            };
+
+            if attempt >= MAX_BATCH_ATTEMPTS {
+                return Err(
+                    AppError::DatabaseError(
+                        format!(
+                            "{} keys from {} were still unprocessed after {} attempts",
+                            pending.keys().len(),
+                            table_name,
+                            MAX_BATCH_ATTEMPTS
+                        )
+                    )
+                );
+            }
+
+            let delay = batch_retry_delay(attempt);
+            warn!(
+                "{} keys from {} unprocessed, retrying in {:?}",
+                pending.keys().len(),
+                table_name,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn key(id: &str) -> HashMap<String, AttributeValue> {
+        HashMap::from([("id".to_string(), AttributeValue::S(id.to_string()))])
+    }
+
+    /// Answers a request with the given items found, and the given keys left unprocessed
+    fn output(
+        found: Vec<HashMap<String, AttributeValue>>,
+        unprocessed: Vec<HashMap<String, AttributeValue>>
+    ) -> BatchGetItemOutput {
+        let mut output = BatchGetItemOutput::builder().responses("Pantries", found);
+        if !unprocessed.is_empty() {
+            let keys = KeysAndAttributes::builder().set_keys(Some(unprocessed)).build().unwrap();
+            output = output.unprocessed_keys("Pantries", keys);
+        }
+        output.build()
+    }
+
+    #[tokio::test]
+    async fn retries_unprocessed_keys_until_every_key_is_read() {
+        let requests = Mutex::new(Vec::new());
+
+        let items = batch_get_items_with("Pantries", vec![key("a"), key("b")], |pending| {
+            let mut requests = requests.lock().unwrap();
+            requests.push(pending.keys().len());
+            // throttled on the first request, so only `a` is read
+            let response = match requests.len() {
+                1 => output(vec![key("a")], vec![key("b")]),
+                _ => output(pending.keys().to_vec(), Vec::new()),
+            };
+            async move { Ok(response) }
+        }).await.unwrap();
+
+        assert_eq!(items, [key("a"), key("b")]);
+        assert_eq!(*requests.lock().unwrap(), [2, 1]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_keys_that_stay_unprocessed() {
+        let requests = Mutex::new(0);
+
+        let result = batch_get_items_with("Pantries", vec![key("a")], |pending| {
+            *requests.lock().unwrap() += 1;
+            let response = output(Vec::new(), pending.keys().to_vec());
+            async move { Ok(response) }
+        }).await;
+
+        assert!(matches!(result, Err(AppError::DatabaseError(_))));
+        assert_eq!(*requests.lock().unwrap(), MAX_BATCH_ATTEMPTS);
+    }
+
+    #[test]
+    fn retry_delay_doubles_per_attempt() {
+        assert_eq!(batch_retry_delay(1), Duration::from_millis(50));
+        assert_eq!(batch_retry_delay(2), Duration::from_millis(100));
+        assert_eq!(batch_retry_delay(4), Duration::from_millis(400));
+    }
+
+    // a throttled chunk must not hold a resolver for long before giving up
+    #[test]
+    fn retries_wait_under_a_second_in_total() {
+        let total: Duration = (1..MAX_BATCH_ATTEMPTS).map(batch_retry_delay).sum();
+
+        assert_eq!(total, Duration::from_millis(750));
+    }
+}
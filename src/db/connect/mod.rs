@@ -1,19 +1,19 @@
-use aws_config::{ meta::region::RegionProviderChain, BehaviorVersion };
+use aws_config::BehaviorVersion;
 use aws_sdk_dynamodb::Client;
 use dotenvy::dotenv;
-use tracing::{ info, warn };
+use tracing::{ error, info, warn };
 use std::env;
 
-use crate::error::AppError;
+use crate::{ db::region::region_provider, error::AppError };
 
 pub async fn setup_local_client() -> Result<Client, AppError> {
     dotenv().ok();
-    let region_provider = RegionProviderChain::default_provider().or_else("us-east-2");
+    let region_provider = region_provider();
     info!("db region provider value: {:?}", &region_provider);
 
     let config = aws_config
         ::from_env()
-        .behavior_version(BehaviorVersion::v2025_01_17())
+        .behavior_version(BehaviorVersion::latest())
         .region(region_provider)
         .load().await;
 
@@ -23,7 +23,7 @@ pub async fn setup_local_client() -> Result<Client, AppError> {
     let db_url = match env::var("DB_URL") {
         Ok(env) => env,
         Err(e) => {
-            eprintln!("Failed to get DB_URL from env");
+            error!("Failed to get DB_URL from env");
             return Err(AppError::EnvError(e));
         }
     };
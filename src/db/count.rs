@@ -0,0 +1,116 @@
+//! Count-only reads.
+//!
+//! These issue scans and queries with `Select::Count`, so DynamoDB returns just
+//! the number of matching items instead of the items themselves. That saves
+//! response size and (de)serialization, but not read capacity: DynamoDB still
+//! reads and bills every item it evaluates, exactly as a full listing would.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{ types::{ AttributeValue, Select }, Client };
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Counts the items of a table that match a filter, following pagination to the end
+///
+/// The filter is applied after the read, so every item of the table is still billed.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `table_name` - table to count
+/// * `filter_expression` - condition an item must meet to be counted
+/// * `values` - expression attribute values used by the filter
+///
+/// # Returns
+///
+/// Number of matching items in the table
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any scan page fails
+pub async fn count_matching_items(
+    client: &Client,
+    table_name: &str,
+    filter_expression: &str,
+    values: HashMap<String, AttributeValue>
+) -> Result<i64, AppError> {
+    let mut count = 0;
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .scan()
+            .table_name(table_name)
+            .select(Select::Count)
+            .filter_expression(filter_expression)
+            .set_expression_attribute_values(Some(values.clone()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to count {}: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to count {}", table_name))
+            })?;
+
+        count += i64::from(response.count);
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Counts the items sharing a partition key value, following pagination to the end
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `table_name` - table to query
+/// * `partition_key` - name of the table's partition key attribute
+/// * `value` - partition key value to count items for
+///
+/// # Returns
+///
+/// Number of items in the partition
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any query page fails
+pub async fn count_partition_items(
+    client: &Client,
+    table_name: &str,
+    partition_key: &str,
+    value: &str
+) -> Result<i64, AppError> {
+    let mut count = 0;
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name(table_name)
+            .select(Select::Count)
+            .key_condition_expression("#pk = :pk")
+            .expression_attribute_names("#pk", partition_key)
+            .expression_attribute_values(":pk", AttributeValue::S(value.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to count {} items: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to count {} items", table_name))
+            })?;
+
+        count += i64::from(response.count);
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,56 @@
+//! Atomic sequence counters.
+//!
+//! Each counter is an item in the PantrySystem table with `PK` `COUNTER#<name>` and
+//! `SK` `COUNTER`, holding the last value handed out in `value`. Values come from an
+//! `ADD` update, which DynamoDB applies atomically, so concurrent callers never get
+//! the same value. A value taken by a write that then fails is not reused, so a
+//! sequence can have gaps.
+
+use aws_sdk_dynamodb::{ types::{ AttributeValue, ReturnValue }, Client };
+use tracing::warn;
+
+use crate::error::AppError;
+
+use super::update_builder::UpdateBuilder;
+
+/// Counter the short pantry codes are numbered from
+pub const PANTRY_CODE_COUNTER: &str = "pantry_code";
+
+/// Takes the next value of a counter, starting at 1
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `name` - name of the counter, created on first use
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the update fails or returns no value
+pub async fn next_value(client: &Client, name: &str) -> Result<i64, AppError> {
+    let update = UpdateBuilder::new()
+        .add("value", AttributeValue::N("1".to_string()))
+        .build()
+        .ok_or_else(|| AppError::InternalServerError("Empty counter update".to_string()))?;
+
+    let response = client
+        .update_item()
+        .table_name("PantrySystem")
+        .key("PK", AttributeValue::S(format!("COUNTER#{}", name)))
+        .key("SK", AttributeValue::S("COUNTER".to_string()))
+        .update_expression(update.expression)
+        .set_expression_attribute_names(Some(update.names))
+        .set_expression_attribute_values(update.values)
+        .return_values(ReturnValue::UpdatedNew)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to increment counter {}: {:?}", name, e);
+            AppError::DatabaseError(format!("Failed to increment counter {}", name))
+        })?;
+
+    response
+        .attributes()
+        .and_then(|attributes| attributes.get("value"))
+        .and_then(|value| value.as_n().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or_else(|| AppError::DatabaseError(format!("Counter {} returned no value", name)))
+}
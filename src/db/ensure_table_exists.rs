@@ -5,7 +5,6 @@
 //! to support the data access patterns required by the application.
 
 use core::fmt;
-
 use aws_sdk_dynamodb::{
     Client,
     Error,
@@ -13,14 +12,18 @@ use aws_sdk_dynamodb::{
     types::{
         AttributeDefinition,
         BillingMode,
+        CreateGlobalSecondaryIndexAction,
         KeySchemaElement,
         KeyType,
         GlobalSecondaryIndex,
+        GlobalSecondaryIndexUpdate,
+        IndexStatus,
         Projection,
         ProjectionType,
         ScalarAttributeType,
     },
 };
+use tracing::{ debug, info, warn };
 
 use crate::error::AppError;
 
@@ -47,6 +50,125 @@ fn build<T, E>(builder_result: Result<T, E>, context: &str) -> Result<T, AppErro
     builder_result.map_err(|e| AppError::DatabaseError(format!("{}: {:?}", context, e.to_string())))
 }
 
+/// Adds the index an existing table is missing, keeping its data.
+///
+/// Tables created before an index was added to their definition don't get it from
+/// `create_table`, so this compares the table against its definition and creates a
+/// missing index with `update_table`. DynamoDB only allows one index to be created per
+/// `update_table` call, and none while another is still backfilling, so a single index is
+/// added per start and the rest on later starts. The backfill is never waited for, the
+/// index stays `CREATING` meanwhile and `/ready` reports the service degraded, see
+/// `db::health`. Indexes are never removed or modified.
+///
+/// Failing to reach DynamoDB or to start a backfill is only logged, so a migration never
+/// keeps the service from starting.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client for AWS API operations
+/// * `table_name` - Existing table to check
+/// * `attribute_definitions` - Attribute definitions from the table definition
+/// * `indexes` - Global secondary indexes from the table definition
+///
+/// # Returns
+///
+/// * `Result<(), AppError>` - Success or a database error if the index action can't be built
+async fn add_missing_indexes(
+    client: &Client,
+    table_name: &str,
+    attribute_definitions: &[AttributeDefinition],
+    indexes: &[GlobalSecondaryIndex]
+) -> Result<(), AppError> {
+    let description = match client.describe_table().table_name(table_name).send().await {
+        Ok(description) => description,
+        Err(e) => {
+            warn!(table = table_name, "Failed to describe table, skipping index check: {:?}", e);
+            return Ok(());
+        }
+    };
+
+    let existing = description
+        .table()
+        .map(|table| table.global_secondary_indexes())
+        .unwrap_or_default();
+
+    let building = existing
+        .iter()
+        .find(|index| index.index_status() != Some(&IndexStatus::Active));
+
+    if let Some(creating) = building {
+        info!(
+            table = table_name,
+            index = creating.index_name(),
+            status = ?creating.index_status(),
+            "Index is still being built, missing indexes are added on a later start"
+        );
+        return Ok(());
+    }
+
+    let existing = existing
+        .iter()
+        .filter_map(|index| index.index_name())
+        .collect::<Vec<&str>>();
+
+    let mut missing = indexes.iter().filter(|index| !existing.contains(&index.index_name()));
+
+    let index = match missing.next() {
+        Some(index) => index,
+        None => {
+            return Ok(());
+        }
+    };
+    let index_name = index.index_name();
+
+    // update_table only accepts definitions for the attributes the new index uses
+    let key_attributes = index
+        .key_schema()
+        .iter()
+        .map(|key| key.attribute_name())
+        .collect::<Vec<&str>>();
+
+    let definitions = attribute_definitions
+        .iter()
+        .filter(|definition| key_attributes.contains(&definition.attribute_name()))
+        .cloned()
+        .collect::<Vec<AttributeDefinition>>();
+
+    let action = build(
+        CreateGlobalSecondaryIndexAction::builder()
+            .index_name(index_name)
+            .set_key_schema(Some(index.key_schema().to_vec()))
+            .set_projection(index.projection().cloned())
+            .build(),
+        &format!("Failed to build {} create action", index_name)
+    )?;
+
+    let result = client
+        .update_table()
+        .table_name(table_name)
+        .set_attribute_definitions(Some(definitions))
+        .global_secondary_index_updates(
+            GlobalSecondaryIndexUpdate::builder().create(action).build()
+        )
+        .send().await;
+
+    match result {
+        Ok(_) => {
+            info!(
+                table = table_name,
+                index = index_name,
+                remaining = missing.count(),
+                "Adding index to existing table, it backfills in the background"
+            );
+        }
+        Err(e) => {
+            warn!(table = table_name, index = index_name, "Failed to add index: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
 /// Creates the PantrySystem table using a single-table design pattern.
 ///
 /// This table uses composite primary keys (PK, SK) and multiple GSIs to support
@@ -57,12 +179,21 @@ fn build<T, E>(builder_result: Result<T, E>, context: &str) -> Result<T, AppErro
 /// * Partition Key (PK): Entity type prefix + ID (e.g., "PANTRY#123", "USER#456")
 /// * Sort Key (SK): Entity metadata or relationship (e.g., "PROFILE", "PANTRY#123")
 ///
+/// Sequence counters live here too, as `PK` "COUNTER#<name>" with `SK` "COUNTER".
+///
 /// # Global Secondary Indexes
 /// * UserAccessIndex: Find pantries a user can access
 /// * PantryManagementIndex: Find users with specific access levels for a pantry
 /// * SelfManagedPantryIndex: Find all self-managed pantries
 /// * EmailLookupIndex: Look up users by email address
 ///
+/// # Index Projections
+///
+/// Every attribute projected into an index is stored and written again for each
+/// item, so indexes only project what their reads need:
+/// * EmailLookupIndex: KEYS_ONLY, a lookup resolves the key then reads the base table
+/// * Other indexes: ALL, they serve listings that return whole items
+///
 /// # Arguments
 ///
 /// * `tables` - List of existing tables to check if this one already exists
@@ -74,12 +205,6 @@ fn build<T, E>(builder_result: Result<T, E>, context: &str) -> Result<T, AppErro
 pub async fn pantry_system(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
     let table_name = "PantrySystem";
 
-    // Check if table already exists
-    if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
-        return Ok(());
-    }
-
     // Define attribute definitions
     let ad_pk = build(
         AttributeDefinition::builder()
@@ -216,11 +341,29 @@ pub async fn pantry_system(tables: &ListTablesOutput, client: &Client) -> Result
         GlobalSecondaryIndex::builder()
             .index_name("EmailLookupIndex")
             .key_schema(gsi4_pk)
-            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .projection(Projection::builder().projection_type(ProjectionType::KeysOnly).build())
             .build(),
         "Failed to build GSI4"
     )?;
 
+    // Existing tables keep their data and only get the indexes they are missing
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return add_missing_indexes(
+            client,
+            table_name,
+            &[
+                ad_pk.clone(),
+                ad_sk.clone(),
+                ad_user_id.clone(),
+                ad_access_level.clone(),
+                ad_is_self_managed.clone(),
+                ad_email.clone(),
+            ],
+            &[gsi1.clone(), gsi2.clone(), gsi3.clone(), gsi4.clone()]
+        ).await;
+    }
+
     // Create the table with proper error handling
     let response = client
         .create_table()
@@ -245,7 +388,11 @@ pub async fn pantry_system(tables: &ListTablesOutput, client: &Client) -> Result
             )
         )?;
 
-    println!("PantrySystem table created: {:?}", response);
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
     Ok(())
 }
 
@@ -260,6 +407,32 @@ pub async fn pantry_system(tables: &ListTablesOutput, client: &Client) -> Result
 /// # Global Secondary Indexes
 /// * EmailIndex: Find users by email address (for authentication)
 /// * RoleIndex: Find users by role (for administrative functions)
+/// * CreatedAtIndex: Find users created within a date range (for reporting)
+///
+/// EmailIndex does not enforce uniqueness. Each email is claimed by an item with id
+/// `EMAIL#<email>` that is written with its user in one transaction, see
+/// `db::users::create_user`. Those items carry no indexed attributes.
+///
+/// # Index Projections
+///
+/// Every attribute projected into an index is stored and written again for each
+/// user, so indexes only project what their reads need:
+/// * EmailIndex: INCLUDE `id`, a lookup resolves the id then reads the full user
+///   from the base table (see `db::users::find_user_by_email`); avoids a second copy
+///   of every user, password hash included, for a single-item read
+/// * RoleIndex: KEYS_ONLY, used to find which users hold a role, not to list them
+/// * CreatedAtIndex: ALL, it serves paginated reporting lists of whole users, where a
+///   base table read per item would cost more than the duplicated storage
+///
+/// Projections are fixed when an index is created; changing one here does not alter an
+/// index that already exists, it has to be deleted and re-added.
+///
+/// CreatedAtIndex is keyed on `entity_type`, which is the same value ("USER") on
+/// every user, with `created_at` as the sort key. That makes date range reads a
+/// single query instead of a scan, at the cost of putting the whole index in one
+/// partition: every user write also writes to that partition, which caps the
+/// index at roughly 1000 writes/sec. That is far above this table's write rate;
+/// if it ever isn't, shard the key (e.g. "USER#0".."USER#9") and query each shard.
 ///
 /// # Arguments
 ///
@@ -272,12 +445,6 @@ pub async fn pantry_system(tables: &ListTablesOutput, client: &Client) -> Result
 pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
     let table_name = "Users";
 
-    // Check if table already exists
-    if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
-        return Ok(());
-    }
-
     // Define attribute definitions
     let ad_user_id = build(
         AttributeDefinition::builder()
@@ -303,6 +470,22 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
         "Failed to build role attribute definition"
     )?;
 
+    let ad_entity_type = build(
+        AttributeDefinition::builder()
+            .attribute_name("entity_type")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build entity_type attribute definition"
+    )?;
+
+    let ad_created_at = build(
+        AttributeDefinition::builder()
+            .attribute_name("created_at")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build created_at attribute definition"
+    )?;
+
     // Define key schema for table
     let ks_user_id = build(
         KeySchemaElement::builder().attribute_name("user_id").key_type(KeyType::Hash).build(),
@@ -319,7 +502,10 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
         GlobalSecondaryIndex::builder()
             .index_name("EmailIndex")
             .key_schema(gsi1_pk)
-            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .projection(Projection::builder()
+                    .projection_type(ProjectionType::Include)
+                    .non_key_attributes("id")
+                    .build())
             .build(),
         "Failed to build EmailIndex GSI"
     )?;
@@ -334,11 +520,49 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
         GlobalSecondaryIndex::builder()
             .index_name("RoleIndex")
             .key_schema(gsi2_pk)
-            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .projection(Projection::builder().projection_type(ProjectionType::KeysOnly).build())
             .build(),
         "Failed to build RoleIndex GSI"
     )?;
 
+    // Define GSI 3: Created At Index
+    let gsi3_pk = build(
+        KeySchemaElement::builder().attribute_name("entity_type").key_type(KeyType::Hash).build(),
+        "Failed to build CreatedAt GSI PK"
+    )?;
+
+    let gsi3_sk = build(
+        KeySchemaElement::builder().attribute_name("created_at").key_type(KeyType::Range).build(),
+        "Failed to build CreatedAt GSI SK"
+    )?;
+
+    let gsi3 = build(
+        GlobalSecondaryIndex::builder()
+            .index_name("CreatedAtIndex")
+            .key_schema(gsi3_pk)
+            .key_schema(gsi3_sk)
+            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .build(),
+        "Failed to build CreatedAtIndex GSI"
+    )?;
+
+    // Existing tables keep their data and only get the indexes they are missing
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return add_missing_indexes(
+            client,
+            table_name,
+            &[
+                ad_user_id.clone(),
+                ad_email.clone(),
+                ad_role.clone(),
+                ad_entity_type.clone(),
+                ad_created_at.clone(),
+            ],
+            &[gsi1.clone(), gsi2.clone(), gsi3.clone()]
+        ).await;
+    }
+
     // Create the table with proper error handling
     let response = client
         .create_table()
@@ -347,9 +571,12 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
         .attribute_definitions(ad_user_id)
         .attribute_definitions(ad_email)
         .attribute_definitions(ad_role)
+        .attribute_definitions(ad_entity_type)
+        .attribute_definitions(ad_created_at)
         .key_schema(ks_user_id)
         .global_secondary_indexes(gsi1)
         .global_secondary_indexes(gsi2)
+        .global_secondary_indexes(gsi3)
         .send().await
         .map_err(|e|
             AppError::DatabaseError(
@@ -357,7 +584,11 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
             )
         )?;
 
-    println!("Users table created: {:?}", response);
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
     Ok(())
 }
 
@@ -371,6 +602,28 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
 ///
 /// # Global Secondary Indexes
 /// * SelfManagedIndex: Identifies self-managed vs. centrally managed pantries
+/// * CodeIndex: Find a pantry by its short code, e.g. `PAN-00042` (for phone support)
+/// * NameIndex: Pantries whose lowercased name starts with a prefix, for autocomplete
+///
+/// SelfManagedIndex projects ALL, it serves listings of whole pantries split by
+/// management, and a base table read per pantry would cost more than the copy.
+/// CodeIndex projects INCLUDE `id`, a lookup resolves the id then reads the pantry.
+/// NameIndex projects INCLUDE `id` and `name`, all a suggestion shows.
+///
+/// NameIndex is keyed on `name_bucket`, the first character of the lowercased name,
+/// with `name_lower` as the sort key. Every name starting with a prefix shares the
+/// prefix's bucket, so a prefix match is one `begins_with` query, in name order.
+/// A single constant partition key would do the same, but would put every pantry
+/// write and every keystroke of every search on one partition; bucketing by letter
+/// spreads both over a few dozen. Buckets are uneven (far more "s" than "x" names),
+/// which is fine at this table's size. Pantries written before the index existed
+/// have neither attribute until `runBackfill(PANTRY_NAME_INDEX)` writes them, see
+/// `db::integrity::Backfill`. Renames move both attributes with the name.
+///
+/// Pantry names are claimed per zipcode by items with id `NAME#<zipcode>#<name>`,
+/// written with the pantry in one transaction and moved on renames, see
+/// `db::pantries::create_pantry`.
+/// Those items carry no indexed attributes.
 ///
 /// # Arguments
 ///
@@ -383,12 +636,6 @@ pub async fn users(tables: &ListTablesOutput, client: &Client) -> Result<(), App
 pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
     let table_name = "Pantries";
 
-    // Check if table already exists
-    if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
-        return Ok(());
-    }
-
     // Define attribute definitions
     let ad_pantry_id = build(
         AttributeDefinition::builder()
@@ -406,6 +653,30 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
         "Failed to build is_self_managed attribute definition"
     )?;
 
+    let ad_code = build(
+        AttributeDefinition::builder()
+            .attribute_name("code")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build code attribute definition"
+    )?;
+
+    let ad_name_bucket = build(
+        AttributeDefinition::builder()
+            .attribute_name("name_bucket")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build name_bucket attribute definition"
+    )?;
+
+    let ad_name_lower = build(
+        AttributeDefinition::builder()
+            .attribute_name("name_lower")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build name_lower attribute definition"
+    )?;
+
     // Define key schema for table
     let ks_pantry_id = build(
         KeySchemaElement::builder().attribute_name("pantry_id").key_type(KeyType::Hash).build(),
@@ -430,6 +701,66 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
         "Failed to build SelfManagedIndex GSI"
     )?;
 
+    // Define GSI 2: Code Index
+    let gsi2_pk = build(
+        KeySchemaElement::builder().attribute_name("code").key_type(KeyType::Hash).build(),
+        "Failed to build Code GSI PK"
+    )?;
+
+    let gsi2 = build(
+        GlobalSecondaryIndex::builder()
+            .index_name("CodeIndex")
+            .key_schema(gsi2_pk)
+            .projection(Projection::builder()
+                    .projection_type(ProjectionType::Include)
+                    .non_key_attributes("id")
+                    .build())
+            .build(),
+        "Failed to build CodeIndex GSI"
+    )?;
+
+    // Define GSI 3: Name Index
+    let gsi3_pk = build(
+        KeySchemaElement::builder().attribute_name("name_bucket").key_type(KeyType::Hash).build(),
+        "Failed to build Name GSI PK"
+    )?;
+
+    let gsi3_sk = build(
+        KeySchemaElement::builder().attribute_name("name_lower").key_type(KeyType::Range).build(),
+        "Failed to build Name GSI SK"
+    )?;
+
+    let gsi3 = build(
+        GlobalSecondaryIndex::builder()
+            .index_name("NameIndex")
+            .key_schema(gsi3_pk)
+            .key_schema(gsi3_sk)
+            .projection(Projection::builder()
+                    .projection_type(ProjectionType::Include)
+                    .non_key_attributes("id")
+                    .non_key_attributes("name")
+                    .build())
+            .build(),
+        "Failed to build NameIndex GSI"
+    )?;
+
+    // Existing tables keep their data and only get the indexes they are missing
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return add_missing_indexes(
+            client,
+            table_name,
+            &[
+                ad_pantry_id.clone(),
+                ad_is_self_managed.clone(),
+                ad_code.clone(),
+                ad_name_bucket.clone(),
+                ad_name_lower.clone(),
+            ],
+            &[gsi1.clone(), gsi2.clone(), gsi3.clone()]
+        ).await;
+    }
+
     // Create the table with proper error handling
     let response = client
         .create_table()
@@ -437,8 +768,13 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
         .billing_mode(BillingMode::PayPerRequest)
         .attribute_definitions(ad_pantry_id)
         .attribute_definitions(ad_is_self_managed)
+        .attribute_definitions(ad_code)
+        .attribute_definitions(ad_name_bucket)
+        .attribute_definitions(ad_name_lower)
         .key_schema(ks_pantry_id)
         .global_secondary_indexes(gsi1)
+        .global_secondary_indexes(gsi2)
+        .global_secondary_indexes(gsi3)
         .send().await
         .map_err(|e|
             AppError::DatabaseError(
@@ -446,7 +782,11 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
             )
         )?;
 
-    println!("Pantries table created: {:?}", response);
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
     Ok(())
 }
 
@@ -466,6 +806,9 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
 /// * AccessLevelIndex: Find users with specific access levels for a pantry
 /// * ContactAgentIndex: Find contact agents for a pantry
 ///
+/// Indexes project ALL: access rows are a handful of short attributes, so a full
+/// copy costs little more than KEYS_ONLY and saves a base table read per row.
+///
 /// # Arguments
 ///
 /// * `tables` - List of existing tables to check if this one already exists
@@ -477,12 +820,6 @@ pub async fn pantries(tables: &ListTablesOutput, client: &Client) -> Result<(),
 pub async fn pantry_access(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
     let table_name = "PantryAccess";
 
-    // Check if table already exists
-    if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
-        return Ok(());
-    }
-
     // Define attribute definitions
     let ad_pantry_id = build(
         AttributeDefinition::builder()
@@ -593,6 +930,22 @@ pub async fn pantry_access(tables: &ListTablesOutput, client: &Client) -> Result
         "Failed to build ContactAgentIndex GSI"
     )?;
 
+    // Existing tables keep their data and only get the indexes they are missing
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return add_missing_indexes(
+            client,
+            table_name,
+            &[
+                ad_pantry_id.clone(),
+                ad_user_id.clone(),
+                ad_access_level.clone(),
+                ad_is_contact_agent.clone(),
+            ],
+            &[gsi1.clone(), gsi2.clone(), gsi3.clone()]
+        ).await;
+    }
+
     // Create the table with proper error handling
     let response = client
         .create_table()
@@ -614,6 +967,236 @@ pub async fn pantry_access(tables: &ListTablesOutput, client: &Client) -> Result
             )
         )?;
 
-    println!("PantryAccess table created: {:?}", response);
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
+    Ok(())
+}
+
+/// Creates an ApiKeys table for service-to-service authentication.
+///
+/// Keys are never stored in plain text. Each item is keyed by the SHA-256 hash
+/// of the API key and carries the role granted to callers presenting that key.
+///
+/// # Primary Key Structure
+/// * Partition Key: key_hash (hex encoded SHA-256 of the key)
+///
+/// # Arguments
+///
+/// * `tables` - List of existing tables to check if this one already exists
+/// * `client` - DynamoDB client for AWS API operations
+///
+/// # Returns
+///
+/// * `Result<(), AppError>` - Success or a database error with context
+pub async fn api_keys(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
+    let table_name = "ApiKeys";
+
+    // Check if table already exists
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return Ok(());
+    }
+
+    // Define attribute definitions
+    let ad_key_hash = build(
+        AttributeDefinition::builder()
+            .attribute_name("key_hash")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build key_hash attribute definition"
+    )?;
+
+    // Define key schema for table
+    let ks_key_hash = build(
+        KeySchemaElement::builder().attribute_name("key_hash").key_type(KeyType::Hash).build(),
+        "Failed to build key_hash key schema"
+    )?;
+
+    // Create the table with proper error handling
+    let response = client
+        .create_table()
+        .table_name("ApiKeys")
+        .billing_mode(BillingMode::PayPerRequest)
+        .attribute_definitions(ad_key_hash)
+        .key_schema(ks_key_hash)
+        .send().await
+        .map_err(|e|
+            AppError::DatabaseError(
+                format!("Failed to create {} table: {:?}", table_name, e.to_string())
+            )
+        )?;
+
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
+    Ok(())
+}
+
+/// Creates an Outbox table for the log of changes and the events waiting to be delivered
+/// to the webhook.
+///
+/// Events are written in the same transaction as the change they describe and removed
+/// from the PendingIndex once delivered, see `models::outbox`.
+///
+/// # Primary Key Structure
+/// * Partition Key: id (UUID of the event)
+///
+/// # Global Secondary Indexes
+/// * PendingIndex: Find events due for delivery, oldest due first. Sparse, only pending
+///   events carry the `pending` attribute.
+/// * CreatedAtIndex: Read the changelog in order. Every event shares the `entity_type`
+///   partition, which caps its write rate near 1000 events a second, far above what
+///   pantry edits produce.
+///
+/// # Arguments
+///
+/// * `tables` - List of existing tables to check if this one already exists
+/// * `client` - DynamoDB client for AWS API operations
+///
+/// # Returns
+///
+/// * `Result<(), AppError>` - Success or a database error with context
+pub async fn outbox(tables: &ListTablesOutput, client: &Client) -> Result<(), AppError> {
+    let table_name = "Outbox";
+
+    // Define attribute definitions
+    let ad_id = build(
+        AttributeDefinition::builder()
+            .attribute_name("id")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build id attribute definition"
+    )?;
+
+    let ad_pending = build(
+        AttributeDefinition::builder()
+            .attribute_name("pending")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build pending attribute definition"
+    )?;
+
+    let ad_next_attempt_at = build(
+        AttributeDefinition::builder()
+            .attribute_name("next_attempt_at")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build next_attempt_at attribute definition"
+    )?;
+
+    let ad_entity_type = build(
+        AttributeDefinition::builder()
+            .attribute_name("entity_type")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build entity_type attribute definition"
+    )?;
+
+    let ad_created_at = build(
+        AttributeDefinition::builder()
+            .attribute_name("created_at")
+            .attribute_type(ScalarAttributeType::S)
+            .build(),
+        "Failed to build created_at attribute definition"
+    )?;
+
+    // Define key schema for table
+    let ks_id = build(
+        KeySchemaElement::builder().attribute_name("id").key_type(KeyType::Hash).build(),
+        "Failed to build id key schema"
+    )?;
+
+    // Define GSI 1: Pending Index
+    let gsi1_pk = build(
+        KeySchemaElement::builder().attribute_name("pending").key_type(KeyType::Hash).build(),
+        "Failed to build Pending GSI PK"
+    )?;
+
+    let gsi1_sk = build(
+        KeySchemaElement::builder()
+            .attribute_name("next_attempt_at")
+            .key_type(KeyType::Range)
+            .build(),
+        "Failed to build Pending GSI SK"
+    )?;
+
+    let gsi1 = build(
+        GlobalSecondaryIndex::builder()
+            .index_name("PendingIndex")
+            .key_schema(gsi1_pk)
+            .key_schema(gsi1_sk)
+            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .build(),
+        "Failed to build PendingIndex GSI"
+    )?;
+
+    // Define GSI 2: Created At Index
+    let gsi2_pk = build(
+        KeySchemaElement::builder().attribute_name("entity_type").key_type(KeyType::Hash).build(),
+        "Failed to build Created At GSI PK"
+    )?;
+
+    let gsi2_sk = build(
+        KeySchemaElement::builder().attribute_name("created_at").key_type(KeyType::Range).build(),
+        "Failed to build Created At GSI SK"
+    )?;
+
+    let gsi2 = build(
+        GlobalSecondaryIndex::builder()
+            .index_name("CreatedAtIndex")
+            .key_schema(gsi2_pk)
+            .key_schema(gsi2_sk)
+            .projection(Projection::builder().projection_type(ProjectionType::All).build())
+            .build(),
+        "Failed to build CreatedAtIndex GSI"
+    )?;
+
+    // Existing tables keep their data and only get the indexes they are missing
+    if tables.table_names().contains(&table_name.to_string()) {
+        debug!(table = table_name, "Table already exists");
+        return add_missing_indexes(
+            client,
+            table_name,
+            &[
+                ad_id.clone(),
+                ad_pending.clone(),
+                ad_next_attempt_at.clone(),
+                ad_entity_type.clone(),
+                ad_created_at.clone(),
+            ],
+            &[gsi1.clone(), gsi2.clone()]
+        ).await;
+    }
+
+    // Create the table with proper error handling
+    let response = client
+        .create_table()
+        .table_name(table_name)
+        .billing_mode(BillingMode::PayPerRequest)
+        .attribute_definitions(ad_id)
+        .attribute_definitions(ad_pending)
+        .attribute_definitions(ad_next_attempt_at)
+        .attribute_definitions(ad_entity_type)
+        .attribute_definitions(ad_created_at)
+        .key_schema(ks_id)
+        .global_secondary_indexes(gsi1)
+        .global_secondary_indexes(gsi2)
+        .send().await
+        .map_err(|e|
+            AppError::DatabaseError(
+                format!("Failed to create {} table: {:?}", table_name, e.to_string())
+            )
+        )?;
+
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
     Ok(())
 }
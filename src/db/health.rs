@@ -0,0 +1,300 @@
+//! Status report of the tables and indexes the application expects.
+//!
+//! A deploy that adds an index leaves it `CREATING` while it backfills, and a failed
+//! one can leave it missing. This reads each expected table with `describe_table` so
+//! that state is visible without the AWS console.
+//!
+//! It also measures the round trip of a trivial call, for telling DynamoDB latency apart
+//! from app latency when requests are slow.
+//!
+//! The same report backs the `/ready` endpoint of both entrypoints, see `readiness`.
+
+use std::time::Instant;
+
+use async_graphql::SimpleObject;
+use aws_sdk_dynamodb::Client;
+use tracing::warn;
+
+use crate::error::AppError;
+
+use super::init::EXPECTED_TABLES;
+
+/// Status reported for a table or index that doesn't exist
+const MISSING: &str = "MISSING";
+
+/// Status of a table or index when it can be used
+const ACTIVE: &str = "ACTIVE";
+
+/// Status of one global secondary index
+///
+/// # Fields
+///
+/// * `name` - name of the index
+/// * `status` - DynamoDB index status, e.g. `ACTIVE` or `CREATING`, or `MISSING`
+#[derive(Debug, SimpleObject)]
+pub struct IndexHealth {
+    pub name: String,
+    pub status: String,
+}
+
+/// Status of one table and its expected indexes
+///
+/// # Fields
+///
+/// * `name` - name of the table
+/// * `status` - DynamoDB table status, e.g. `ACTIVE` or `UPDATING`, or `MISSING`
+/// * `indexes` - every index the table is expected to have
+/// * `healthy` - whether the table and all of its indexes are `ACTIVE`
+#[derive(Debug, SimpleObject)]
+pub struct TableHealth {
+    pub name: String,
+    pub status: String,
+    pub indexes: Vec<IndexHealth>,
+    pub healthy: bool,
+}
+
+/// Describes one table and reports its status and the status of its expected indexes
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if describing the table fails for a
+/// reason other than the table not existing
+async fn table_health(
+    client: &Client,
+    table_name: &str,
+    index_names: &[&str]
+) -> Result<TableHealth, AppError> {
+    let table = match client.describe_table().table_name(table_name).send().await {
+        Ok(output) => output.table,
+        Err(e) if
+            e
+                .as_service_error()
+                .is_some_and(|e| e.is_resource_not_found_exception())
+        => None,
+        Err(e) => {
+            warn!("Failed to describe table {}: {:?}", table_name, e);
+            return Err(AppError::DatabaseError(format!("Failed to describe {}", table_name)));
+        }
+    };
+
+    let status = table
+        .as_ref()
+        .and_then(|table| table.table_status())
+        .map(|status| status.as_str().to_string())
+        .unwrap_or_else(|| MISSING.to_string());
+
+    let indexes = index_names
+        .iter()
+        .map(|index_name| {
+            let status = table
+                .as_ref()
+                .map(|table| table.global_secondary_indexes())
+                .unwrap_or_default()
+                .iter()
+                .find(|index| index.index_name() == Some(*index_name))
+                .and_then(|index| index.index_status())
+                .map(|status| status.as_str().to_string())
+                .unwrap_or_else(|| MISSING.to_string());
+
+            IndexHealth { name: index_name.to_string(), status }
+        })
+        .collect::<Vec<IndexHealth>>();
+
+    let healthy = status == ACTIVE && indexes.iter().all(|index| index.status == ACTIVE);
+
+    Ok(TableHealth {
+        name: table_name.to_string(),
+        status,
+        indexes,
+        healthy,
+    })
+}
+
+/// Reports the status of every table in `EXPECTED_TABLES`, in that order
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if describing any table fails
+pub async fn schema_health(client: &Client) -> Result<Vec<TableHealth>, AppError> {
+    let mut tables = Vec::with_capacity(EXPECTED_TABLES.len());
+
+    for (table_name, index_names) in EXPECTED_TABLES {
+        tables.push(table_health(client, table_name, index_names).await?);
+    }
+
+    Ok(tables)
+}
+
+/// Measures the round trip of a trivial DynamoDB call
+///
+/// Times a `list_tables` of a single name, which reads no items. Compared with the time the
+/// client sees for a request, it tells DynamoDB latency apart from time spent in the app.
+///
+/// # Returns
+///
+/// Round trip in milliseconds
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the call fails
+pub async fn db_latency_ms(client: &Client) -> Result<i64, AppError> {
+    let started = Instant::now();
+
+    client
+        .list_tables()
+        .limit(1)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to list tables for latency probe: {:?}", e);
+            AppError::DatabaseError("Failed to reach the db".to_string())
+        })?;
+
+    Ok(started.elapsed().as_millis() as i64)
+}
+
+/// Overall state reported by the readiness check
+///
+/// # Variants
+///
+/// * `Ok` - every table and index is `ACTIVE`
+/// * `Degraded` - every table can serve requests, but an index is still backfilling or
+///   missing, so the reads that use it fail
+/// * `Down` - DynamoDB can't be reached, or a table is missing or not yet created
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadinessStatus {
+    Ok,
+    Degraded,
+    Down,
+}
+
+impl ReadinessStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadinessStatus::Ok => "ok",
+            ReadinessStatus::Degraded => "degraded",
+            ReadinessStatus::Down => "down",
+        }
+    }
+}
+
+/// Result of the readiness check
+///
+/// # Fields
+///
+/// * `status` - overall state
+/// * `problems` - what makes the state degraded or down, e.g. `Pantries.NameIndex is CREATING`
+#[derive(Debug)]
+pub struct Readiness {
+    pub status: ReadinessStatus,
+    pub problems: Vec<String>,
+}
+
+impl Readiness {
+    /// Renders the readiness as the JSON body of the readiness endpoint
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": self.status.as_str(),
+            "problems": self.problems,
+        })
+    }
+}
+
+/// Whether a table can serve reads and writes, an `UPDATING` table still can
+fn table_usable(status: &str) -> bool {
+    status == ACTIVE || status == "UPDATING"
+}
+
+/// Checks whether the application can serve requests, for load balancer readiness probes
+///
+/// Describes every expected table, see `schema_health`. A failure to reach DynamoDB is
+/// reported as `Down` rather than returned, the probe always gets an answer.
+pub async fn readiness(client: &Client) -> Readiness {
+    match schema_health(client).await {
+        Ok(tables) => classify(&tables),
+        Err(e) => Readiness { status: ReadinessStatus::Down, problems: vec![e.to_string()] },
+    }
+}
+
+/// Classifies the health of every expected table, see `ReadinessStatus`
+fn classify(tables: &[TableHealth]) -> Readiness {
+    let mut status = ReadinessStatus::Ok;
+    let mut problems = Vec::new();
+
+    for table in tables {
+        if !table_usable(&table.status) {
+            status = ReadinessStatus::Down;
+            problems.push(format!("{} is {}", table.name, table.status));
+            continue;
+        }
+
+        for index in table.indexes.iter().filter(|index| index.status != ACTIVE) {
+            if status == ReadinessStatus::Ok {
+                status = ReadinessStatus::Degraded;
+            }
+            problems.push(format!("{}.{} is {}", table.name, index.name, index.status));
+        }
+    }
+
+    Readiness { status, problems }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &str, status: &str, indexes: &[(&str, &str)]) -> TableHealth {
+        let indexes = indexes
+            .iter()
+            .map(|(name, status)| {
+                IndexHealth { name: name.to_string(), status: status.to_string() }
+            })
+            .collect::<Vec<IndexHealth>>();
+        let healthy = status == ACTIVE && indexes.iter().all(|index| index.status == ACTIVE);
+
+        TableHealth { name: name.to_string(), status: status.to_string(), indexes, healthy }
+    }
+
+    #[test]
+    fn active_and_updating_tables_are_ok() {
+        let readiness = classify(
+            &[table("Users", ACTIVE, &[("EmailIndex", ACTIVE)]), table("Pantries", "UPDATING", &[])]
+        );
+
+        assert_eq!(readiness.status, ReadinessStatus::Ok);
+        assert!(readiness.problems.is_empty());
+    }
+
+    #[test]
+    fn an_index_that_is_not_active_degrades() {
+        let readiness = classify(
+            &[
+                table("Users", ACTIVE, &[("EmailIndex", ACTIVE)]),
+                table("Pantries", ACTIVE, &[("NameIndex", "CREATING"), ("CodeIndex", MISSING)]),
+            ]
+        );
+
+        assert_eq!(readiness.status, ReadinessStatus::Degraded);
+        assert_eq!(
+            readiness.problems,
+            ["Pantries.NameIndex is CREATING", "Pantries.CodeIndex is MISSING"]
+        );
+    }
+
+    #[test]
+    fn a_missing_table_is_down_even_with_degraded_indexes() {
+        let readiness = classify(
+            &[
+                table("Users", ACTIVE, &[("EmailIndex", "CREATING")]),
+                table("Pantries", MISSING, &[]),
+                table("ApiKeys", "CREATING", &[]),
+            ]
+        );
+
+        assert_eq!(readiness.status, ReadinessStatus::Down);
+        assert_eq!(
+            readiness.problems,
+            ["Users.EmailIndex is CREATING", "Pantries is MISSING", "ApiKeys is CREATING"]
+        );
+        assert_eq!(readiness.to_json()["status"], "down");
+    }
+}
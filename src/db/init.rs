@@ -11,6 +11,26 @@ use crate::error::AppError;
 
 use super::ensure_table_exists;
 
+/// Tables created by `ensure_tables_exist`, each with the names of its global secondary indexes
+///
+/// Keep in sync with the definitions in `ensure_table_exists`, `db::health` checks against it.
+pub const EXPECTED_TABLES: &[(&str, &[&str])] = &[
+    (
+        "PantrySystem",
+        &[
+            "UserAccessIndex",
+            "PantryManagementIndex",
+            "SelfManagedPantryIndex",
+            "EmailLookupIndex",
+        ],
+    ),
+    ("Users", &["EmailIndex", "RoleIndex", "CreatedAtIndex"]),
+    ("Pantries", &["SelfManagedIndex", "CodeIndex", "NameIndex"]),
+    ("PantryAccess", &["UserAccessIndex", "AccessLevelIndex", "ContactAgentIndex"]),
+    ("ApiKeys", &[]),
+    ("Outbox", &["PendingIndex", "CreatedAtIndex"]),
+];
+
 /// Ensures that all required tables for the application exist in DynamoDB.
 ///
 /// This function checks if each required table exists, and creates
@@ -47,6 +67,8 @@ pub async fn ensure_tables_exist(client: &Client) -> Result<(), AppError> {
     ensure_table_exists::users(&tables, client).await?;
     ensure_table_exists::pantries(&tables, client).await?;
     ensure_table_exists::pantry_access(&tables, client).await?;
+    ensure_table_exists::api_keys(&tables, client).await?;
+    ensure_table_exists::outbox(&tables, client).await?;
 
     // Additional tables can be added here in the future
 
@@ -0,0 +1,476 @@
+//! Report of stored items the models can't read, and backfills of items saved before a
+//! change to how they are stored.
+//!
+//! Listing resolvers parse items with `filter_map(Model::from_item)`, so an item that
+//! doesn't parse, e.g. a legacy row missing a required attribute, just disappears from
+//! results. This scans a table with the same parser and reports the items it rejects.
+//!
+//! Backfills bring items saved before a change up to date, e.g. give users saved before
+//! email claims existed their claim. Each is safe to run again, items already up to date
+//! are left alone.
+
+use std::collections::HashMap;
+
+use async_graphql::{ Enum, SimpleObject };
+use aws_sdk_dynamodb::{
+    operation::put_item::PutItemError,
+    types::{ AttributeValue, ReturnValuesOnConditionCheckFailure },
+    Client,
+};
+use tracing::{ info, warn };
+
+use crate::{
+    error::AppError,
+    db::{ update_builder::UpdateBuilder, users::find_user_by_email },
+    models::{
+        normalize::normalize_email,
+        pantry::{ name_search_bucket, name_search_key, Pantry },
+        pantry_access::PantryAccess,
+        user::{ email_owner_id, User, EMAIL_OWNER_PREFIX },
+    },
+};
+
+use super::pantries::NAME_GUARD_PREFIX;
+
+/// Most offending keys listed in a report
+const MAX_SAMPLE_KEYS: usize = 50;
+
+/// Tables whose items map onto a model
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum IntegrityTable {
+    Users,
+    Pantries,
+    PantryAccess,
+}
+
+impl IntegrityTable {
+    pub fn table_name(self) -> &'static str {
+        match self {
+            IntegrityTable::Users => "Users",
+            IntegrityTable::Pantries => "Pantries",
+            IntegrityTable::PantryAccess => "PantryAccess",
+        }
+    }
+
+    /// Key attributes of the table, used to identify an offending item
+    fn key_attributes(self) -> &'static [&'static str] {
+        match self {
+            IntegrityTable::Users | IntegrityTable::Pantries => &["id"],
+            IntegrityTable::PantryAccess => &["pantry_id", "user_id"],
+        }
+    }
+
+    /// Whether an item is a guard item rather than a model, e.g. an email claim
+    fn is_guard_item(self, item: &HashMap<String, AttributeValue>) -> bool {
+        let id = item.get("id").and_then(|v| v.as_s().ok());
+        match self {
+            IntegrityTable::Users => id.is_some_and(|id| id.starts_with(EMAIL_OWNER_PREFIX)),
+            IntegrityTable::Pantries => id.is_some_and(|id| id.starts_with(NAME_GUARD_PREFIX)),
+            IntegrityTable::PantryAccess => false,
+        }
+    }
+
+    /// Whether the table's model can read an item
+    fn parses(self, item: &HashMap<String, AttributeValue>) -> bool {
+        match self {
+            IntegrityTable::Users => User::from_item(item).is_some(),
+            IntegrityTable::Pantries => Pantry::from_item(item).is_some(),
+            IntegrityTable::PantryAccess => PantryAccess::from_item(item).is_some(),
+        }
+    }
+}
+
+/// Result of checking every item of a table
+///
+/// # Fields
+///
+/// * `table` - the table checked
+/// * `scanned` - model items read, guard items excluded
+/// * `failed` - items the model couldn't read
+/// * `sample_keys` - keys of up to `MAX_SAMPLE_KEYS` failed items, e.g. `id=...`
+#[derive(Debug, SimpleObject)]
+pub struct IntegrityReport {
+    pub table: IntegrityTable,
+    pub scanned: i64,
+    pub failed: i64,
+    pub sample_keys: Vec<String>,
+}
+
+/// Renders the key of an item as `name=value` pairs
+fn describe_key(item: &HashMap<String, AttributeValue>, key_attributes: &[&str]) -> String {
+    key_attributes
+        .iter()
+        .map(|name| {
+            let value = item
+                .get(*name)
+                .and_then(|v| v.as_s().ok())
+                .map(String::as_str)
+                .unwrap_or("<missing>");
+            format!("{}={}", name, value)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Scans a table and reports the items its model fails to read
+///
+/// Reads the whole table one page at a time, so it is billed as a full table read.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `table` - table to check
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any scan page fails
+pub async fn scan_data_integrity(
+    client: &Client,
+    table: IntegrityTable
+) -> Result<IntegrityReport, AppError> {
+    let table_name = table.table_name();
+    let mut report = IntegrityReport { table, scanned: 0, failed: 0, sample_keys: Vec::new() };
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .scan()
+            .table_name(table_name)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan {} for integrity check: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to scan {}", table_name))
+            })?;
+
+        for item in response.items().iter().filter(|item| !table.is_guard_item(item)) {
+            report.scanned += 1;
+            if table.parses(item) {
+                continue;
+            }
+
+            report.failed += 1;
+            if report.sample_keys.len() < MAX_SAMPLE_KEYS {
+                report.sample_keys.push(describe_key(item, table.key_attributes()));
+            }
+        }
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    if report.failed > 0 {
+        warn!(
+            table = table_name,
+            failed = report.failed,
+            scanned = report.scanned,
+            "Items failed to parse"
+        );
+    }
+
+    Ok(report)
+}
+
+/// Backfills of items saved before a change to how they are stored
+///
+/// # Variants
+///
+/// * `EmailClaims` - writes the email claim of users registered before claims existed
+/// * `NormalizedEmails` - lowercases and trims the email of users saved before emails were
+///   normalized, so login and lookups by email find them
+/// * `PantryNameIndex` - writes the `NameIndex` keys of pantries saved before the index
+///   existed, so name prefix search finds them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum Backfill {
+    EmailClaims,
+    NormalizedEmails,
+    PantryNameIndex,
+}
+
+/// Result of running a backfill over a table
+///
+/// # Fields
+///
+/// * `backfill` - the backfill run
+/// * `scanned` - model items read, guard items excluded
+/// * `updated` - items written by the backfill
+/// * `conflicts` - items left as they were because the write would clash with another
+///   item, e.g. a second user with an email already claimed
+/// * `sample_keys` - keys of up to `MAX_SAMPLE_KEYS` conflicting items
+#[derive(Debug, SimpleObject)]
+pub struct BackfillReport {
+    pub backfill: Backfill,
+    pub scanned: i64,
+    pub updated: i64,
+    pub conflicts: i64,
+    pub sample_keys: Vec<String>,
+}
+
+impl BackfillReport {
+    /// Counts an item left as it was because of a clash
+    fn conflict(&mut self, key: String) {
+        self.conflicts += 1;
+        if self.sample_keys.len() < MAX_SAMPLE_KEYS {
+            self.sample_keys.push(key);
+        }
+    }
+}
+
+/// Runs a backfill over every item of its table
+///
+/// Reads the whole table one page at a time, so it is billed as a full table read plus
+/// a write per item updated.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `backfill` - backfill to run
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if a scan page or a write fails
+pub async fn run_backfill(client: &Client, backfill: Backfill) -> Result<BackfillReport, AppError> {
+    let mut report = BackfillReport {
+        backfill,
+        scanned: 0,
+        updated: 0,
+        conflicts: 0,
+        sample_keys: Vec::new(),
+    };
+
+    let table = match backfill {
+        Backfill::EmailClaims | Backfill::NormalizedEmails => IntegrityTable::Users,
+        Backfill::PantryNameIndex => IntegrityTable::Pantries,
+    };
+    let table_name = table.table_name();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .scan()
+            .table_name(table_name)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan {} for backfill: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to scan {}", table_name))
+            })?;
+
+        for item in response.items().iter().filter(|item| !table.is_guard_item(item)) {
+            report.scanned += 1;
+            match backfill {
+                Backfill::EmailClaims => backfill_email_claim(client, item, &mut report).await?,
+                Backfill::NormalizedEmails =>
+                    backfill_normalized_email(client, item, &mut report).await?,
+                Backfill::PantryNameIndex =>
+                    backfill_pantry_name_keys(client, item, &mut report).await?,
+            }
+        }
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    info!(
+        backfill = ?backfill,
+        scanned = report.scanned,
+        updated = report.updated,
+        conflicts = report.conflicts,
+        "Backfill finished"
+    );
+
+    Ok(report)
+}
+
+/// Writes the email claim of a user that has none
+///
+/// A claim held by another user is a duplicate registered before claims existed, it is
+/// reported as a conflict and left to be merged, see `mergeUsers`.
+async fn backfill_email_claim(
+    client: &Client,
+    item: &HashMap<String, AttributeValue>,
+    report: &mut BackfillReport
+) -> Result<(), AppError> {
+    let (id, email) = match
+        (item.get("id").and_then(|v| v.as_s().ok()), item.get("email").and_then(|v| v.as_s().ok()))
+    {
+        (Some(id), Some(email)) => (id, email),
+        _ => {
+            return Ok(());
+        }
+    };
+
+    let result = client
+        .put_item()
+        .table_name("Users")
+        .item("id", AttributeValue::S(email_owner_id(email)))
+        .item("user_id", AttributeValue::S(id.clone()))
+        .condition_expression("attribute_not_exists(id)")
+        .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+        .send().await;
+
+    let error = match result {
+        Ok(_) => {
+            report.updated += 1;
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    let owner = match error.as_service_error() {
+        Some(PutItemError::ConditionalCheckFailedException(e)) =>
+            e
+                .item()
+                .and_then(|claim| claim.get("user_id"))
+                .and_then(|v| v.as_s().ok()),
+        _ => {
+            warn!("Failed to backfill email claim of user {}: {:?}", id, error);
+            return Err(AppError::DatabaseError("Failed to write email claim".to_string()));
+        }
+    };
+
+    if owner != Some(id) {
+        report.conflict(describe_key(item, &["id", "email"]));
+    }
+
+    Ok(())
+}
+
+/// Normalizes the stored email of a user, see `models::normalize::normalize_email`
+///
+/// A user whose normalized email already belongs to another user is a duplicate, it is
+/// reported as a conflict and left to be merged, see `mergeUsers`. The email claim needs
+/// no change, claims are keyed by the normalized email already.
+async fn backfill_normalized_email(
+    client: &Client,
+    item: &HashMap<String, AttributeValue>,
+    report: &mut BackfillReport
+) -> Result<(), AppError> {
+    let (id, email) = match
+        (item.get("id").and_then(|v| v.as_s().ok()), item.get("email").and_then(|v| v.as_s().ok()))
+    {
+        (Some(id), Some(email)) => (id, email),
+        _ => {
+            return Ok(());
+        }
+    };
+
+    let normalized = normalize_email(email);
+    if normalized == *email {
+        return Ok(());
+    }
+
+    if let Some(owner) = find_user_by_email(client, &normalized).await? {
+        if owner.id != *id {
+            report.conflict(describe_key(item, &["id", "email"]));
+            return Ok(());
+        }
+    }
+
+    // only while the email is still the one read, a concurrent change wins
+    let result = client
+        .update_item()
+        .table_name("Users")
+        .key("id", AttributeValue::S(id.clone()))
+        .update_expression("SET email = :email")
+        .condition_expression("email = :old_email")
+        .expression_attribute_values(":email", AttributeValue::S(normalized))
+        .expression_attribute_values(":old_email", AttributeValue::S(email.clone()))
+        .send().await;
+
+    match result {
+        Ok(_) => {
+            report.updated += 1;
+            Ok(())
+        }
+        Err(e) if
+            e
+                .as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception())
+        => Ok(()),
+        Err(e) => {
+            warn!("Failed to normalize email of user {}: {:?}", id, e);
+            Err(AppError::DatabaseError("Failed to normalize user email".to_string()))
+        }
+    }
+}
+
+/// Writes the `NameIndex` keys of a pantry that lacks them or has stale ones, see
+/// `Pantry::to_item`
+///
+/// A pantry with a blank name has no valid keys, so any it has are removed instead.
+async fn backfill_pantry_name_keys(
+    client: &Client,
+    item: &HashMap<String, AttributeValue>,
+    report: &mut BackfillReport
+) -> Result<(), AppError> {
+    let (id, name) = match
+        (item.get("id").and_then(|v| v.as_s().ok()), item.get("name").and_then(|v| v.as_s().ok()))
+    {
+        (Some(id), Some(name)) => (id, name),
+        _ => {
+            return Ok(());
+        }
+    };
+
+    let stored = |attribute: &str| item.get(attribute).and_then(|v| v.as_s().ok()).cloned();
+    let name_key = name_search_key(name);
+    let name_bucket = name_search_bucket(&name_key);
+
+    let update = if name_bucket.is_empty() {
+        if stored("name_lower").is_none() && stored("name_bucket").is_none() {
+            return Ok(());
+        }
+        UpdateBuilder::new().remove("name_lower").remove("name_bucket")
+    } else {
+        if
+            stored("name_lower").as_ref() == Some(&name_key) &&
+            stored("name_bucket").as_ref() == Some(&name_bucket)
+        {
+            return Ok(());
+        }
+        UpdateBuilder::new()
+            .set("name_lower", AttributeValue::S(name_key))
+            .set("name_bucket", AttributeValue::S(name_bucket))
+    };
+
+    let mut update = update
+        .build()
+        .ok_or_else(|| AppError::InternalServerError("Empty name key update".to_string()))?;
+
+    // only while the name is still the one read, a concurrent rename writes its own keys
+    update.names.insert("#backfill_name".to_string(), "name".to_string());
+    update.values
+        .get_or_insert_default()
+        .insert(":backfill_name".to_string(), AttributeValue::S(name.clone()));
+
+    let result = client
+        .update_item()
+        .table_name("Pantries")
+        .key("id", AttributeValue::S(id.clone()))
+        .update_expression(update.expression)
+        .set_expression_attribute_names(Some(update.names))
+        .set_expression_attribute_values(update.values)
+        .condition_expression("#backfill_name = :backfill_name")
+        .send().await;
+
+    match result {
+        Ok(_) => {
+            report.updated += 1;
+            Ok(())
+        }
+        Err(e) if
+            e
+                .as_service_error()
+                .is_some_and(|e| e.is_conditional_check_failed_exception())
+        => Ok(()),
+        Err(e) => {
+            warn!("Failed to backfill name keys of pantry {}: {:?}", id, e);
+            Err(AppError::DatabaseError("Failed to write pantry name keys".to_string()))
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! Detection of writes rejected for exceeding DynamoDB's item size limit.
+//!
+//! DynamoDB rejects any item larger than 400KB, attribute names included, with a
+//! `ValidationException`. Pantries are the items at risk, since their operating hours
+//! and any embedded lists grow with use. The SDK reports it like any other failed
+//! write, so writes check for it and return a validation error the caller can act on
+//! instead of an opaque database error.
+
+use aws_sdk_dynamodb::error::ProvideErrorMetadata;
+
+use crate::error::AppError;
+
+/// Whether a write failed because the item would exceed the 400KB limit
+pub fn is_item_too_large(error: &impl ProvideErrorMetadata) -> bool {
+    error.code() == Some("ValidationException") &&
+        error.message().is_some_and(|message| message.contains("Item size"))
+}
+
+/// Error returned when a record outgrows the item size limit
+pub fn record_too_large(record: &str) -> AppError {
+    AppError::ValidationError(
+        format!(
+            "record too large: the {} exceeds the 400KB storage limit, \
+             shorten long text fields or remove old holiday hours and try again",
+            record
+        )
+    )
+}
@@ -0,0 +1,50 @@
+//! Helpers for logging DynamoDB items safely.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+
+/// Attributes whose values must never reach the logs
+const SENSITIVE_ATTRIBUTES: [&str; 2] = ["password_hash", "key_hash"];
+
+/// Returns a copy of a DynamoDB item with sensitive attribute values replaced
+///
+/// # Arguments
+///
+/// * `item` - the dynamo db item
+///
+/// # Returns
+///
+/// Item safe to pass to `debug!`, with `password_hash` and similar values redacted
+pub fn redact_item(item: &HashMap<String, AttributeValue>) -> HashMap<String, AttributeValue> {
+    item.iter()
+        .map(|(name, value)| {
+            if SENSITIVE_ATTRIBUTES.contains(&name.as_str()) {
+                (name.clone(), AttributeValue::S("[REDACTED]".to_string()))
+            } else {
+                (name.clone(), value.clone())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_only_sensitive_attributes() {
+        let item = HashMap::from([
+            ("email".to_string(), AttributeValue::S("ana@example.com".to_string())),
+            ("password_hash".to_string(), AttributeValue::S("$argon2id$secret".to_string())),
+            ("key_hash".to_string(), AttributeValue::S("abc123".to_string())),
+        ]);
+
+        let redacted = redact_item(&item);
+
+        assert_eq!(redacted["email"], item["email"]);
+        assert_eq!(redacted["password_hash"], AttributeValue::S("[REDACTED]".to_string()));
+        assert_eq!(redacted["key_hash"], AttributeValue::S("[REDACTED]".to_string()));
+        assert!(!format!("{:?}", redacted).contains("secret"));
+    }
+}
@@ -1,4 +1,25 @@
 pub mod init;
 pub mod local;
 pub mod connect;
-pub mod ensure_table_exists;
\ No newline at end of file
+pub mod ensure_table_exists;
+pub mod pagination;
+pub mod batch;
+pub mod logging;
+pub mod users;
+pub mod scan;
+pub mod pantry_access;
+pub mod count;
+pub mod pantries;
+pub mod projection;
+pub mod update_builder;
+pub mod region;
+pub mod item_size;
+pub mod health;
+pub mod transaction;
+pub mod counter;
+pub mod throttle;
+pub mod single_flight;
+pub mod integrity;
+pub mod parse;
+pub mod attributes;
+pub mod outbox;
@@ -0,0 +1,153 @@
+//! Reads and writes against the Outbox table, see `models::outbox`.
+
+use std::time::Duration;
+
+use aws_sdk_dynamodb::{ types::{ AttributeValue, Put, TransactWriteItem }, Client };
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{
+    error::AppError,
+    models::outbox::{ outbox_timestamp, OutboxEvent, OUTBOX_PENDING },
+};
+
+/// Name of the table events are recorded in
+pub const OUTBOX_TABLE: &str = "Outbox";
+
+/// Wait before the first retry of a failed delivery, doubled after each later failure
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait between retries, an event is retried at least this often until delivered
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Gets how long to wait before retrying an event that has failed `attempts` times
+pub fn retry_delay(attempts: i64) -> Duration {
+    let doublings = attempts.clamp(1, 16) as u32 - 1;
+    RETRY_BASE_DELAY.saturating_mul(1 << doublings).min(RETRY_MAX_DELAY)
+}
+
+/// Builds the transaction action recording an event
+///
+/// Add it to the `transact_write_items` of the change the event describes.
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the put can't be built
+pub fn outbox_put(event: &OutboxEvent) -> Result<TransactWriteItem, AppError> {
+    let put = Put::builder()
+        .table_name(OUTBOX_TABLE)
+        .set_item(Some(event.to_item()))
+        .condition_expression("attribute_not_exists(id)")
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build outbox put: {}", e)))?;
+
+    Ok(TransactWriteItem::builder().put(put).build())
+}
+
+/// Gets pending events due for delivery through the PendingIndex GSI, oldest due first
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `now` - events whose `next_attempt_at` is after this are left for later
+/// * `limit` - most events to return
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the query fails
+pub async fn due_events(
+    client: &Client,
+    now: DateTime<Utc>,
+    limit: i32
+) -> Result<Vec<OutboxEvent>, AppError> {
+    let response = client
+        .query()
+        .table_name(OUTBOX_TABLE)
+        .index_name("PendingIndex")
+        .key_condition_expression("pending = :pending AND next_attempt_at <= :now")
+        .expression_attribute_values(":pending", AttributeValue::S(OUTBOX_PENDING.to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(outbox_timestamp(now)))
+        .limit(limit)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to query due outbox events: {:?}", e);
+            AppError::DatabaseError("Failed to get outbox events from db".to_string())
+        })?;
+
+    Ok(response.items().iter().filter_map(OutboxEvent::from_item).collect())
+}
+
+/// Marks an event delivered, taking it out of the PendingIndex
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the update fails
+pub async fn mark_delivered(client: &Client, id: &str, now: DateTime<Utc>) -> Result<(), AppError> {
+    client
+        .update_item()
+        .table_name(OUTBOX_TABLE)
+        .key("id", AttributeValue::S(id.to_string()))
+        .update_expression("SET delivered_at = :delivered_at REMOVE pending")
+        .expression_attribute_values(":delivered_at", AttributeValue::S(outbox_timestamp(now)))
+        .condition_expression("attribute_exists(id)")
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to mark outbox event {} delivered: {:?}", id, e);
+            AppError::DatabaseError("Failed to update outbox event".to_string())
+        })?;
+
+    Ok(())
+}
+
+/// Counts a failed delivery and pushes the event's next attempt back, see `retry_delay`
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the update fails
+pub async fn schedule_retry(
+    client: &Client,
+    event: &OutboxEvent,
+    now: DateTime<Utc>
+) -> Result<(), AppError> {
+    let attempts = event.attempts + 1;
+    let next_attempt_at = now + retry_delay(attempts);
+
+    client
+        .update_item()
+        .table_name(OUTBOX_TABLE)
+        .key("id", AttributeValue::S(event.id.clone()))
+        .update_expression("SET attempts = :attempts, next_attempt_at = :next_attempt_at")
+        .expression_attribute_values(":attempts", AttributeValue::N(attempts.to_string()))
+        .expression_attribute_values(
+            ":next_attempt_at",
+            AttributeValue::S(outbox_timestamp(next_attempt_at))
+        )
+        .condition_expression("attribute_exists(pending)")
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to reschedule outbox event {}: {:?}", event.id, e);
+            AppError::DatabaseError("Failed to update outbox event".to_string())
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(7), Duration::from_secs(1920));
+        assert_eq!(retry_delay(8), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(i64::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn retry_delay_treats_no_attempts_as_the_first() {
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(-3), RETRY_BASE_DELAY);
+    }
+}
@@ -0,0 +1,193 @@
+//! Cursor helpers for paginated DynamoDB reads.
+//!
+//! DynamoDB pages through results using the `LastEvaluatedKey` of the previous
+//! response. This module turns that key into an opaque, URL-safe cursor string
+//! that can be handed to GraphQL clients, and back again.
+
+use std::{ collections::HashMap, env, sync::OnceLock };
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine };
+
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Default number of items returned by a paginated resolver when `first` is not given
+pub const DEFAULT_PAGE_SIZE: i32 = 25;
+
+/// Maximum number of items a client may request in one page when `MAX_PAGE_SIZE` is unset
+pub const DEFAULT_MAX_PAGE_SIZE: i32 = 100;
+
+static MAX_PAGE_SIZE: OnceLock<i32> = OnceLock::new();
+
+/// Gets the most items a client may request in one page
+///
+/// Read once from the `MAX_PAGE_SIZE` env var, falling back to `DEFAULT_MAX_PAGE_SIZE`
+/// if it is unset or not a positive number.
+pub fn max_page_size() -> i32 {
+    *MAX_PAGE_SIZE.get_or_init(|| {
+        match env::var("MAX_PAGE_SIZE") {
+            Ok(value) =>
+                match value.trim().parse::<i32>() {
+                    Ok(size) if size > 0 => size,
+                    _ => {
+                        warn!(
+                            "MAX_PAGE_SIZE {:?} is not a positive number, using {}",
+                            value,
+                            DEFAULT_MAX_PAGE_SIZE
+                        );
+                        DEFAULT_MAX_PAGE_SIZE
+                    }
+                }
+            Err(_) => DEFAULT_MAX_PAGE_SIZE,
+        }
+    })
+}
+
+/// Clamps a client supplied page size to `1..=max_page_size()`
+///
+/// # Arguments
+///
+/// * `first` - optional page size requested by the client
+///
+/// # Returns
+///
+/// Page size to use for the DynamoDB `limit`
+pub fn page_size(first: Option<i32>) -> i32 {
+    first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, max_page_size())
+}
+
+/// Encodes a DynamoDB `LastEvaluatedKey` into an opaque cursor
+///
+/// Only string and number key attributes are supported, which covers every
+/// key schema defined in `ensure_table_exists`.
+///
+/// # Arguments
+///
+/// * `key` - the last evaluated key returned by a query or scan
+///
+/// # Returns
+///
+/// URL-safe base64 cursor string
+pub fn encode_cursor(key: &HashMap<String, AttributeValue>) -> String {
+    let mut plain = serde_json::Map::new();
+
+    for (name, value) in key {
+        match value {
+            AttributeValue::S(s) => {
+                plain.insert(name.clone(), serde_json::json!({ "S": s }));
+            }
+            AttributeValue::N(n) => {
+                plain.insert(name.clone(), serde_json::json!({ "N": n }));
+            }
+            _ => {}
+        }
+    }
+
+    URL_SAFE_NO_PAD.encode(serde_json::Value::Object(plain).to_string())
+}
+
+/// Extracts the key attributes of an item, for building a cursor that points at it
+///
+/// # Arguments
+///
+/// * `item` - the dynamo db item
+/// * `key_attributes` - names of the table key attributes
+///
+/// # Returns
+///
+/// Map containing only the key attributes present on the item
+pub fn key_of(
+    item: &HashMap<String, AttributeValue>,
+    key_attributes: &[&str]
+) -> HashMap<String, AttributeValue> {
+    key_attributes
+        .iter()
+        .filter_map(|name| item.get(*name).map(|value| (name.to_string(), value.clone())))
+        .collect()
+}
+
+/// Decodes a cursor produced by `encode_cursor` back into an `ExclusiveStartKey`
+///
+/// # Arguments
+///
+/// * `cursor` - cursor string supplied by the client
+///
+/// # Returns
+///
+/// Key map to pass as `exclusive_start_key`
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the cursor is malformed
+pub fn decode_cursor(cursor: &str) -> Result<HashMap<String, AttributeValue>, AppError> {
+    let invalid = || AppError::ValidationError("Invalid pagination cursor".to_string());
+
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let plain: serde_json::Map<String, serde_json::Value> = serde_json
+        ::from_slice(&bytes)
+        .map_err(|_| invalid())?;
+
+    let mut key = HashMap::new();
+
+    for (name, value) in plain {
+        let attribute = if let Some(s) = value.get("S").and_then(|v| v.as_str()) {
+            AttributeValue::S(s.to_string())
+        } else if let Some(n) = value.get("N").and_then(|v| v.as_str()) {
+            AttributeValue::N(n.to_string())
+        } else {
+            return Err(invalid());
+        };
+        key.insert(name, attribute);
+    }
+
+    if key.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            ("id".to_string(), AttributeValue::S("user-1".to_string())),
+            ("rank".to_string(), AttributeValue::N("42".to_string())),
+        ])
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = encode_cursor(&key());
+
+        assert!(!cursor.contains(['+', '/', '=']));
+        assert_eq!(decode_cursor(&cursor).unwrap(), key());
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let empty = URL_SAFE_NO_PAD.encode("{}");
+        let unsupported = URL_SAFE_NO_PAD.encode(r#"{"id":{"BOOL":true}}"#);
+
+        for cursor in ["not base64!", "bm90IGpzb24", empty.as_str(), unsupported.as_str()] {
+            assert!(
+                matches!(decode_cursor(cursor), Err(AppError::ValidationError(_))),
+                "{}",
+                cursor
+            );
+        }
+    }
+
+    #[test]
+    fn clamps_page_sizes() {
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(10)), 10);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(-5)), 1);
+        assert_eq!(page_size(Some(i32::MAX)), max_page_size());
+    }
+}
@@ -0,0 +1,596 @@
+//! Reads and writes against the Pantries table shared by several resolvers.
+
+use aws_sdk_dynamodb::{
+    operation::scan::builders::ScanFluentBuilder,
+    types::{ AttributeValue, Delete, Put, TransactWriteItem, Update },
+    Client,
+};
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{
+    db::{
+        counter::{ next_value, PANTRY_CODE_COUNTER },
+        item_size::{ is_item_too_large, record_too_large },
+        outbox::outbox_put,
+        projection::projection_of,
+        transaction::{ failed_condition_index, first_condition_failed },
+        update_builder::{ UpdateBuilder, UpdateExpression },
+    },
+    error::AppError,
+    models::{
+        normalize::normalize_name,
+        outbox::OutboxEvent,
+        pantry::{ name_search_bucket, OptStatus, OptStatusChange, Pantry },
+    },
+};
+
+/// Prefix of the `id` of pantry name guard items in the Pantries table
+///
+/// These items carry only `id` and `pantry_id`, so they stay out of the SelfManagedIndex,
+/// but scans of the Pantries table have to skip them.
+pub const NAME_GUARD_PREFIX: &str = "NAME#";
+
+/// Filter expression that keeps name guard items out of scans of the Pantries table
+pub const NOT_NAME_GUARD_FILTER: &str = "NOT begins_with(id, :name_guard_prefix)";
+
+/// Gets the id of the item claiming a pantry name within a zipcode
+///
+/// Names are compared lowercased and with whitespace collapsed.
+pub fn name_guard_id(name: &str, zipcode: &str) -> String {
+    format!(
+        "{}{}#{}",
+        NAME_GUARD_PREFIX,
+        zipcode.trim(),
+        normalize_name(name).to_lowercase()
+    )
+}
+
+/// Adds `NOT_NAME_GUARD_FILTER` to a scan of the Pantries table
+///
+/// Filters are applied after the read, so a page may hold fewer items than its limit
+pub fn exclude_name_guards(scan: ScanFluentBuilder) -> ScanFluentBuilder {
+    scan
+        .filter_expression(NOT_NAME_GUARD_FILTER)
+        .expression_attribute_values(
+            ":name_guard_prefix",
+            AttributeValue::S(NAME_GUARD_PREFIX.to_string())
+        )
+}
+
+/// Looks up a pantry by id
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `id` - ID of the pantry
+///
+/// # Returns
+///
+/// 'some' Pantry if one exists with that id, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the read fails
+pub async fn get_pantry(client: &Client, id: &str) -> Result<Option<Pantry>, AppError> {
+    let response = client
+        .get_item()
+        .table_name("Pantries")
+        .key("id", AttributeValue::S(id.to_string()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get pantry by id: {:?}", e);
+            AppError::DatabaseError("Failed to get pantry by id from db".to_string())
+        })?;
+
+    Ok(response.item.as_ref().and_then(Pantry::from_item))
+}
+
+/// Takes the next short pantry code, e.g. `PAN-00042`
+///
+/// Codes come from an atomic counter, so concurrent creates never share one. A code taken
+/// by a create that then fails is not reused, leaving a gap in the sequence.
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the counter can't be incremented
+pub async fn next_pantry_code(client: &Client) -> Result<String, AppError> {
+    let value = next_value(client, PANTRY_CODE_COUNTER).await?;
+    Ok(format!("PAN-{:05}", value))
+}
+
+/// Looks up a pantry by its short code through the CodeIndex GSI
+///
+/// The index only projects the pantry's id, so the pantry itself is read from the base table.
+/// Codes are matched case-insensitively.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `code` - short code of the pantry, e.g. `PAN-00042`
+///
+/// # Returns
+///
+/// 'some' Pantry if one has that code, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the query or read fails
+pub async fn find_pantry_by_code(client: &Client, code: &str) -> Result<Option<Pantry>, AppError> {
+    let response = client
+        .query()
+        .table_name("Pantries")
+        .index_name("CodeIndex")
+        .key_condition_expression("code = :code")
+        .expression_attribute_values(":code", AttributeValue::S(code.trim().to_uppercase()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to query pantry by code: {:?}", e);
+            AppError::DatabaseError("Failed to get pantry by code from db".to_string())
+        })?;
+
+    match response.items().first().and_then(|item| item.get("id")).and_then(|id| id.as_s().ok()) {
+        Some(id) => get_pantry(client, id).await,
+        None => Ok(None),
+    }
+}
+
+/// Saves a new pantry together with the guard item claiming its name in its zipcode
+///
+/// The guard item has id `NAME#<zipcode>#<lowercased name>` and stores the pantry id.
+/// It is always written, so the latest pantry to use a name owns it, but when
+/// `reject_duplicate_name` is set the transaction fails if the guard already exists.
+/// Renaming a pantry or changing its zipcode moves the guard, see `name_guard_move`.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry` - the pantry to save
+/// * `reject_duplicate_name` - fail instead of saving when the name is taken in the zipcode
+///
+/// # Errors
+///
+/// Returns Conflict Error (409) App error variant if `reject_duplicate_name` is set and a
+/// pantry with the same name already exists in the zipcode
+///
+/// Returns Validation Error (400) App error variant if the pantry exceeds the item size limit
+///
+/// Returns Database Error (500) App error variant if the transaction fails otherwise
+pub async fn create_pantry(
+    client: &Client,
+    pantry: &Pantry,
+    reject_duplicate_name: bool
+) -> Result<(), AppError> {
+    let mut guard = Put::builder()
+        .table_name("Pantries")
+        .item("id", AttributeValue::S(name_guard_id(&pantry.name, &pantry.address.zipcode)))
+        .item("pantry_id", AttributeValue::S(pantry.id.clone()));
+    if reject_duplicate_name {
+        guard = guard.condition_expression("attribute_not_exists(id)");
+    }
+    let guard = guard
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build name guard: {}", e)))?;
+
+    let item = Put::builder()
+        .table_name("Pantries")
+        .set_item(Some(pantry.to_item()))
+        .condition_expression("attribute_not_exists(id)")
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build pantry put: {}", e)))?;
+
+    let result = client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(guard).build())
+        .transact_items(TransactWriteItem::builder().put(item).build())
+        .send().await;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if reject_duplicate_name && first_condition_failed(&e) =>
+            Err(
+                AppError::ConflictError(
+                    format!(
+                        "A pantry named {} already exists in {}",
+                        pantry.name,
+                        pantry.address.zipcode
+                    )
+                )
+            ),
+        Err(e) if is_item_too_large(&e) => Err(record_too_large("pantry")),
+        Err(e) => {
+            warn!("Failed to save pantry {}: {:?}", pantry.id, e);
+            Err(AppError::DatabaseError("Failed to save pantry".to_string()))
+        }
+    }
+}
+
+/// Name guard items to swap when a pantry's name or zipcode changes
+///
+/// # Fields
+///
+/// * `name` - the pantry's new name
+/// * `zipcode` - the pantry's new zipcode
+/// * `new_id` - id of the guard claiming the new name
+/// * `old_id` - id of the guard to release, 'none' if the pantry doesn't own its old guard,
+///   e.g. it was created before guards existed or another pantry took the name since
+#[derive(Debug)]
+pub struct NameGuardMove {
+    pub name: String,
+    pub zipcode: String,
+    pub new_id: String,
+    pub old_id: Option<String>,
+}
+
+/// Works out how a pantry's name guard moves when its name or zipcode changes
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry` - the pantry as last read
+/// * `name` - the pantry's name after the update
+/// * `zipcode` - the pantry's zipcode after the update
+///
+/// # Returns
+///
+/// 'some' NameGuardMove if the guard id changes, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the old guard can't be read
+pub async fn name_guard_move(
+    client: &Client,
+    pantry: &Pantry,
+    name: &str,
+    zipcode: &str
+) -> Result<Option<NameGuardMove>, AppError> {
+    let old_id = name_guard_id(&pantry.name, &pantry.address.zipcode);
+    let new_id = name_guard_id(name, zipcode);
+    if old_id == new_id {
+        return Ok(None);
+    }
+
+    let response = client
+        .get_item()
+        .table_name("Pantries")
+        .key("id", AttributeValue::S(old_id.clone()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get name guard: {:?}", e);
+            AppError::DatabaseError("Failed to get pantry name guard from db".to_string())
+        })?;
+
+    let owns_old_guard = response.item
+        .as_ref()
+        .and_then(|item| item.get("pantry_id"))
+        .and_then(|v| v.as_s().ok())
+        .is_some_and(|owner| *owner == pantry.id);
+
+    Ok(
+        Some(NameGuardMove {
+            name: name.to_string(),
+            zipcode: zipcode.to_string(),
+            new_id,
+            old_id: owns_old_guard.then_some(old_id),
+        })
+    )
+}
+
+/// Builds the transaction actions that claim a pantry's new name and release its old one
+///
+/// The new guard may only be taken if it is free or already points at the pantry, the old
+/// one is only released while it still points at the pantry.
+fn name_guard_actions(
+    pantry_id: &str,
+    guard: &NameGuardMove
+) -> Result<Vec<TransactWriteItem>, AppError> {
+    let claim = Put::builder()
+        .table_name("Pantries")
+        .item("id", AttributeValue::S(guard.new_id.clone()))
+        .item("pantry_id", AttributeValue::S(pantry_id.to_string()))
+        .condition_expression("attribute_not_exists(id) OR pantry_id = :pantry_id")
+        .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.to_string()))
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build name guard: {}", e)))?;
+
+    let mut actions = vec![TransactWriteItem::builder().put(claim).build()];
+
+    if let Some(old_id) = &guard.old_id {
+        let release = Delete::builder()
+            .table_name("Pantries")
+            .key("id", AttributeValue::S(old_id.clone()))
+            .condition_expression("pantry_id = :pantry_id")
+            .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.to_string()))
+            .build()
+            .map_err(|e|
+                AppError::DatabaseError(format!("Failed to build name guard release: {}", e))
+            )?;
+
+        actions.push(TransactWriteItem::builder().delete(release).build());
+    }
+
+    Ok(actions)
+}
+
+/// Applies a pantry update in a transaction, with the outbox event describing it and the
+/// move of its name guard
+///
+/// Transactions can't return the updated item, so the pantry is read back afterwards
+/// with a strongly consistent read.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `id` - ID of the pantry to update
+/// * `update` - the update, its values including any the condition uses
+/// * `condition` - condition the pantry must meet, e.g. still having the opt status it was read with
+/// * `event` - event to record in the outbox, 'none' if the update has none, see `models::outbox`
+/// * `name_guard` - the name guard move, 'none' if the name and zipcode are unchanged
+///
+/// # Returns
+///
+/// The pantry after the update
+///
+/// # Errors
+///
+/// Returns Conflict Error (409) App error variant if the pantry doesn't meet the condition,
+/// or its new name is taken in its zipcode
+///
+/// Returns Validation Error (400) App error variant if the pantry would exceed the item size limit
+///
+/// Returns Database Error (500) App error variant if the transaction or the read back fails
+pub async fn update_pantry_with_event(
+    client: &Client,
+    id: &str,
+    update: UpdateExpression,
+    condition: &str,
+    event: Option<&OutboxEvent>,
+    name_guard: Option<&NameGuardMove>
+) -> Result<Pantry, AppError> {
+    let pantry_update = Update::builder()
+        .table_name("Pantries")
+        .key("id", AttributeValue::S(id.to_string()))
+        .update_expression(update.expression)
+        .set_expression_attribute_names(Some(update.names))
+        .set_expression_attribute_values(update.values)
+        .condition_expression(condition)
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build pantry update: {}", e)))?;
+
+    // the pantry update goes first and the guard claim second, so a failed condition's
+    // position says which of them failed
+    let mut transact_items = vec![TransactWriteItem::builder().update(pantry_update).build()];
+    if let Some(guard) = name_guard {
+        transact_items.extend(name_guard_actions(id, guard)?);
+    }
+    if let Some(event) = event {
+        transact_items.push(outbox_put(event)?);
+    }
+
+    let result = client
+        .transact_write_items()
+        .set_transact_items(Some(transact_items))
+        .send().await;
+
+    if let Err(e) = result {
+        return Err(match (failed_condition_index(&e), name_guard) {
+            (Some(0), _) =>
+                AppError::ConflictError("The pantry changed during the update, try again".to_string()),
+            (Some(1), Some(guard)) =>
+                AppError::ConflictError(
+                    format!("A pantry named {} already exists in {}", guard.name, guard.zipcode)
+                ),
+            (Some(_), _) =>
+                AppError::ConflictError(
+                    "The pantry's name changed during the update, try again".to_string()
+                ),
+            (None, _) if is_item_too_large(&e) => record_too_large("pantry"),
+            (None, _) => {
+                warn!("Failed to update pantry {}: {:?}", id, e);
+                AppError::DatabaseError("Failed to update pantry".to_string())
+            }
+        });
+    }
+
+    let response = client
+        .get_item()
+        .table_name("Pantries")
+        .key("id", AttributeValue::S(id.to_string()))
+        .consistent_read(true)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to read back pantry {}: {:?}", id, e);
+            AppError::DatabaseError("Updated pantry could not be read back".to_string())
+        })?;
+
+    response.item
+        .as_ref()
+        .and_then(Pantry::from_item)
+        .ok_or_else(|| AppError::DatabaseError("Updated pantry could not be read back".to_string()))
+}
+
+/// Changes a pantry's opt status and appends the change to its history
+///
+/// The update only applies while the pantry still has the opt status it was read with,
+/// so a concurrent change is never overwritten or left out of the history. Changing to
+/// the status the pantry already has does nothing and returns the pantry as it was.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry` - the pantry as last read
+/// * `opt_status` - new opt status
+/// * `actor` - ID of the user making the change
+/// * `deliver_event` - whether the change's outbox event is delivered to the webhook, see
+///   `OutboxEvent::delivered_by_webhook`
+/// * `now` - when the change is made, from `schema::context::now`
+///
+/// # Returns
+///
+/// The pantry after the change
+///
+/// # Errors
+///
+/// Returns Conflict Error (409) App error variant if the pantry was deleted or its opt
+/// status changed since it was read
+///
+/// Returns Validation Error (400) App error variant if the change isn't allowed, see
+/// `OptStatus::can_transition_to`, or the pantry would exceed the item size limit
+///
+/// Returns Database Error (500) App error variant if the update fails otherwise
+pub async fn set_opt_status(
+    client: &Client,
+    pantry: Pantry,
+    opt_status: OptStatus,
+    actor: &str,
+    deliver_event: bool,
+    now: DateTime<Utc>
+) -> Result<Pantry, AppError> {
+    if pantry.opt_status == opt_status {
+        return Ok(pantry);
+    }
+
+    pantry.opt_status.check_transition(opt_status)?;
+
+    let change = OptStatusChange {
+        changed_at: now,
+        from: pantry.opt_status,
+        to: opt_status,
+        actor: actor.to_string(),
+    };
+    let event = OutboxEvent::opt_status_changed(&pantry.id, &pantry.name, &change)
+        .delivered_by_webhook(deliver_event);
+    let history = change.append_to(pantry.opt_status_history);
+
+    let mut update = UpdateBuilder::new()
+        .set("opt_status", AttributeValue::S(opt_status.to_str().to_string()))
+        .set("opt_status_history", OptStatusChange::history_to_attribute(&history))
+        .touch(now)
+        .build()
+        .ok_or_else(|| AppError::InternalServerError("Empty opt status update".to_string()))?;
+
+    update.values.get_or_insert_default().insert(
+        ":expected_opt_status".to_string(),
+        AttributeValue::S(pantry.opt_status.to_str().to_string())
+    );
+
+    update_pantry_with_event(
+        client,
+        &pantry.id,
+        update,
+        "attribute_exists(id) AND opt_status = :expected_opt_status",
+        Some(&event),
+        None
+    ).await
+}
+
+/// Reads the opt status and self managed flag of every pantry, following pagination to the end
+///
+/// Only the two attributes are returned, but DynamoDB bills every item of the table in full,
+/// so this costs a complete table read.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+///
+/// # Returns
+///
+/// `(opt_status, is_self_managed)` of each pantry, as stored
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any scan page fails
+pub async fn scan_pantry_groups(client: &Client) -> Result<Vec<(String, String)>, AppError> {
+    let projection = projection_of(&["opt_status", "is_self_managed"]);
+    let mut groups = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = exclude_name_guards(client.scan().table_name("Pantries"))
+            .projection_expression(projection.expression.clone())
+            .set_expression_attribute_names(Some(projection.names.clone()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan pantry groups: {:?}", e);
+                AppError::DatabaseError("Failed to scan Pantries".to_string())
+            })?;
+
+        groups.extend(
+            response
+                .items()
+                .iter()
+                .map(|item| {
+                    let attribute = |name: &str| {
+                        item.get(name)
+                            .and_then(|v| v.as_s().ok())
+                            .cloned()
+                            .unwrap_or_default()
+                    };
+                    (attribute("opt_status"), attribute("is_self_managed"))
+                })
+        );
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Finds pantries whose name starts with a prefix through the NameIndex GSI
+///
+/// Pantries saved before the index was added only appear once their keys are backfilled, see
+/// `db::integrity::Backfill::PantryNameIndex`.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `prefix` - start of the name, already lowercased with `name_search_key`, not empty
+/// * `limit` - most pantries to return
+///
+/// # Returns
+///
+/// `(id, name)` of each matching pantry, in name order ignoring case
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the query fails
+pub async fn find_pantries_by_name_prefix(
+    client: &Client,
+    prefix: &str,
+    limit: i32
+) -> Result<Vec<(String, String)>, AppError> {
+    let response = client
+        .query()
+        .table_name("Pantries")
+        .index_name("NameIndex")
+        .key_condition_expression(
+            "name_bucket = :name_bucket AND begins_with(name_lower, :prefix)"
+        )
+        .expression_attribute_values(
+            ":name_bucket",
+            AttributeValue::S(name_search_bucket(prefix))
+        )
+        .expression_attribute_values(":prefix", AttributeValue::S(prefix.to_string()))
+        .limit(limit)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to query pantries by name prefix: {:?}", e);
+            AppError::DatabaseError("Failed to get pantry suggestions from db".to_string())
+        })?;
+
+    Ok(
+        response
+            .items()
+            .iter()
+            .filter_map(|item| {
+                let id = item.get("id")?.as_s().ok()?;
+                let name = item.get("name")?.as_s().ok()?;
+                Some((id.clone(), name.clone()))
+            })
+            .collect()
+    )
+}
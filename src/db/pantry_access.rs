@@ -0,0 +1,180 @@
+//! Reads against the PantryAccess table shared by resolvers and auth checks.
+
+use aws_sdk_dynamodb::{ types::AttributeValue, Client };
+use tracing::warn;
+
+use crate::{ db::parse::parse_items, error::AppError, models::pantry_access::PantryAccess };
+
+/// Gets a single user's access row for a pantry
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry_id` - ID of the pantry
+/// * `user_id` - ID of the user
+///
+/// # Returns
+///
+/// 'some' PantryAccess if the user has access to the pantry, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the read fails
+pub async fn get_pantry_access(
+    client: &Client,
+    pantry_id: &str,
+    user_id: &str
+) -> Result<Option<PantryAccess>, AppError> {
+    let response = client
+        .get_item()
+        .table_name("PantryAccess")
+        .key("pantry_id", AttributeValue::S(pantry_id.to_string()))
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get pantry access: {:?}", e);
+            AppError::DatabaseError("Failed to get pantry access from db".to_string())
+        })?;
+
+    Ok(response.item.as_ref().and_then(PantryAccess::from_item))
+}
+
+/// Lists every access row for a pantry, following pagination to the end
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry_id` - ID of the pantry
+///
+/// # Returns
+///
+/// All access rows for the pantry, ordered by user id
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any query page fails
+pub async fn list_pantry_access(
+    client: &Client,
+    pantry_id: &str
+) -> Result<Vec<PantryAccess>, AppError> {
+    let mut rows = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name("PantryAccess")
+            .key_condition_expression("pantry_id = :pantry_id")
+            .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to query pantry access: {:?}", e);
+                AppError::DatabaseError("Failed to get pantry access from db".to_string())
+            })?;
+
+        rows.extend(parse_items(response.items(), "PantryAccess", PantryAccess::from_item)?);
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Lists every access row of a user through the UserAccessIndex GSI, following pagination to the end
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `user_id` - ID of the user
+///
+/// # Returns
+///
+/// All access rows of the user, ordered by pantry id
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any query page fails
+pub async fn list_user_access(
+    client: &Client,
+    user_id: &str
+) -> Result<Vec<PantryAccess>, AppError> {
+    let mut rows = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name("PantryAccess")
+            .index_name("UserAccessIndex")
+            .key_condition_expression("user_id = :user_id")
+            .expression_attribute_values(":user_id", AttributeValue::S(user_id.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to query user access: {:?}", e);
+                AppError::DatabaseError("Failed to get user access from db".to_string())
+            })?;
+
+        rows.extend(parse_items(response.items(), "PantryAccess", PantryAccess::from_item)?);
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Lists the contact agents of a pantry through the ContactAgentIndex GSI, following pagination to the end
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `pantry_id` - ID of the pantry
+///
+/// # Returns
+///
+/// Access rows of the pantry that have `is_contact_agent` set
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any query page fails
+pub async fn list_contact_agents(
+    client: &Client,
+    pantry_id: &str
+) -> Result<Vec<PantryAccess>, AppError> {
+    let mut rows = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .query()
+            .table_name("PantryAccess")
+            .index_name("ContactAgentIndex")
+            .key_condition_expression(
+                "pantry_id = :pantry_id AND is_contact_agent = :is_contact_agent"
+            )
+            .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.to_string()))
+            .expression_attribute_values(":is_contact_agent", AttributeValue::S("true".to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to query contact agents: {:?}", e);
+                AppError::DatabaseError("Failed to get contact agents from db".to_string())
+            })?;
+
+        rows.extend(parse_items(response.items(), "PantryAccess", PantryAccess::from_item)?);
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,115 @@
+//! Turning read items into models, leniently or strictly.
+//!
+//! By default an item its model can't read is skipped, so one corrupt row doesn't
+//! fail a whole listing. Setting `STRICT_ITEM_PARSING=true` makes such an item fail
+//! the read with a Database Error instead, so corruption surfaces right away, e.g.
+//! in staging. `scanDataIntegrity` lists the offending items either way.
+
+use std::{ collections::HashMap, env };
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use tracing::{ debug, warn };
+
+use crate::error::AppError;
+
+use super::logging::redact_item;
+
+/// Whether items that fail to parse are errors rather than skipped
+///
+/// Read from the `STRICT_ITEM_PARSING` env var, `true` or `1` enables it.
+pub fn strict_item_parsing() -> bool {
+    env::var("STRICT_ITEM_PARSING").is_ok_and(|value| {
+        matches!(value.trim().to_lowercase().as_str(), "true" | "1")
+    })
+}
+
+/// Parses every item of a read with `parse`, strictly if `STRICT_ITEM_PARSING` is set
+///
+/// # Arguments
+///
+/// * `items` - items as read from the table
+/// * `table_name` - table the items were read from, for logs and errors
+/// * `parse` - the model's parser, e.g. `Pantry::from_item`
+///
+/// # Returns
+///
+/// The parsed models, in item order; items that fail to parse are skipped unless strict
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant in strict mode if any item fails to parse
+pub fn parse_items<T>(
+    items: &[HashMap<String, AttributeValue>],
+    table_name: &str,
+    parse: impl Fn(&HashMap<String, AttributeValue>) -> Option<T>
+) -> Result<Vec<T>, AppError> {
+    parse_items_with(items, table_name, strict_item_parsing(), parse)
+}
+
+/// Parses every item of a read with `parse`, see `parse_items`
+///
+/// # Arguments
+///
+/// * `items` - items as read from the table
+/// * `table_name` - table the items were read from, for logs and errors
+/// * `strict` - whether an item that fails to parse fails the read instead of being skipped
+/// * `parse` - the model's parser, e.g. `Pantry::from_item`
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if `strict` and any item fails to parse
+pub fn parse_items_with<T>(
+    items: &[HashMap<String, AttributeValue>],
+    table_name: &str,
+    strict: bool,
+    parse: impl Fn(&HashMap<String, AttributeValue>) -> Option<T>
+) -> Result<Vec<T>, AppError> {
+    let mut models = Vec::with_capacity(items.len());
+
+    for item in items {
+        match parse(item) {
+            Some(model) => models.push(model),
+            None if strict => {
+                warn!("Failed to parse {} item: {:?}", table_name, redact_item(item));
+                return Err(
+                    AppError::DatabaseError(format!("A {} item could not be read", table_name))
+                );
+            }
+            None => debug!("Skipping unparsable {} item: {:?}", table_name, redact_item(item)),
+        }
+    }
+
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: Option<&str>) -> HashMap<String, AttributeValue> {
+        name.map(|name| HashMap::from([("name".to_string(), AttributeValue::S(name.to_string()))]))
+            .unwrap_or_default()
+    }
+
+    fn name(item: &HashMap<String, AttributeValue>) -> Option<String> {
+        item.get("name")?.as_s().ok().cloned()
+    }
+
+    #[test]
+    fn lenient_parsing_skips_unreadable_items() {
+        let items = [item(Some("a")), item(None), item(Some("b"))];
+
+        let names = parse_items_with(&items, "Pantries", false, name).unwrap();
+
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn strict_parsing_fails_on_an_unreadable_item() {
+        let items = [item(Some("a")), item(None), item(Some("b"))];
+
+        let result = parse_items_with(&items, "Pantries", true, name);
+
+        assert!(matches!(result, Err(AppError::DatabaseError(_))));
+    }
+}
@@ -0,0 +1,79 @@
+//! Projection expressions built from the GraphQL selection.
+//!
+//! Reading only the attributes a query selects shrinks DynamoDB responses and
+//! the work of deserializing them. It does not lower read capacity: get_item,
+//! query and scan are billed on the size of the whole item read, projected or
+//! not. Items read with a projection are partial, so they must be turned into
+//! models with a builder that tolerates missing attributes.
+
+use std::collections::{ BTreeSet, HashMap };
+
+use async_graphql::Lookahead;
+
+/// A DynamoDB projection expression with its attribute name placeholders
+///
+/// # Fields
+///
+/// * `expression` - value for `projection_expression`, e.g. `#p0, #p1`
+/// * `names` - value for `expression_attribute_names`, placeholders to attribute names
+///
+/// Placeholders are used for every attribute so reserved words such as `name` are safe.
+#[derive(Debug)]
+pub struct Projection {
+    pub expression: String,
+    pub names: HashMap<String, String>,
+}
+
+/// Builds a projection for the fields selected under a lookahead
+///
+/// # Arguments
+///
+/// * `lookahead` - selection of the object being read, e.g. `ctx.look_ahead()` or its `nodes`
+/// * `field_attributes` - GraphQL field names of the object paired with the attribute each reads
+/// * `always` - attributes read regardless of selection, e.g. key attributes needed for cursors
+///
+/// # Returns
+///
+/// 'some' Projection when every selected field has a known attribute, 'none' when one
+/// doesn't, in which case the caller should read the full item
+pub fn projection_for(
+    lookahead: &Lookahead<'_>,
+    field_attributes: &[(&str, &str)],
+    always: &[&str]
+) -> Option<Projection> {
+    let mut attributes = always.iter().copied().collect::<BTreeSet<&str>>();
+
+    for selection in lookahead.selection_fields() {
+        for field in selection.selection_set() {
+            // introspection fields such as __typename don't read the item
+            if field.name().starts_with("__") {
+                continue;
+            }
+
+            let (_, attribute) = field_attributes
+                .iter()
+                .find(|(name, _)| *name == field.name())?;
+            attributes.insert(attribute);
+        }
+    }
+
+    Some(projection_of(&attributes.into_iter().collect::<Vec<&str>>()))
+}
+
+/// Builds a projection reading a fixed list of attributes
+pub fn projection_of(attributes: &[&str]) -> Projection {
+    let names = attributes
+        .iter()
+        .enumerate()
+        .map(|(i, attribute)| (format!("#p{}", i), attribute.to_string()))
+        .collect::<Vec<(String, String)>>();
+
+    Projection {
+        expression: names
+            .iter()
+            .map(|(placeholder, _)| placeholder.as_str())
+            .collect::<Vec<&str>>()
+            .join(", "),
+        names: names.into_iter().collect(),
+    }
+}
@@ -0,0 +1,28 @@
+//! AWS region selection for the DynamoDB client.
+
+use std::env;
+
+use aws_config::{ meta::region::RegionProviderChain, Region };
+
+/// Region used when neither the provider chain nor `APP_REGION` gives one
+const DEFAULT_REGION: &str = "us-east-2";
+
+/// Gets the region used when the provider chain finds none
+///
+/// Read from the `APP_REGION` env var, falling back to `DEFAULT_REGION`. Set it for
+/// deployments outside `DEFAULT_REGION` that don't configure the region through the
+/// standard `AWS_REGION` env var or AWS profile.
+fn fallback_region() -> Region {
+    match env::var("APP_REGION") {
+        Ok(region) if !region.trim().is_empty() => Region::new(region.trim().to_string()),
+        _ => Region::new(DEFAULT_REGION),
+    }
+}
+
+/// Builds the region provider for the DynamoDB client
+///
+/// The environment's default provider chain wins, `fallback_region` is only used when it
+/// finds no region.
+pub fn region_provider() -> RegionProviderChain {
+    RegionProviderChain::default_provider().or_else(fallback_region())
+}
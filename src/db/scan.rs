@@ -0,0 +1,55 @@
+//! Full table scan helper.
+//!
+//! A single `Scan` call returns at most 1MB of data, so reading a whole table
+//! means following `LastEvaluatedKey` until DynamoDB stops returning one. Every
+//! item read is billed, so only use this where no key or index can narrow the read.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{ types::AttributeValue, Client };
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Reads every item in a table, following pagination to the end
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `table_name` - table to scan
+///
+/// # Returns
+///
+/// All items in the table
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if any scan page fails
+pub async fn scan_all_items(
+    client: &Client,
+    table_name: &str
+) -> Result<Vec<HashMap<String, AttributeValue>>, AppError> {
+    let mut items = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = client
+            .scan()
+            .table_name(table_name)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan {}: {:?}", table_name, e);
+                AppError::DatabaseError(format!("Failed to scan {}", table_name))
+            })?;
+
+        items.extend(response.items.unwrap_or_default());
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(items)
+}
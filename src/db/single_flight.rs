@@ -0,0 +1,130 @@
+//! Sharing of identical expensive reads that run at the same time.
+//!
+//! A resolver opts in by keeping a static `SingleFlight` and running its read
+//! through `SingleFlight::run`. While a read for a key is in flight, every other
+//! caller asking for the same key waits for it and gets a clone of its result
+//! instead of issuing its own scan. Nothing is cached: once the read finishes,
+//! the next caller starts a new one.
+//!
+//! The key must capture everything the read depends on, e.g. the table name.
+//! Callers must still check their own authorization, the shared result is the
+//! raw read.
+
+use std::{ collections::HashMap, future::Future, sync::{ Arc, Mutex } };
+
+use tokio::sync::OnceCell;
+
+use crate::error::AppError;
+
+type Flight<T> = Arc<OnceCell<Result<T, AppError>>>;
+
+/// In-flight reads of one resolver, keyed by what they read
+pub struct SingleFlight<T> {
+    in_flight: Mutex<HashMap<String, Flight<T>>>,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self { in_flight: Mutex::new(HashMap::new()) }
+    }
+
+    /// Runs `read`, or joins the run of an identical read already in flight
+    ///
+    /// If the caller running the read is cancelled, one of the waiting callers runs it instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - identifies the read, equal keys must produce equal results
+    /// * `read` - the read to run when none is in flight for `key`
+    ///
+    /// # Returns
+    ///
+    /// The result of the read, shared by every caller that joined it; errors are shared too
+    pub async fn run<F, Fut>(&self, key: &str, read: F) -> Result<T, AppError>
+        where F: FnOnce() -> Fut, Fut: Future<Output = Result<T, AppError>>
+    {
+        let flight = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(in_flight.entry(key.to_string()).or_default())
+        };
+
+        let result = flight.get_or_init(read).await.clone();
+
+        // the first caller to finish retires the flight, later callers start a new read
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if in_flight.get(key).is_some_and(|current| Arc::ptr_eq(current, &flight)) {
+            in_flight.remove(key);
+        }
+
+        result
+    }
+}
+
+impl<T: Clone> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{ sync::atomic::{ AtomicUsize, Ordering }, time::Duration };
+
+    #[tokio::test]
+    async fn concurrent_runs_share_one_read() {
+        let flights = Arc::new(SingleFlight::<usize>::new());
+        let reads = Arc::new(AtomicUsize::new(0));
+
+        let callers: Vec<_> = (0..8)
+            .map(|_| {
+                let flights = Arc::clone(&flights);
+                let reads = Arc::clone(&reads);
+                tokio::spawn(async move {
+                    flights.run("pantries", || async move {
+                        reads.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok(42)
+                    }).await
+                })
+            })
+            .collect();
+
+        for caller in callers {
+            assert_eq!(caller.await.unwrap().unwrap(), 42);
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        // the flight is retired, the next caller reads again
+        let again = flights.run("pantries", || async { Ok(7) }).await.unwrap();
+        assert_eq!(again, 7);
+    }
+
+    #[tokio::test]
+    async fn a_waiter_takes_over_when_the_leader_is_cancelled() {
+        let flights = Arc::new(SingleFlight::<usize>::new());
+        let (started, leader_started) = tokio::sync::oneshot::channel();
+
+        let leader = {
+            let flights = Arc::clone(&flights);
+            tokio::spawn(async move {
+                flights.run("pantries", || async move {
+                    let _ = started.send(());
+                    std::future::pending::<Result<usize, AppError>>().await
+                }).await
+            })
+        };
+        leader_started.await.unwrap();
+
+        let waiter = {
+            let flights = Arc::clone(&flights);
+            tokio::spawn(async move { flights.run("pantries", || async { Ok(7) }).await })
+        };
+        // let the waiter join the leader's flight before cancelling it
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        leader.abort();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), waiter).await;
+        assert_eq!(result.expect("waiter should not hang").unwrap().unwrap(), 7);
+    }
+}
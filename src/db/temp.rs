@@ -15,6 +15,7 @@ use aws_sdk_dynamodb::{
         ScalarAttributeType,
     },
 };
+use tracing::{ debug, info };
 
 use crate::error::AppError;
 
@@ -45,11 +46,11 @@ pub async fn usertt(tables: ListTablesOutput, client: &Client) -> Result<(), Err
 
     // Check if table already exists
     if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
+        debug!(table = table_name, "Table already exists");
         return Ok(());
     }
 
-    println!("Creating '{}' table...", table_name);
+    info!(table = table_name, "Creating table");
 
     // Create the table with the following configuration:
     // - Primary key: id (String, Hash/Partition key)
@@ -100,7 +101,7 @@ pub async fn usertt(tables: ListTablesOutput, client: &Client) -> Result<(), Err
 
         .send().await?;
 
-    println!("Table '{}' created successfully", table_name);
+    info!(table = table_name, "Table created");
 
     Ok(())
 }
@@ -110,7 +111,7 @@ async fn users(tables: ListTablesOutput, client: &Client) -> Result<(), AppError
 
   
     if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
+        debug!(table = table_name, "Table already exists");
         return Ok(());
     }
 
@@ -121,7 +122,7 @@ async fn pantry_system(tables: ListTablesOutput, client: &Client) -> Result<(),
     let table_name = "Pantries";
 
     if tables.table_names().contains(&table_name.to_string()) {
-        println!("Table '{}' already exists", table_name);
+        debug!(table = table_name, "Table already exists");
         return Ok(());
     }
 
@@ -277,6 +278,10 @@ async fn pantry_system(tables: ListTablesOutput, client: &Client) -> Result<(),
         .send().await
         .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
-    println!("Table created: {:?}", response);
+    info!(
+        table = table_name,
+        status = ?response.table_description().and_then(|table| table.table_status()),
+        "Table created"
+    );
     Ok(())
 }
@@ -0,0 +1,50 @@
+//! Concurrency limit for bulk writes.
+//!
+//! PayPerRequest tables still throttle sudden bursts, and a throttled write is retried
+//! by the SDK, adding more load. Bulk mutations take a permit from one process-wide
+//! semaphore per write, so however many run at once, at most `bulk_write_concurrency`
+//! of their writes are in flight.
+
+use std::{ env, sync::{ Arc, OnceLock } };
+
+use tokio::sync::{ OwnedSemaphorePermit, Semaphore };
+use tracing::warn;
+
+/// Writes in flight at once when `BULK_WRITE_CONCURRENCY` is unset
+const DEFAULT_BULK_WRITE_CONCURRENCY: usize = 10;
+
+static BULK_WRITE_LIMITER: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+/// Gets the most bulk writes allowed in flight at once
+///
+/// Read from the `BULK_WRITE_CONCURRENCY` env var, falling back to
+/// `DEFAULT_BULK_WRITE_CONCURRENCY` if it is unset or not a positive number.
+pub fn bulk_write_concurrency() -> usize {
+    match env::var("BULK_WRITE_CONCURRENCY") {
+        Ok(value) =>
+            match value.trim().parse::<usize>() {
+                Ok(limit) if limit > 0 => limit,
+                _ => {
+                    warn!(
+                        "BULK_WRITE_CONCURRENCY {:?} is not a positive number, using {}",
+                        value,
+                        DEFAULT_BULK_WRITE_CONCURRENCY
+                    );
+                    DEFAULT_BULK_WRITE_CONCURRENCY
+                }
+            }
+        Err(_) => DEFAULT_BULK_WRITE_CONCURRENCY,
+    }
+}
+
+/// Waits for a bulk write slot, the slot is released when the permit is dropped
+///
+/// Hold the permit for the duration of one write request.
+pub async fn acquire_bulk_write_permit() -> OwnedSemaphorePermit {
+    let limiter = BULK_WRITE_LIMITER.get_or_init(|| {
+        Arc::new(Semaphore::new(bulk_write_concurrency()))
+    });
+
+    // the semaphore is never closed, so acquiring can't fail
+    Arc::clone(limiter).acquire_owned().await.expect("bulk write limiter is never closed")
+}
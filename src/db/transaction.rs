@@ -0,0 +1,38 @@
+//! Helpers for reading the outcome of `TransactWriteItems` requests.
+
+use aws_sdk_dynamodb::{
+    error::SdkError,
+    operation::transact_write_items::TransactWriteItemsError,
+};
+
+/// Gets the position of the first action whose condition cancelled a transaction
+///
+/// DynamoDB reports one cancellation reason per action, in the order the actions were
+/// sent, so the position tells which action's condition failed.
+///
+/// # Returns
+///
+/// 'some' index into the transaction's actions if a condition failed, 'none' otherwise
+pub fn failed_condition_index<R>(error: &SdkError<TransactWriteItemsError, R>) -> Option<usize> {
+    let canceled = match error.as_service_error() {
+        Some(TransactWriteItemsError::TransactionCanceledException(e)) => e,
+        _ => {
+            return None;
+        }
+    };
+
+    canceled
+        .cancellation_reasons()
+        .iter()
+        .position(|reason| reason.code() == Some("ConditionalCheckFailed"))
+}
+
+/// Whether a transaction was cancelled because the condition of its first action failed
+pub fn first_condition_failed<R>(error: &SdkError<TransactWriteItemsError, R>) -> bool {
+    failed_condition_index(error) == Some(0)
+}
+
+/// Whether a transaction was cancelled because the condition of any of its actions failed
+pub fn any_condition_failed<R>(error: &SdkError<TransactWriteItemsError, R>) -> bool {
+    failed_condition_index(error).is_some()
+}
@@ -0,0 +1,376 @@
+//! Builder for DynamoDB `UpdateExpression`s.
+//!
+//! Collects `SET`, `REMOVE` and `ADD` clauses and the placeholder maps they need,
+//! so update mutations don't assemble expression strings by hand. Every attribute
+//! name goes through an `ExpressionAttributeNames` placeholder, which keeps
+//! reserved words such as `name` or `role` from breaking the expression, and
+//! every value through an `ExpressionAttributeValues` placeholder.
+//!
+//! Attribute paths may be nested with dots, e.g. `address.unit`; each segment gets
+//! its own placeholder. Attribute names containing a dot are therefore not supported.
+//!
+//! ```text
+//! UpdateBuilder::new()
+//!     .set("name", AttributeValue::S(name))
+//!     .remove("address.unit")
+//!     .set_if_not_exists("created_at", AttributeValue::S(now))
+//!     .build()
+//! // SET #a0 = :v0, #a3 = if_not_exists(#a3, :v1) REMOVE #a1.#a2
+//! ```
+//!
+//! Single-item updates that return the updated record go through
+//! `UpdateExpression::send_returning`, which maps the outcome onto AppErrors the same way
+//! for every record type.
+
+use std::collections::HashMap;
+
+use async_graphql::MaybeUndefined;
+use aws_sdk_dynamodb::{
+    operation::update_item::builders::UpdateItemFluentBuilder,
+    types::{ AttributeValue, ReturnValue },
+};
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{ db::item_size::{ is_item_too_large, record_too_large }, error::AppError };
+
+/// Change to make to an optional attribute
+///
+/// # Variants
+///
+/// * `Unchanged` - leave the attribute as it is
+/// * `Set` - set the attribute to a value
+/// * `Clear` - remove the attribute from the item
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FieldUpdate<T> {
+    #[default]
+    Unchanged,
+    Set(T),
+    Clear,
+}
+
+/// Maps a GraphQL input field onto an update: omitted leaves it, null clears it
+impl<T> From<MaybeUndefined<T>> for FieldUpdate<T> {
+    fn from(value: MaybeUndefined<T>) -> Self {
+        match value {
+            MaybeUndefined::Undefined => FieldUpdate::Unchanged,
+            MaybeUndefined::Null => FieldUpdate::Clear,
+            MaybeUndefined::Value(value) => FieldUpdate::Set(value),
+        }
+    }
+}
+
+/// Maps a plain optional input onto an update: `None` leaves it, there is no way to clear
+impl<T> From<Option<T>> for FieldUpdate<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => FieldUpdate::Set(value),
+            None => FieldUpdate::Unchanged,
+        }
+    }
+}
+
+/// A finished update expression with its placeholder maps
+///
+/// # Fields
+///
+/// * `expression` - value for `update_expression`
+/// * `names` - value for `expression_attribute_names`
+/// * `values` - value for `expression_attribute_values`, 'none' when the expression only
+///   removes attributes, since DynamoDB rejects an empty map
+#[derive(Debug)]
+pub struct UpdateExpression {
+    pub expression: String,
+    pub names: HashMap<String, String>,
+    pub values: Option<HashMap<String, AttributeValue>>,
+}
+
+impl UpdateExpression {
+    /// Applies the update to one item and reads the updated item back
+    ///
+    /// The placeholders are added to those already on the request, so a condition can
+    /// bring its own values, e.g. `:pantry_id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - update_item request with the table, key and condition already set
+    /// * `record` - what the item is, e.g. "pantry", used in error messages
+    /// * `from_item` - parses the updated item, e.g. `Pantry::from_item`
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing 'some' updated record, 'none' if the condition failed
+    ///
+    /// # Errors
+    ///
+    /// Returns Validation Error (400) App error variant if the item would exceed the size limit
+    ///
+    /// Returns Database Error (500) App error variant if the update fails or the updated item
+    /// can't be parsed
+    pub async fn send_returning<T>(
+        self,
+        request: UpdateItemFluentBuilder,
+        record: &str,
+        from_item: impl FnOnce(&HashMap<String, AttributeValue>) -> Option<T>
+    ) -> Result<Option<T>, AppError> {
+        let mut request = request
+            .update_expression(self.expression)
+            .return_values(ReturnValue::AllNew);
+        for (placeholder, name) in self.names {
+            request = request.expression_attribute_names(placeholder, name);
+        }
+        for (placeholder, value) in self.values.into_iter().flatten() {
+            request = request.expression_attribute_values(placeholder, value);
+        }
+
+        match request.send().await {
+            Ok(output) =>
+                output.attributes
+                    .as_ref()
+                    .and_then(from_item)
+                    .map(Some)
+                    .ok_or_else(|| {
+                        AppError::DatabaseError(
+                            format!("Updated {} could not be read back", record)
+                        )
+                    }),
+            Err(e) if
+                e
+                    .as_service_error()
+                    .is_some_and(|e| e.is_conditional_check_failed_exception())
+            => Ok(None),
+            Err(e) if is_item_too_large(&e) => Err(record_too_large(record)),
+            Err(e) => {
+                warn!("Failed to update {}: {:?}", record, e);
+                Err(AppError::DatabaseError(format!("Failed to update {}", record)))
+            }
+        }
+    }
+}
+
+/// Accumulates the clauses of an update expression
+#[derive(Debug, Default)]
+pub struct UpdateBuilder {
+    sets: Vec<String>,
+    removes: Vec<String>,
+    adds: Vec<String>,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl UpdateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the placeholder for one attribute name, reusing it if the name was seen before
+    fn name_segment(&mut self, segment: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, name)| *name == segment) {
+            return placeholder.clone();
+        }
+
+        let placeholder = format!("#a{}", self.names.len());
+        self.names.insert(placeholder.clone(), segment.to_string());
+        placeholder
+    }
+
+    /// Gets the placeholder path for a possibly nested attribute path
+    fn name(&mut self, path: &str) -> String {
+        path.split('.')
+            .map(|segment| self.name_segment(segment))
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+
+    /// Registers a value and returns its placeholder
+    fn value(&mut self, value: AttributeValue) -> String {
+        let placeholder = format!(":v{}", self.values.len());
+        self.values.insert(placeholder.clone(), value);
+        placeholder
+    }
+
+    /// Sets an attribute to a value
+    pub fn set(mut self, attribute: &str, value: AttributeValue) -> Self {
+        let name = self.name(attribute);
+        let value = self.value(value);
+        self.sets.push(format!("{} = {}", name, value));
+        self
+    }
+
+    /// Sets an attribute only if the item doesn't have it yet, e.g. `created_at`
+    pub fn set_if_not_exists(mut self, attribute: &str, value: AttributeValue) -> Self {
+        let name = self.name(attribute);
+        let value = self.value(value);
+        self.sets.push(format!("{} = if_not_exists({}, {})", name, name, value));
+        self
+    }
+
+    /// Removes an attribute from the item
+    pub fn remove(mut self, attribute: &str) -> Self {
+        let name = self.name(attribute);
+        self.removes.push(name);
+        self
+    }
+
+    /// Applies a three-way change to an optional attribute
+    ///
+    /// # Arguments
+    ///
+    /// * `attribute` - name of the attribute
+    /// * `update` - whether to leave, set or remove the attribute
+    /// * `to_value` - converts the new value into an AttributeValue when setting
+    pub fn field<T>(
+        self,
+        attribute: &str,
+        update: FieldUpdate<T>,
+        to_value: impl FnOnce(T) -> AttributeValue
+    ) -> Self {
+        match update {
+            FieldUpdate::Unchanged => self,
+            FieldUpdate::Set(value) => self.set(attribute, to_value(value)),
+            FieldUpdate::Clear => self.remove(attribute),
+        }
+    }
+
+    /// Adds to a number attribute, or adds elements to a set attribute
+    ///
+    /// A missing attribute is treated as 0 or the empty set. Applied atomically by
+    /// DynamoDB, so concurrent adds never lose an increment.
+    pub fn add(mut self, attribute: &str, value: AttributeValue) -> Self {
+        let name = self.name(attribute);
+        let value = self.value(value);
+        self.adds.push(format!("{} {}", name, value));
+        self
+    }
+
+    /// Marks the item as modified by setting `updated_at`
+    ///
+    /// Every update of an existing item must call this, the update counterpart of the models' `touch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - the current time, from `schema::context::now` in resolvers
+    pub fn touch(self, now: DateTime<Utc>) -> Self {
+        self.set("updated_at", AttributeValue::S(now.to_string()))
+    }
+
+    /// Whether no clause has been added
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty() && self.removes.is_empty() && self.adds.is_empty()
+    }
+
+    /// Assembles the update expression
+    ///
+    /// # Returns
+    ///
+    /// 'some' UpdateExpression, 'none' if no clause was added
+    pub fn build(self) -> Option<UpdateExpression> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut clauses = Vec::new();
+        if !self.sets.is_empty() {
+            clauses.push(format!("SET {}", self.sets.join(", ")));
+        }
+        if !self.removes.is_empty() {
+            clauses.push(format!("REMOVE {}", self.removes.join(", ")));
+        }
+        if !self.adds.is_empty() {
+            clauses.push(format!("ADD {}", self.adds.join(", ")));
+        }
+
+        Some(UpdateExpression {
+            expression: clauses.join(" "),
+            names: self.names,
+            values: if self.values.is_empty() { None } else { Some(self.values) },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(value: &str) -> AttributeValue {
+        AttributeValue::S(value.to_string())
+    }
+
+    #[test]
+    fn builds_nothing_without_clauses() {
+        assert!(UpdateBuilder::new().is_empty());
+        assert!(UpdateBuilder::new().build().is_none());
+    }
+
+    #[test]
+    fn orders_clauses_and_numbers_placeholders() {
+        let update = UpdateBuilder::new()
+            .set("name", s("Food Shelf"))
+            .remove("address.unit")
+            .set_if_not_exists("created_at", s("2024-01-01"))
+            .add("visits", AttributeValue::N("1".to_string()))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            update.expression,
+            "SET #a0 = :v0, #a3 = if_not_exists(#a3, :v1) REMOVE #a1.#a2 ADD #a4 :v2"
+        );
+        assert_eq!(update.names["#a0"], "name");
+        assert_eq!(update.names["#a1"], "address");
+        assert_eq!(update.names["#a2"], "unit");
+        assert_eq!(update.values.as_ref().unwrap()[":v1"], s("2024-01-01"));
+    }
+
+    #[test]
+    fn reuses_the_placeholder_of_a_repeated_name() {
+        let update = UpdateBuilder::new()
+            .set("address.city", s("Madison"))
+            .set("address.zip", s("53703"))
+            .build()
+            .unwrap();
+
+        assert_eq!(update.expression, "SET #a0.#a1 = :v0, #a0.#a2 = :v1");
+        assert_eq!(update.names.len(), 3);
+    }
+
+    #[test]
+    fn leaves_values_out_of_remove_only_updates() {
+        let update = UpdateBuilder::new().remove("image_url").build().unwrap();
+
+        assert_eq!(update.expression, "REMOVE #a0");
+        assert!(update.values.is_none());
+    }
+
+    #[test]
+    fn touch_sets_updated_at() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let update = UpdateBuilder::new().touch(now).build().unwrap();
+
+        assert_eq!(update.names["#a0"], "updated_at");
+        assert_eq!(update.values.unwrap()[":v0"], s(&now.to_string()));
+    }
+
+    #[test]
+    fn maps_graphql_input_onto_field_updates() {
+        assert_eq!(FieldUpdate::from(MaybeUndefined::<i32>::Undefined), FieldUpdate::Unchanged);
+        assert_eq!(FieldUpdate::from(MaybeUndefined::<i32>::Null), FieldUpdate::Clear);
+        assert_eq!(FieldUpdate::from(MaybeUndefined::Value(3)), FieldUpdate::Set(3));
+        assert_eq!(FieldUpdate::from(None::<i32>), FieldUpdate::Unchanged);
+        assert_eq!(FieldUpdate::from(Some(3)), FieldUpdate::Set(3));
+    }
+
+    #[test]
+    fn field_sets_clears_or_skips() {
+        let update = UpdateBuilder::new()
+            .field("first_name", FieldUpdate::Set("Ana".to_string()), AttributeValue::S)
+            .field("last_name", FieldUpdate::Unchanged, AttributeValue::S)
+            .field("pantry_id", FieldUpdate::Clear, AttributeValue::S)
+            .build()
+            .unwrap();
+
+        assert_eq!(update.expression, "SET #a0 = :v0 REMOVE #a1");
+        assert_eq!(update.names["#a1"], "pantry_id");
+        assert!(!update.names.values().any(|name| name == "last_name"));
+    }
+}
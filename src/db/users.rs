@@ -0,0 +1,361 @@
+//! Reads and writes against the Users table shared by several resolvers.
+
+use aws_sdk_dynamodb::{
+    operation::scan::builders::ScanFluentBuilder,
+    types::{ AttributeValue, Delete, Put, TransactWriteItem },
+    Client,
+};
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{
+    db::{ projection::projection_of, transaction::first_condition_failed },
+    error::AppError,
+    models::{
+        normalize::normalize_email,
+        user::{
+            email_owner_id,
+            is_active_attribute,
+            token_version_attribute,
+            User,
+            EMAIL_OWNER_PREFIX,
+        },
+    },
+};
+
+/// Filter expression that keeps email ownership items out of scans of the Users table
+pub const NOT_EMAIL_OWNER_FILTER: &str = "NOT begins_with(id, :email_owner_prefix)";
+
+/// Adds `NOT_EMAIL_OWNER_FILTER` to a scan of the Users table
+///
+/// Filters are applied after the read, so a page may hold fewer items than its limit
+pub fn exclude_email_owners(scan: ScanFluentBuilder) -> ScanFluentBuilder {
+    scan
+        .filter_expression(NOT_EMAIL_OWNER_FILTER)
+        .expression_attribute_values(
+            ":email_owner_prefix",
+            AttributeValue::S(EMAIL_OWNER_PREFIX.to_string())
+        )
+}
+
+/// Looks up a user by email address through the EmailIndex GSI
+///
+/// The index only projects the user's id, so the user itself is read from the base table.
+/// The email is normalized first, so a space-padded or differently cased email still matches.
+/// Users saved before emails were normalized may still have a mixed case email, see
+/// `db::integrity::Backfill::NormalizedEmails`, so on a miss the email is looked up again
+/// as given, only trimmed.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `email` - email address to look up
+///
+/// # Returns
+///
+/// 'some' User if one exists with that email, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the query fails
+pub async fn find_user_by_email(client: &Client, email: &str) -> Result<Option<User>, AppError> {
+    let normalized = normalize_email(email);
+
+    let mut id = find_user_id_by_email(client, &normalized).await?;
+    if id.is_none() && email.trim() != normalized {
+        id = find_user_id_by_email(client, email.trim()).await?;
+    }
+
+    let id = match id {
+        Some(id) => id,
+        None => {
+            return Ok(None);
+        }
+    };
+
+    let response = client
+        .get_item()
+        .table_name("Users")
+        .key("id", id)
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get user by id after email lookup: {:?}", e);
+            AppError::DatabaseError("Failed to get user by email from db".to_string())
+        })?;
+
+    Ok(response.item.as_ref().and_then(User::from_item))
+}
+
+/// Gets the id of the user with exactly this stored email through the EmailIndex GSI
+async fn find_user_id_by_email(
+    client: &Client,
+    email: &str
+) -> Result<Option<AttributeValue>, AppError> {
+    let response = client
+        .query()
+        .table_name("Users")
+        .index_name("EmailIndex")
+        .key_condition_expression("email = :email")
+        .expression_attribute_values(":email", AttributeValue::S(email.to_string()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to query user by email: {:?}", e);
+            AppError::DatabaseError("Failed to get user by email from db".to_string())
+        })?;
+
+    Ok(response.items().first().and_then(|item| item.get("id")).cloned())
+}
+
+/// Looks up a user by id
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `id` - ID of the user
+///
+/// # Returns
+///
+/// 'some' User if one exists with that id, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the read fails
+pub async fn get_user(client: &Client, id: &str) -> Result<Option<User>, AppError> {
+    let response = client
+        .get_item()
+        .table_name("Users")
+        .key("id", AttributeValue::S(id.to_string()))
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get user by id: {:?}", e);
+            AppError::DatabaseError("Failed to get user by id from db".to_string())
+        })?;
+
+    Ok(response.item.as_ref().and_then(User::from_item))
+}
+
+/// What a user's tokens are checked against on every request
+///
+/// # Fields
+///
+/// * `password_changed_at` - when the password was last changed, 'none' if it never was
+/// * `token_version` - the user's current token version, 0 until sessions are revoked
+/// * `deleted_at` - when the user was soft deleted, e.g. merged into another user
+/// * `is_active` - false while an Admin has deactivated the user
+#[derive(Clone, Debug)]
+pub struct TokenState {
+    pub password_changed_at: Option<DateTime<Utc>>,
+    pub token_version: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub is_active: bool,
+}
+
+/// Gets what a user's tokens are checked against
+///
+/// Reads only `password_changed_at`, `token_version`, `deleted_at` and `is_active`, for
+/// checking tokens on every request.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `id` - ID of the user
+///
+/// # Returns
+///
+/// 'some' TokenState if a user has that id, 'none' otherwise
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the read fails
+pub async fn get_token_state(client: &Client, id: &str) -> Result<Option<TokenState>, AppError> {
+    let response = client
+        .get_item()
+        .table_name("Users")
+        .key("id", AttributeValue::S(id.to_string()))
+        .projection_expression("password_changed_at, token_version, deleted_at, is_active")
+        .send().await
+        .map_err(|e| {
+            warn!("Failed to get token state: {:?}", e);
+            AppError::DatabaseError("Failed to get user from db".to_string())
+        })?;
+
+    Ok(
+        response.item.as_ref().map(|item| TokenState {
+            password_changed_at: item
+                .get("password_changed_at")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+            token_version: token_version_attribute(item),
+            deleted_at: item
+                .get("deleted_at")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+            is_active: is_active_attribute(item),
+        })
+    )
+}
+
+/// Saves a new user together with the item claiming its email
+///
+/// Both writes happen in one transaction, so a user is never saved without owning its
+/// email and two users can't claim the same email, even when registering concurrently.
+/// Users registered before claims existed may not own a claim yet, see
+/// `db::integrity::Backfill::EmailClaims`, so the email is also looked up first.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `user` - user to save
+///
+/// # Errors
+///
+/// Returns Conflict Error (409) App error variant if the email is already claimed or used
+///
+/// Returns Database Error (500) App error variant if the lookup or the transaction fails
+pub async fn create_user(client: &Client, user: &User) -> Result<(), AppError> {
+    if find_user_by_email(client, &user.email).await?.is_some() {
+        return Err(AppError::ConflictError("A user with that email already exists".to_string()));
+    }
+
+    let owner = Put::builder()
+        .table_name("Users")
+        .item("id", AttributeValue::S(email_owner_id(&user.email)))
+        .item("user_id", AttributeValue::S(user.id.clone()))
+        .condition_expression("attribute_not_exists(id)")
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build email claim: {}", e)))?;
+
+    let item = Put::builder()
+        .table_name("Users")
+        .set_item(Some(user.to_item()))
+        .condition_expression("attribute_not_exists(id)")
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build user put: {}", e)))?;
+
+    client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(owner).build())
+        .transact_items(TransactWriteItem::builder().put(item).build())
+        .send().await
+        .map_err(|e| {
+            if first_condition_failed(&e) {
+                return AppError::ConflictError("A user with that email already exists".to_string());
+            }
+            warn!("Failed to create user: {:?}", e);
+            AppError::DatabaseError("Failed to create user".to_string())
+        })?;
+
+    Ok(())
+}
+
+/// Deletes a user and releases the email it claimed
+///
+/// The email claim is only removed while it still points at this user. A claim held by
+/// another user, left by duplicates registered before claims existed, is kept and only
+/// the user is deleted.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `user` - user to delete
+///
+/// # Errors
+///
+/// Returns Database Error (500) App error variant if the transaction fails
+pub async fn delete_user(client: &Client, user: &User) -> Result<(), AppError> {
+    let owner = Delete::builder()
+        .table_name("Users")
+        .key("id", AttributeValue::S(email_owner_id(&user.email)))
+        .condition_expression("attribute_not_exists(id) OR user_id = :user_id")
+        .expression_attribute_values(":user_id", AttributeValue::S(user.id.clone()))
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build email release: {}", e)))?;
+
+    let item = Delete::builder()
+        .table_name("Users")
+        .key("id", AttributeValue::S(user.id.clone()))
+        .build()
+        .map_err(|e| AppError::DatabaseError(format!("Failed to build user delete: {}", e)))?;
+
+    let result = client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().delete(owner).build())
+        .transact_items(TransactWriteItem::builder().delete(item).build())
+        .send().await;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if first_condition_failed(&e) => {
+            client
+                .delete_item()
+                .table_name("Users")
+                .key("id", AttributeValue::S(user.id.clone()))
+                .send().await
+                .map_err(|e| {
+                    warn!("Failed to delete user: {:?}", e);
+                    AppError::DatabaseError("Failed to delete user".to_string())
+                })?;
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Failed to delete user: {:?}", e);
+            Err(AppError::DatabaseError("Failed to delete user".to_string()))
+        }
+    }
+}
+
+/// Reads every user in the Users table, following pagination to the end
+///
+/// Only `attributes` are read, so the users are partial, see `User::from_projected_item`.
+/// Every item is still billed in full and all users are held in memory, so `max_users`
+/// bounds both the read and the memory used.
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client
+/// * `attributes` - attributes to read, must include `id`
+/// * `max_users` - most users to read
+///
+/// # Errors
+///
+/// Returns Validation Error (400) App error variant if the table holds more than `max_users` users
+///
+/// Returns Database Error (500) App error variant if any scan page fails
+pub async fn scan_users(
+    client: &Client,
+    attributes: &[&str],
+    max_users: usize
+) -> Result<Vec<User>, AppError> {
+    let projection = projection_of(attributes);
+    let mut users = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let response = exclude_email_owners(client.scan().table_name("Users"))
+            .projection_expression(projection.expression.clone())
+            .set_expression_attribute_names(Some(projection.names.clone()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan users: {:?}", e);
+                AppError::DatabaseError("Failed to scan Users".to_string())
+            })?;
+
+        users.extend(response.items().iter().filter_map(User::from_projected_item));
+
+        if users.len() > max_users {
+            return Err(
+                AppError::ValidationError(
+                    format!("There are more than {} users, too many to read at once", max_users)
+                )
+            );
+        }
+
+        exclusive_start_key = response.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(users)
+}
@@ -1,10 +1,11 @@
 use async_graphql::{ Error as GraphQLError, ErrorExtensions };
 // use aws_sdk_dynamodb::error::SdkError;
-use axum::{ http::StatusCode, response::{ IntoResponse, Response } };
+use axum::{ http::StatusCode, response::{ IntoResponse, Response }, Json };
+use serde_json::{ json, Value };
 use std::env::VarError;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum AppError {
     // Env errors
     #[error("Environment variable error: {0}")] EnvError(#[from] VarError),
@@ -24,6 +25,9 @@ pub enum AppError {
     // Not found errors
     #[error("Not found: {0}")] NotFound(String),
 
+    // Conflicts with existing data
+    #[error("Conflict: {0}")] ConflictError(String),
+
     // External service errors
     #[error("External service error: {0}")] ExternalServiceError(String),
 
@@ -32,68 +36,130 @@ pub enum AppError {
 }
 
 impl AppError {
-    pub fn to_graphql_error(&self) -> GraphQLError {
+    /// HTTP status used when this error is returned outside of a GraphQL response
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::EnvError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::ConflictError(_) => StatusCode::CONFLICT,
+            Self::ExternalServiceError(_) => StatusCode::BAD_GATEWAY,
+            Self::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine readable code, matches the `code` extension of the GraphQL error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EnvError(_) => "ENV_ERROR",
+            Self::ValidationError(_) => "VALIDATION_ERROR",
+            Self::NotFound(_) => "NOT_FOUND",
+            Self::ConflictError(_) => "CONFLICT",
+            Self::Unauthorized(_) => "UNAUTHORIZED",
+            Self::Forbidden(_) => "FORBIDDEN",
+            | Self::DatabaseError(_)
+            | Self::ExternalServiceError(_)
+            | Self::InternalServerError(_) => "INTERNAL_SERVER_ERROR",
+        }
+    }
+
+    /// Message shown to clients, without the variant prefix of `Display`
+    pub fn message(&self) -> String {
         match self {
-            AppError::EnvError(msg) => {
-                GraphQLError::new(msg.clone().to_string()).extend_with(|_, e| {
-                    e.set("code", "ENV_ERROR");
-                    e.set("status", 404);
-                })
-            }
-            AppError::ValidationError(msg) => {
-                GraphQLError::new(msg.clone()).extend_with(|_, e| {
-                    e.set("code", "VALIDATION_ERROR");
-                    e.set("status", 400);
-                })
-            }
-            AppError::NotFound(msg) => {
-                GraphQLError::new(msg.clone()).extend_with(|_, e| {
-                    e.set("code", "NOT_FOUND");
-                    e.set("status", 404);
-                })
-            }
-            AppError::Unauthorized(msg) => {
-                GraphQLError::new(msg.clone()).extend_with(|_, e| {
-                    e.set("code", "UNAUTHORIZED");
-                    e.set("status", 401);
-                })
-            }
-            AppError::Forbidden(msg) => {
-                GraphQLError::new(msg.clone()).extend_with(|_, e| {
-                    e.set("code", "FORBIDDEN");
-                    e.set("status", 403);
-                })
-            }
-            | AppError::DatabaseError(msg)
-            | AppError::ExternalServiceError(msg)
-            | AppError::InternalServerError(msg) => {
-                GraphQLError::new(msg.clone()).extend_with(|_, e| {
-                    e.set("code", "INTERNAL_SERVER_ERROR");
-                    e.set("status", 500);
-                })
-            }
+            Self::EnvError(e) => e.to_string(),
+            | Self::DatabaseError(msg)
+            | Self::Unauthorized(msg)
+            | Self::Forbidden(msg)
+            | Self::ValidationError(msg)
+            | Self::NotFound(msg)
+            | Self::ConflictError(msg)
+            | Self::ExternalServiceError(msg)
+            | Self::InternalServerError(msg) => msg.clone(),
         }
     }
+
+    /// JSON body for errors returned outside of a GraphQL response
+    ///
+    /// Mirrors the GraphQL error extensions:
+    /// `{ "error": { "code": "VALIDATION_ERROR", "message": "...", "status": 400 } }`
+    pub fn to_json(&self) -> Value {
+        json!({
+            "error": {
+                "code": self.code(),
+                "message": self.message(),
+                "status": self.status_code().as_u16(),
+            },
+        })
+    }
+
+    /// GraphQL error with the same `code` and `status` extensions as `to_json`
+    pub fn to_graphql_error(&self) -> GraphQLError {
+        let code = self.code();
+        let status = self.status_code().as_u16();
+
+        GraphQLError::new(self.message()).extend_with(|_, e| {
+            e.set("code", code);
+            e.set("status", status);
+        })
+    }
 }
 
 // Convert AppError to Axum Response for REST endpoints or middleware
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            Self::EnvError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.to_string()),
-            Self::DatabaseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            Self::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
-            Self::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
-            Self::ValidationError(msg) => (StatusCode::BAD_REQUEST, msg),
-            Self::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
-            Self::ExternalServiceError(msg) => (StatusCode::BAD_GATEWAY, msg),
-            Self::InternalServerError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-        };
-
-        // You could return JSON here instead of plain text if preferred
-        (status, message).into_response()
+        (self.status_code(), Json(self.to_json())).into_response()
     }
 }
 
 // Convenience type for results in your application
 pub type AppResult<T> = Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::Value as GraphQLValue;
+
+    /// Gets the message, code and status of the GraphQL error
+    fn graphql_shape(error: &AppError) -> (String, GraphQLValue, GraphQLValue) {
+        let error = error.to_graphql_error();
+        let extensions = error.extensions.expect("errors carry extensions");
+        let code = extensions.get("code").unwrap().clone();
+        let status = extensions.get("status").unwrap().clone();
+        (error.message, code, status)
+    }
+
+    #[test]
+    fn json_and_graphql_errors_share_code_message_and_status() {
+        let cases = [
+            (AppError::EnvError(VarError::NotPresent), "ENV_ERROR", 500),
+            (AppError::ValidationError("Bad zip".to_string()), "VALIDATION_ERROR", 400),
+            (AppError::NotFound("No pantry".to_string()), "NOT_FOUND", 404),
+            (AppError::ConflictError("Taken".to_string()), "CONFLICT", 409),
+            (AppError::Unauthorized("No token".to_string()), "UNAUTHORIZED", 401),
+            (AppError::Forbidden("Not yours".to_string()), "FORBIDDEN", 403),
+            (AppError::DatabaseError("Scan failed".to_string()), "INTERNAL_SERVER_ERROR", 500),
+        ];
+
+        for (error, code, status) in cases {
+            assert_eq!(
+                error.to_json(),
+                json!({ "error": { "code": code, "message": error.message(), "status": status } })
+            );
+            assert_eq!(
+                graphql_shape(&error),
+                (error.message(), GraphQLValue::from(code), GraphQLValue::from(status))
+            );
+        }
+    }
+
+    #[test]
+    fn messages_drop_the_variant_prefix() {
+        let error = AppError::NotFound("No pantry".to_string());
+
+        assert_eq!(error.to_string(), "Not found: No pantry");
+        assert_eq!(error.message(), "No pantry");
+    }
+}
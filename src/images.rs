@@ -0,0 +1,187 @@
+//! Direct-to-S3 uploads of pantry images.
+//!
+//! Clients never send image bytes through this service. They ask for a presigned
+//! PUT URL, upload the image to S3 with it, then save the image's public URL on
+//! the pantry with `setPantryImageUrl`.
+//!
+//! Uploads are enabled by setting `PANTRY_IMAGE_BUCKET`. Images are served from
+//! `PANTRY_IMAGE_PUBLIC_URL` when set, e.g. a CloudFront distribution in front of
+//! the bucket, and from the bucket's own S3 URL otherwise.
+
+use std::{ env, time::{ Duration, SystemTime } };
+
+use async_graphql::SimpleObject;
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::{ presigning::PresigningConfig, Client };
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{ db::region::region_provider, error::AppError };
+
+/// How long a presigned upload URL stays valid, in seconds
+pub const UPLOAD_URL_LIFETIME_SECS: u64 = 15 * 60;
+
+/// Content types accepted for pantry images
+pub const ALLOWED_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
+
+/// Where pantry images are uploaded to and served from
+#[derive(Clone, Debug)]
+pub struct ImageStore {
+    client: Client,
+    bucket: String,
+    public_base_url: String,
+}
+
+/// A presigned upload and where the image will be served from once uploaded
+///
+/// # Fields
+///
+/// * `upload_url` - URL to PUT the image to, with the same `Content-Type` it was presigned for
+/// * `public_url` - URL the image is served from after the upload, to save with `setPantryImageUrl`
+/// * `expires_at` - when `upload_url` stops being accepted
+#[derive(Debug, SimpleObject)]
+pub struct PresignedUpload {
+    pub upload_url: String,
+    pub public_url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Gets the S3 key a pantry's logo is stored under
+pub fn pantry_logo_key(pantry_id: &str) -> String {
+    format!("pantries/{}/logo", pantry_id)
+}
+
+impl ImageStore {
+    /// Creates the store from `PANTRY_IMAGE_BUCKET` and `PANTRY_IMAGE_PUBLIC_URL`
+    ///
+    /// # Returns
+    ///
+    /// 'some' ImageStore if `PANTRY_IMAGE_BUCKET` is set, 'none' when uploads are disabled
+    pub async fn from_env() -> Option<Self> {
+        let bucket = env::var("PANTRY_IMAGE_BUCKET").ok()?.trim().to_string();
+        if bucket.is_empty() {
+            return None;
+        }
+
+        let config = aws_config
+            ::from_env()
+            .behavior_version(BehaviorVersion::latest())
+            .region(region_provider())
+            .load().await;
+
+        let public_base_url = match env::var("PANTRY_IMAGE_PUBLIC_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().trim_end_matches('/').to_string(),
+            _ => {
+                let region = config
+                    .region()
+                    .map(|region| region.to_string())
+                    .unwrap_or_default();
+                format!("https://{}.s3.{}.amazonaws.com", bucket, region)
+            }
+        };
+
+        Some(Self {
+            client: Client::new(&config),
+            bucket,
+            public_base_url,
+        })
+    }
+
+    /// Presigns a PUT of a pantry's logo
+    ///
+    /// The upload replaces any logo uploaded before, since every pantry has one logo key.
+    ///
+    /// # Arguments
+    ///
+    /// * `pantry_id` - ID of the pantry the logo belongs to
+    /// * `content_type` - one of `ALLOWED_IMAGE_TYPES`, the upload must send the same type
+    /// * `now` - time the URL becomes valid
+    ///
+    /// # Errors
+    ///
+    /// Returns a ValidationError (400) App error variant if the content type isn't allowed
+    ///
+    /// Returns an External Service Error (502) App error variant if the URL can't be signed
+    pub async fn presign_logo_upload(
+        &self,
+        pantry_id: &str,
+        content_type: &str,
+        now: DateTime<Utc>
+    ) -> Result<PresignedUpload, AppError> {
+        let content_type = content_type.trim().to_lowercase();
+        if !ALLOWED_IMAGE_TYPES.contains(&content_type.as_str()) {
+            return Err(
+                AppError::ValidationError(
+                    format!("Image type must be one of {}", ALLOWED_IMAGE_TYPES.join(", "))
+                )
+            );
+        }
+
+        let lifetime = Duration::from_secs(UPLOAD_URL_LIFETIME_SECS);
+        let presigning = PresigningConfig::builder()
+            .start_time(SystemTime::from(now))
+            .expires_in(lifetime)
+            .build()
+            .map_err(|e| AppError::InternalServerError(format!("Invalid presigning config: {}", e)))?;
+
+        let key = pantry_logo_key(pantry_id);
+        let request = self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .content_type(content_type)
+            .presigned(presigning).await
+            .map_err(|e| {
+                warn!("Failed to presign image upload: {:?}", e);
+                AppError::ExternalServiceError("Failed to create image upload URL".to_string())
+            })?;
+
+        Ok(PresignedUpload {
+            upload_url: request.uri().to_string(),
+            public_url: format!("{}/{}", self.public_base_url, key),
+            expires_at: now + lifetime,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_s3::config::{ Credentials, Region };
+
+    use super::*;
+
+    // signs with made-up credentials, presigning never reaches S3
+    fn store() -> ImageStore {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("AKIDTEST", "secret", None, None, "test"))
+            .build();
+
+        ImageStore {
+            client: Client::from_conf(config),
+            bucket: "pantry-images".to_string(),
+            public_base_url: "https://images.example.com".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn presigns_the_logo_key_for_fifteen_minutes() {
+        let now = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+        let upload = store().presign_logo_upload("pantry-1", " Image/PNG ", now).await.unwrap();
+
+        assert!(upload.upload_url.contains("/pantries/pantry-1/logo?"), "{}", upload.upload_url);
+        assert!(upload.upload_url.contains("X-Amz-Date=20240501T120000Z"));
+        assert!(upload.upload_url.contains("X-Amz-Expires=900"));
+        assert_eq!(upload.public_url, "https://images.example.com/pantries/pantry-1/logo");
+        assert_eq!(upload.expires_at, now + Duration::from_secs(UPLOAD_URL_LIFETIME_SECS));
+    }
+
+    #[tokio::test]
+    async fn rejects_content_types_that_are_not_images() {
+        let result = store().presign_logo_upload("pantry-1", "text/html", Utc::now()).await;
+
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+    }
+}
@@ -0,0 +1,145 @@
+//! AWS Lambda entrypoint.
+//!
+//! Handles API Gateway proxy events (REST and HTTP API payloads) carrying a
+//! GraphQL request in the body, and readiness probes sent to a path ending in `/ready`.
+//! Only compiled with the `lambda` feature.
+
+use aws_sdk_dynamodb::Client;
+use axum::http::{ HeaderMap, HeaderName, HeaderValue, StatusCode };
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use lambda_runtime::{ service_fn, LambdaEvent };
+use serde_json::{ json, Value };
+use chrono::Utc;
+use tracing::warn;
+
+use crate::{
+    auth,
+    db::health::{ readiness, ReadinessStatus },
+    error::AppError,
+    locale::Locale,
+    schema::AppSchema,
+};
+
+/// Builds an API Gateway proxy response
+fn proxy_response(status: StatusCode, body: String) -> Value {
+    json!({
+        "statusCode": status.as_u16(),
+        "headers": { "content-type": "application/json" },
+        "body": body,
+    })
+}
+
+/// Copies the event's headers into a HeaderMap so the shared auth code can read them
+fn event_headers(event: &Value) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if let Some(map) = event.get("headers").and_then(|h| h.as_object()) {
+        for (name, value) in map {
+            let name = HeaderName::from_bytes(name.as_bytes());
+            let value = value.as_str().map(HeaderValue::from_str);
+            if let (Ok(name), Some(Ok(value))) = (name, value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    headers
+}
+
+/// Reads the raw request body, decoding it if API Gateway base64 encoded it
+fn event_body(event: &Value) -> Result<Vec<u8>, AppError> {
+    let body = event
+        .get("body")
+        .and_then(|b| b.as_str())
+        .unwrap_or_default();
+
+    let is_base64 = event
+        .get("isBase64Encoded")
+        .and_then(|b| b.as_bool())
+        .unwrap_or(false);
+
+    if is_base64 {
+        STANDARD.decode(body).map_err(|_|
+            AppError::ValidationError("Request body is not valid base64".to_string())
+        )
+    } else {
+        Ok(body.as_bytes().to_vec())
+    }
+}
+
+/// Whether the event is a readiness probe, `rawPath` for HTTP API payloads, `path` for REST
+fn is_readiness_probe(event: &Value) -> bool {
+    event
+        .get("rawPath")
+        .or_else(|| event.get("path"))
+        .and_then(|p| p.as_str())
+        .is_some_and(|path| path.trim_end_matches('/').ends_with("/ready"))
+}
+
+/// Answers a readiness probe, 503 only when the app is down
+async fn readiness_response(db_client: &Client) -> Value {
+    let readiness = readiness(db_client).await;
+    let status = match readiness.status {
+        ReadinessStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        ReadinessStatus::Ok | ReadinessStatus::Degraded => StatusCode::OK,
+    };
+
+    proxy_response(status, readiness.to_json().to_string())
+}
+
+/// Executes the GraphQL request carried by one API Gateway event
+async fn handle_event(
+    schema: &AppSchema,
+    db_client: &Client,
+    event: &Value
+) -> Result<String, AppError> {
+    let body = event_body(event)?;
+
+    let mut req = serde_json
+        ::from_slice::<async_graphql::Request>(&body)
+        .map_err(|e| AppError::ValidationError(format!("Invalid GraphQL request: {}", e)))?;
+
+    let headers = event_headers(event);
+    req = req.data(Locale::from_headers(&headers));
+
+    if let Some(claims) = auth::middleware::request_claims(&headers, db_client, Utc::now()).await? {
+        req = req.data(claims);
+    }
+
+    let response = schema.execute(req).await;
+
+    serde_json::to_string(&response).map_err(|e| AppError::InternalServerError(e.to_string()))
+}
+
+/// Runs the Lambda runtime loop until the runtime shuts the function down
+///
+/// # Arguments
+///
+/// * `schema` - the built GraphQL schema
+/// * `db_client` - DynamoDB client used for api key lookups
+///
+/// # Errors
+///
+/// Returns the runtime error if the Lambda runtime API can't be reached
+pub async fn run(schema: AppSchema, db_client: Client) -> Result<(), lambda_runtime::Error> {
+    lambda_runtime::run(
+        service_fn(|event: LambdaEvent<Value>| {
+            let schema = schema.clone();
+            let db_client = db_client.clone();
+            async move {
+                if is_readiness_probe(&event.payload) {
+                    return Ok::<Value, lambda_runtime::Error>(readiness_response(&db_client).await);
+                }
+
+                let response = match handle_event(&schema, &db_client, &event.payload).await {
+                    Ok(body) => proxy_response(StatusCode::OK, body),
+                    Err(e) => {
+                        warn!("Lambda request failed: {}", e);
+                        proxy_response(e.status_code(), e.to_json().to_string())
+                    }
+                };
+                Ok::<Value, lambda_runtime::Error>(response)
+            }
+        })
+    ).await
+}
@@ -0,0 +1,226 @@
+//! Language of user-facing error messages.
+//!
+//! Resolvers build their errors in English. The locale picked from the request's
+//! `Accept-Language` header is attached to the request data, and the
+//! `LocalizeErrors` extension translates Validation and Not Found messages found in
+//! `SPANISH_MESSAGES` before the response is sent. Messages missing from the catalog
+//! stay in English, as does everything for an unsupported language.
+
+use axum::http::{ header::ACCEPT_LANGUAGE, HeaderMap };
+
+/// Spanish translations of user-facing messages, keyed by the English message
+///
+/// A `{}` in both stands for one value that is carried over as is, e.g. a timezone name.
+/// Keys must match the messages resolvers build exactly, the tests fail when a key is no
+/// longer found in the source so a reworded message doesn't silently stay in English.
+const SPANISH_MESSAGES: &[(&str, &str)] = &[
+    ("No pantry found with that ID", "No se encontró ninguna despensa con ese ID"),
+    ("No user found with that ID", "No se encontró ningún usuario con ese ID"),
+    ("No user found with that email", "No se encontró ningún usuario con ese correo electrónico"),
+    ("No user found with the source ID", "No se encontró ningún usuario con el ID de origen"),
+    ("No user found with the target ID", "No se encontró ningún usuario con el ID de destino"),
+    ("Not found", "No encontrado"),
+    ("Query is empty", "La consulta está vacía"),
+    ("Invalid pagination cursor", "El cursor de paginación no es válido"),
+    ("New password can't be empty", "La nueva contraseña no puede estar vacía"),
+    ("start must not be after end", "start no puede ser posterior a end"),
+    ("radius_km must be greater than 0", "radius_km debe ser mayor que 0"),
+    (
+        "lat must be between -90 and 90 and lng between -180 and 180",
+        "lat debe estar entre -90 y 90 y lng entre -180 y 180",
+    ),
+    ("At least one grant is required", "Se requiere al menos un permiso"),
+    ("You can't deactivate yourself", "No puede desactivar su propia cuenta"),
+    ("Cannot merge a user into itself", "No se puede fusionar un usuario consigo mismo"),
+    (
+        "Cannot merge a user that has been deleted",
+        "No se puede fusionar un usuario que ha sido eliminado",
+    ),
+    (
+        "Only users with access to the pantry can be contact agents",
+        "Solo los usuarios con acceso a la despensa pueden ser agentes de contacto",
+    ),
+    (
+        "A booking URL can only be set on a pantry that requires appointments",
+        "Solo se puede indicar una URL de reservas en una despensa que requiere cita",
+    ),
+    (
+        "A closure reason or reopening time can only be set on a closed pantry",
+        "Solo se puede indicar un motivo o una hora de reapertura en una despensa cerrada",
+    ),
+    ("reopens_at must be in the future", "reopens_at debe estar en el futuro"),
+    ("{} is not a valid IANA timezone", "{} no es una zona horaria IANA válida"),
+    ("{} is not an ISO 639-1 language code", "{} no es un código de idioma ISO 639-1"),
+    ("{} needs both an open and a close time", "{} necesita una hora de apertura y de cierre"),
+    ("{} opens and closes at the same time", "{} abre y cierra a la misma hora"),
+    ("{} is marked closed but has opening times", "{} está marcado como cerrado pero tiene horario"),
+    ("{} has more than one holiday override", "{} tiene más de un horario festivo"),
+    ("No user found with id {}", "No se encontró ningún usuario con el id {}"),
+    ("No pantry found with id {}", "No se encontró ninguna despensa con el id {}"),
+    ("User {} is listed more than once", "El usuario {} aparece más de una vez"),
+    (
+        "At most {} pantries can be updated at once",
+        "Se pueden actualizar como máximo {} despensas a la vez",
+    ),
+    ("Image type must be one of {}", "El tipo de imagen debe ser uno de {}"),
+];
+
+/// Language user-facing messages are sent in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// Gets the locale for a language tag, e.g. `es-MX`, 'none' if it isn't supported
+    fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        match language.trim().to_ascii_lowercase().as_str() {
+            "en" => Some(Self::En),
+            "es" => Some(Self::Es),
+            _ => None,
+        }
+    }
+
+    /// Picks the supported locale the client prefers from an `Accept-Language` value
+    ///
+    /// Languages are ranked by their `q` weight, ties keep header order; `*` and
+    /// unsupported languages are skipped. Falls back to English.
+    pub fn from_accept_language(value: &str) -> Self {
+        let mut best: Option<(f32, Self)> = None;
+
+        for entry in value.split(',') {
+            let mut parts = entry.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+
+            let weight = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let locale = match Self::from_tag(tag) {
+                Some(locale) if weight > 0.0 => locale,
+                _ => {
+                    continue;
+                }
+            };
+
+            if best.is_none_or(|(best_weight, _)| weight > best_weight) {
+                best = Some((weight, locale));
+            }
+        }
+
+        best.map(|(_, locale)| locale).unwrap_or_default()
+    }
+
+    /// Picks the locale from the `Accept-Language` header of a request, English if it's missing
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Self::from_accept_language)
+            .unwrap_or_default()
+    }
+
+    /// Translates an English message into this locale
+    ///
+    /// # Returns
+    ///
+    /// 'some' translated message if the catalog has it, 'none' for English or unknown messages
+    pub fn translate(self, message: &str) -> Option<String> {
+        let catalog = match self {
+            Self::En => {
+                return None;
+            }
+            Self::Es => SPANISH_MESSAGES,
+        };
+
+        catalog.iter().find_map(|(english, translated)| {
+            match english.split_once("{}") {
+                None => (*english == message).then(|| translated.to_string()),
+                Some((prefix, suffix)) => {
+                    let value = message
+                        .strip_prefix(prefix)?
+                        .strip_suffix(suffix)
+                        .filter(|value| !value.is_empty())?;
+                    Some(translated.replacen("{}", value, 1))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ fs, path::Path };
+
+    use super::*;
+
+    /// Collects the source of every file under `dir` except this one
+    fn read_sources(dir: &Path, sources: &mut String) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                read_sources(&path, sources);
+            } else if path.extension().is_some_and(|ext| ext == "rs") && !path.ends_with("locale.rs") {
+                sources.push_str(&fs::read_to_string(&path).unwrap());
+            }
+        }
+    }
+
+    // the catalog is keyed by English messages, so a reworded message would silently stay English
+    #[test]
+    fn every_catalog_key_is_produced_by_the_source() {
+        let mut sources = String::new();
+        read_sources(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut sources);
+
+        let missing: Vec<&str> = SPANISH_MESSAGES.iter()
+            .map(|(english, _)| *english)
+            .filter(|english| !sources.contains(&format!("\"{}\"", english)))
+            .collect();
+
+        assert!(missing.is_empty(), "catalog messages no longer in the source: {:?}", missing);
+    }
+
+    #[test]
+    fn every_placeholder_is_in_both_messages() {
+        for (english, translated) in SPANISH_MESSAGES {
+            assert_eq!(english.matches("{}").count(), translated.matches("{}").count(), "{}", english);
+            assert!(english.matches("{}").count() <= 1, "{}", english);
+        }
+    }
+
+    #[test]
+    fn translates_exact_messages() {
+        assert_eq!(
+            Locale::Es.translate("No pantry found with that ID").as_deref(),
+            Some("No se encontró ninguna despensa con ese ID")
+        );
+    }
+
+    #[test]
+    fn carries_placeholder_values_over() {
+        assert_eq!(
+            Locale::Es.translate("Mars/Olympus is not a valid IANA timezone").as_deref(),
+            Some("Mars/Olympus no es una zona horaria IANA válida")
+        );
+        assert_eq!(Locale::Es.translate(" is not a valid IANA timezone"), None);
+    }
+
+    #[test]
+    fn leaves_english_and_unknown_messages() {
+        assert_eq!(Locale::En.translate("No pantry found with that ID"), None);
+        assert_eq!(Locale::Es.translate("Something nobody wrote"), None);
+    }
+
+    #[test]
+    fn picks_the_preferred_supported_language() {
+        assert_eq!(Locale::from_accept_language("es-MX,es;q=0.9,en;q=0.8"), Locale::Es);
+        assert_eq!(Locale::from_accept_language("fr, en;q=0.5, es;q=0.7"), Locale::Es);
+        assert_eq!(Locale::from_accept_language("es;q=0, en"), Locale::En);
+        assert_eq!(Locale::from_accept_language("*"), Locale::En);
+        assert_eq!(Locale::from_accept_language(""), Locale::En);
+    }
+}
@@ -1,128 +1,191 @@
 use aws_sdk_dynamodb::Client;
-use axum::{ extract::Extension, http::Method, middleware::from_fn, routing::get, Router };
-use error::AppError;
-use schema::{ MutationRoot, QueryRoot };
-use tower::builder::ServiceBuilder;
-use tower_http::{ compression::CompressionLayer, cors::{ Any, CorsLayer } };
+use tracing_subscriber::EnvFilter;
 
-use async_graphql_axum::{ GraphQLRequest, GraphQLResponse };
-
-use async_graphql::{ Context, EmptySubscription, Object, Schema, SimpleObject };
-
-use serde::Serialize;
-use tracing::{ warn, error };
-
-use std::sync::{ Arc, Mutex };
+#[cfg(not(any(feature = "local-server", feature = "lambda")))]
+compile_error!("enable at least one of the `local-server` or `lambda` features");
 
 mod schema;
 mod error;
 mod db;
 mod models;
 mod auth;
-
-// App state, replace with dynamo db connection
-#[derive(Clone)]
-pub struct AppState {
-    db_client: Client,
+mod clock;
+mod images;
+mod locale;
+mod webhook;
+#[cfg(feature = "local-server")]
+mod server;
+#[cfg(feature = "lambda")]
+mod lambda;
+
+/// Whether an env var is set to a non-empty value
+fn env_is_set(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| !value.trim().is_empty())
 }
 
-// Success http response struct
-#[derive(Debug, Serialize)]
-struct SuccessResponse {
-    pub body: String,
-}
-
-#[derive(Debug, Serialize)]
-struct FailureResponse {
-    pub body: String,
-}
-
-// Implement Display for FailureResponse
-impl std::fmt::Display for FailureResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.body)
-    }
+/// Gets the configuration the process resolved, as pairs of setting and value
+///
+/// Secrets are never included, only whether they are set.
+fn config_summary(db_client: &Client, log_filter: &str) -> Vec<(&'static str, String)> {
+    #[cfg(feature = "local-server")]
+    let playground = format!("{:?}", server::PlaygroundMode::from_env());
+    #[cfg(not(feature = "local-server"))]
+    let playground = "Off".to_string();
+
+    let region = db_client.config().region().map(|region| region.to_string());
+
+    vec![
+        ("region", region.unwrap_or_default()),
+        ("endpoint", std::env::var("DB_URL").unwrap_or_default()),
+        ("log_filter", log_filter.to_string()),
+        ("jwt_expiry_secs", auth::jwt::TOKEN_LIFETIME_SECS.to_string()),
+        ("jwt_secret_set", env_is_set("JWT_SECRET").to_string()),
+        ("password_pepper_set", env_is_set("PASSWORD_PEPPER").to_string()),
+        (
+            "aws_credentials_set",
+            (env_is_set("AWS_ACCESS_KEY_ID") && env_is_set("AWS_SECRET_ACCESS_KEY")).to_string(),
+        ),
+        ("default_pantry_timezone", models::timezone::default_timezone().to_string()),
+        ("debug_queries", schema::query::debug_queries_enabled().to_string()),
+        ("bulk_write_concurrency", db::throttle::bulk_write_concurrency().to_string()),
+        ("image_uploads", env_is_set("PANTRY_IMAGE_BUCKET").to_string()),
+        ("strict_item_parsing", db::parse::strict_item_parsing().to_string()),
+        ("max_page_size", db::pagination::max_page_size().to_string()),
+        ("operation_allowlist", env_is_set("GRAPHQL_ALLOWLIST_FILE").to_string()),
+        ("webhooks", (env_is_set("WEBHOOK_URL") && env_is_set("WEBHOOK_SECRET")).to_string()),
+        ("playground", playground),
+        ("local_server", cfg!(feature = "local-server").to_string()),
+        ("lambda", cfg!(feature = "lambda").to_string()),
+    ]
 }
 
-// Implement Error trait for FailureResponse
-impl std::error::Error for FailureResponse {}
-// Handler for graphql requests
-async fn graphql_handler(
-    Extension(schema): Extension<Schema<QueryRoot, MutationRoot, EmptySubscription>>,
-    req: GraphQLRequest
-) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
-}
+/// Logs `config_summary` as one line
+fn log_config_summary(db_client: &Client, log_filter: &str) {
+    let summary = config_summary(db_client, log_filter)
+        .into_iter()
+        .map(|(setting, value)| format!("{}={}", setting, value))
+        .collect::<Vec<String>>()
+        .join(" ");
 
-// Handler for graphql playground
-async fn graphql_playground() -> impl axum::response::IntoResponse {
-    axum::response::Html(async_graphql::http::GraphiQLSource::build().endpoint("/graphql").finish())
+    tracing::info!("Effective configuration: {}", summary);
 }
 
 #[tokio::main]
 async fn main() {
+    // Load .env before the subscriber so RUST_LOG set there is honored
+    dotenvy::dotenv().ok();
+
+    // Honor RUST_LOG (e.g. `uw_alice_food_pantry_emailer_lambda=debug,aws=warn`),
+    // falling back to info when it's unset, empty or unparsable
+    let rust_log = std::env::var("RUST_LOG").unwrap_or_default();
+    let (env_filter, filter_error) = match EnvFilter::try_new(&rust_log) {
+        Ok(filter) if !rust_log.trim().is_empty() => (filter, None),
+        Ok(_) => (EnvFilter::new("info"), None),
+        Err(e) => (EnvFilter::new("info"), Some(e)),
+    };
+
+    let log_filter = env_filter.to_string();
+
     // Initialize tracing with detailed configuration
     tracing_subscriber
         ::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_env_filter(env_filter)
         .with_target(false)
         .with_thread_ids(true)
         .with_line_number(true)
         .with_file(true)
         .init();
 
+    if let Some(e) = filter_error {
+        tracing::warn!("Ignoring unparsable RUST_LOG {:?}, using info: {}", rust_log, e);
+    }
+
+    // `--emit-schema <path>` writes the SDL and exits before touching the db
+    let args = std::env::args().collect::<Vec<String>>();
+    if let Some(flag_index) = args.iter().position(|arg| arg == "--emit-schema") {
+        let path = match args.get(flag_index + 1) {
+            Some(p) => p,
+            None => {
+                tracing::error!("--emit-schema requires an output path");
+                std::process::exit(2);
+            }
+        };
+        if let Err(e) = schema::emit_sdl(path) {
+            tracing::error!("Failed to write schema to {}: {}", path, e);
+            std::process::exit(1);
+        }
+        tracing::info!("Wrote GraphQL schema to {}", path);
+        return;
+    }
+
     tracing::info!("Starting up UW Pantry service");
 
     // Create db client
     let db_client = match db::local::setup_local_client().await {
         Ok(c) => c,
         Err(e) => {
-            eprintln!("Fatal error during startup: {}", e);
+            tracing::error!("Fatal error during startup: {}", e);
             std::process::exit(1);
         }
     };
 
-    db::init::ensure_tables_exist(&db_client).await.unwrap();
-
-    // Define app state
-    // Replace with db connection
-    // let state = Arc::new(AppState {
-    //     db_client,
-    // });
-
-    let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
-        .data(db_client.clone())
-        .finish();
-
-    // Configure cors
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers(Any);
-
-    // Initialize axum router and add route endpoints
-    let app = Router::new().route("/graphql", get(graphql_playground).post(graphql_handler));
-    // .layer(from_fn(auth::middleware::auth_middleware));
-
-    let app = app.layer(
-        ServiceBuilder::new()
-            .layer(CompressionLayer::new().gzip(true).deflate(true).br(true))
-            .layer(Extension(db_client))
-            .layer(Extension(schema))
-            .layer(cors)
-    );
-
-    // Run app with hyper, listen globally on port 3000
-    let listener = match tokio::net::TcpListener::bind(&"0.0.0.0:3000").await {
-        Ok(l) => l,
-        Err(e) => {
-            eprintln!("Fatal error during startup: {}", e);
+    log_config_summary(&db_client, &log_filter);
+
+    if let Err(e) = db::init::ensure_tables_exist(&db_client).await {
+        tracing::error!("Fatal error during startup: {}", e);
+        std::process::exit(1);
+    }
+
+    let image_store = images::ImageStore::from_env().await;
+
+    // events are recorded by the resolvers and delivered in the background, see `webhook`
+    let webhook = webhook::WebhookNotifier::from_env();
+    if let Some(notifier) = webhook.clone() {
+        tokio::spawn(webhook::run_outbox_poller(db_client.clone(), notifier));
+    }
+
+    let schema = schema::build_schema(&db_client, image_store, webhook);
+
+    // With both features enabled, the Lambda entrypoint is used only when running inside Lambda
+    #[cfg(feature = "lambda")]
+    if !cfg!(feature = "local-server") || std::env::var("AWS_LAMBDA_RUNTIME_API").is_ok() {
+        if let Err(e) = lambda::run(schema, db_client).await {
+            tracing::error!("Fatal error in lambda runtime: {}", e);
             std::process::exit(1);
         }
-    };
-    println!("Server running on http://localhost:3000");
-    axum::serve(listener, app).await.unwrap_or_else(|e| {
-        eprintln!("Fatal error during startup: {}", e);
-        std::process::exit(1);
-    });
+        return;
+    }
+
+    #[cfg(feature = "local-server")]
+    server::run(schema, db_client).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::{ BehaviorVersion, Region };
+
+    use super::*;
+
+    #[test]
+    fn config_summary_leaves_secrets_out() {
+        // the same secret as the jwt tests, so they can run in parallel
+        std::env::set_var("JWT_SECRET", "test-secret");
+        std::env::set_var("PASSWORD_PEPPER", "summary-pepper");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "summary-aws-secret");
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build();
+
+        let summary = config_summary(&Client::from_conf(config), "info");
+
+        for (setting, value) in &summary {
+            for secret in ["test-secret", "summary-pepper", "summary-aws-secret"] {
+                assert!(!value.contains(secret), "{} shows a secret", setting);
+            }
+        }
+        assert!(summary.contains(&("jwt_secret_set", "true".to_string())));
+        assert!(summary.contains(&("password_pepper_set", "true".to_string())));
+        assert!(summary.contains(&("region", "us-east-1".to_string())));
+    }
 }
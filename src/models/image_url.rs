@@ -0,0 +1,127 @@
+//! URLs shown for a pantry, e.g. its logo or its appointment booking page.
+//!
+//! Images are uploaded to S3 outside this service; a pantry only stores the URL
+//! the image is served from.
+
+use crate::error::AppError;
+
+/// Longest URL accepted, in bytes
+pub const MAX_IMAGE_URL_LEN: usize = 2048;
+
+/// Validates an absolute http(s) URL and returns it trimmed
+///
+/// The URL must be absolute http or https with a host, and may not contain whitespace
+/// or control characters.
+///
+/// # Arguments
+///
+/// * `url` - the URL as sent by the client
+/// * `label` - what the URL points at, starts the error messages, e.g. `Image`
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the URL is longer than
+/// `MAX_IMAGE_URL_LEN`, isn't http(s) or has no host
+pub fn parse_http_url(url: &str, label: &str) -> Result<String, AppError> {
+    let url = url.trim();
+
+    if url.len() > MAX_IMAGE_URL_LEN {
+        return Err(
+            AppError::ValidationError(
+                format!("{} URL can't be longer than {} characters", label, MAX_IMAGE_URL_LEN)
+            )
+        );
+    }
+
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(
+            AppError::ValidationError(
+                format!("{} URL can't contain spaces or control characters", label)
+            )
+        );
+    }
+
+    let rest = url
+        .split_once("://")
+        .filter(|(scheme, _)| {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        })
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| {
+            AppError::ValidationError(format!("{} URL must start with http:// or https://", label))
+        })?;
+
+    // the authority runs up to the path, query or fragment, minus any userinfo
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if host.is_empty() || host.starts_with(':') {
+        return Err(AppError::ValidationError(format!("{} URL must have a host", label)));
+    }
+
+    Ok(url.to_string())
+}
+
+/// Validates an image URL and returns it trimmed, see `parse_http_url`
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the URL is invalid
+pub fn parse_image_url(url: &str) -> Result<String, AppError> {
+    parse_http_url(url, "Image")
+}
+
+/// Validates the URL of a pantry's appointment booking page and returns it trimmed,
+/// see `parse_http_url`
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the URL is invalid
+pub fn parse_booking_url(url: &str) -> Result<String, AppError> {
+    parse_http_url(url, "Booking")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(result: Result<String, AppError>) -> String {
+        match result {
+            Err(AppError::ValidationError(message)) => message,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_http_and_https_urls() {
+        assert_eq!(
+            parse_image_url(" https://cdn.example.com/logo.png ").unwrap(),
+            "https://cdn.example.com/logo.png"
+        );
+        assert!(parse_booking_url("HTTP://example.com:8080?slot=1").is_ok());
+        assert!(parse_image_url("https://user@example.com/a.png").is_ok());
+    }
+
+    #[test]
+    fn rejects_other_schemes_and_missing_hosts() {
+        assert_eq!(
+            message(parse_image_url("javascript://alert(1)")),
+            "Image URL must start with http:// or https://"
+        );
+        assert!(parse_image_url("example.com/logo.png").is_err());
+        assert_eq!(message(parse_booking_url("https:///book")), "Booking URL must have a host");
+        assert!(parse_booking_url("https://:443/book").is_err());
+        assert!(parse_booking_url("https://user@/book").is_err());
+    }
+
+    #[test]
+    fn rejects_spaces_and_long_urls() {
+        assert!(parse_image_url("https://example.com/my logo.png").is_err());
+        assert!(parse_image_url("https://example.com/\u{7}").is_err());
+
+        let long = format!("https://example.com/{}", "a".repeat(MAX_IMAGE_URL_LEN));
+        assert_eq!(
+            message(parse_image_url(&long)),
+            format!("Image URL can't be longer than {} characters", MAX_IMAGE_URL_LEN)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Languages a pantry serves people in.
+//!
+//! Languages are ISO 639-1 two letter codes, e.g. `en` or `es`, stored lowercased on
+//! the pantry item as a string set. DynamoDB rejects empty sets, so a pantry without
+//! languages has no `languages` attribute.
+
+use std::collections::BTreeSet;
+
+use crate::error::AppError;
+
+/// Every ISO 639-1 language code
+const ISO_639_1_CODES: &[&str] = &[
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az",
+    "ba", "be", "bg", "bi", "bm", "bn", "bo", "br", "bs",
+    "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy",
+    "da", "de", "dv", "dz",
+    "ee", "el", "en", "eo", "es", "et", "eu",
+    "fa", "ff", "fi", "fj", "fo", "fr", "fy",
+    "ga", "gd", "gl", "gn", "gu", "gv",
+    "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz",
+    "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu",
+    "ja", "jv",
+    "ka", "kg", "ki", "kj", "kk", "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky",
+    "la", "lb", "lg", "li", "ln", "lo", "lt", "lu", "lv",
+    "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my",
+    "na", "nb", "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny",
+    "oc", "oj", "om", "or", "os",
+    "pa", "pi", "pl", "ps", "pt",
+    "qu",
+    "rm", "rn", "ro", "ru", "rw",
+    "sa", "sc", "sd", "se", "sg", "si", "sk", "sl", "sm", "sn", "so", "sq", "sr", "ss", "st",
+    "su", "sv", "sw",
+    "ta", "te", "tg", "th", "ti", "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty",
+    "ug", "uk", "ur", "uz",
+    "ve", "vi", "vo",
+    "wa", "wo",
+    "xh",
+    "yi", "yo",
+    "za", "zh", "zu",
+];
+
+/// Parses one ISO 639-1 language code, ignoring case and surrounding whitespace
+///
+/// # Returns
+///
+/// The code lowercased
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the code isn't an ISO 639-1 code
+pub fn parse_language(code: &str) -> Result<String, AppError> {
+    let code = code.trim().to_lowercase();
+
+    if !ISO_639_1_CODES.contains(&code.as_str()) {
+        return Err(
+            AppError::ValidationError(format!("{} is not an ISO 639-1 language code", code))
+        );
+    }
+
+    Ok(code)
+}
+
+/// Parses a list of language codes, see `parse_language`
+///
+/// # Returns
+///
+/// The codes lowercased, sorted and without duplicates
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if any code isn't an ISO 639-1 code
+pub fn parse_languages(codes: &[String]) -> Result<Vec<String>, AppError> {
+    let codes = codes
+        .iter()
+        .map(|code| parse_language(code))
+        .collect::<Result<BTreeSet<String>, AppError>>()?;
+
+    Ok(codes.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_language_codes() {
+        assert_eq!(parse_language(" ES ").unwrap(), "es");
+        assert!(matches!(parse_language("spanish"), Err(AppError::ValidationError(_))));
+        assert!(parse_language("xx").is_err());
+        assert!(parse_language("").is_err());
+    }
+
+    #[test]
+    fn sorts_and_dedupes_languages() {
+        let codes = ["so", "EN", "es", "en"].map(String::from);
+
+        assert_eq!(parse_languages(&codes).unwrap(), ["en", "es", "so"]);
+        assert!(parse_languages(&["en".to_string(), "zz".to_string()]).is_err());
+        assert!(parse_languages(&[]).unwrap().is_empty());
+    }
+}
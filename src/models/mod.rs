@@ -7,4 +7,19 @@ pub mod user;
 
 pub mod pantry;
 
-pub mod pantry_access;
\ No newline at end of file
+pub mod pantry_access;
+
+pub mod operating_hours;
+
+pub mod timezone;
+
+pub mod language;
+
+pub mod image_url;
+
+pub mod normalize;
+
+
+pub mod zipcode;
+
+pub mod outbox;
\ No newline at end of file
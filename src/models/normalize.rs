@@ -0,0 +1,50 @@
+//! Normalization of free text inputs before they are stored or looked up.
+//!
+//! Stray whitespace makes names display badly and makes exact-match lookups such as
+//! login by email miss. Mutations normalize their inputs with these before saving,
+//! and lookups normalize the value they search for, so both sides agree.
+
+/// Trims leading and trailing whitespace
+pub fn normalize_text(value: &str) -> String {
+    value.trim().to_string()
+}
+
+/// Trims a name and collapses internal runs of whitespace into single spaces
+///
+/// `"  Mary   Ann "` becomes `"Mary Ann"`
+pub fn normalize_name(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Trims and lowercases an email address
+///
+/// Emails are compared exactly, both on lookup and by the email ownership items,
+/// so every stored and searched email must go through this.
+pub fn normalize_email(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_trimmed() {
+        assert_eq!(normalize_text("  Bob  "), "Bob");
+        assert_eq!(normalize_text("Main  St"), "Main  St");
+        assert_eq!(normalize_text("   "), "");
+    }
+
+    #[test]
+    fn names_are_trimmed_and_collapsed() {
+        assert_eq!(normalize_name("  Bob  "), "Bob");
+        assert_eq!(normalize_name("  Mary   Ann "), "Mary Ann");
+        assert_eq!(normalize_name("Mary\tAnn\n"), "Mary Ann");
+    }
+
+    #[test]
+    fn emails_are_trimmed_and_lowercased() {
+        assert_eq!(normalize_email("  Bob@Example.COM "), "bob@example.com");
+        assert_eq!(normalize_email("bob@example.com"), "bob@example.com");
+    }
+}
@@ -0,0 +1,538 @@
+//! Pantry operating schedule.
+//!
+//! A schedule is a set of weekly hours, one entry per weekday, plus dated
+//! holiday overrides that replace the weekly entry for that date. A window whose
+//! `close` is earlier than its `open` runs overnight into the next day.
+//!
+//! Stored on the pantry item as a nested map:
+//!
+//! ```text
+//! hours: {
+//!     weekly: { monday: { closed: "false", open: "09:00", close: "17:00" }, ... },
+//!     holidays: { "2025-12-25": { closed: "true" }, ... }
+//! }
+//! ```
+
+use std::collections::{ HashMap, HashSet };
+
+use async_graphql::{ Enum, InputObject, SimpleObject };
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{ Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday };
+use serde::{ Deserialize, Serialize };
+
+use crate::error::AppError;
+
+/// Format times are stored in on the db item
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Format holiday dates are stored in on the db item
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Represents a day of the week
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Enum)]
+pub enum DayOfWeek {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl DayOfWeek {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            DayOfWeek::Monday => "monday",
+            DayOfWeek::Tuesday => "tuesday",
+            DayOfWeek::Wednesday => "wednesday",
+            DayOfWeek::Thursday => "thursday",
+            DayOfWeek::Friday => "friday",
+            DayOfWeek::Saturday => "saturday",
+            DayOfWeek::Sunday => "sunday",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Option<DayOfWeek> {
+        match s {
+            "monday" => Some(Self::Monday),
+            "tuesday" => Some(Self::Tuesday),
+            "wednesday" => Some(Self::Wednesday),
+            "thursday" => Some(Self::Thursday),
+            "friday" => Some(Self::Friday),
+            "saturday" => Some(Self::Saturday),
+            "sunday" => Some(Self::Sunday),
+            _ => None,
+        }
+    }
+}
+
+impl From<Weekday> for DayOfWeek {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Mon => DayOfWeek::Monday,
+            Weekday::Tue => DayOfWeek::Tuesday,
+            Weekday::Wed => DayOfWeek::Wednesday,
+            Weekday::Thu => DayOfWeek::Thursday,
+            Weekday::Fri => DayOfWeek::Friday,
+            Weekday::Sat => DayOfWeek::Saturday,
+            Weekday::Sun => DayOfWeek::Sunday,
+        }
+    }
+}
+
+/// Opening window of a pantry on one weekday
+///
+/// # Fields
+///
+/// * `day` - the weekday the window applies to
+/// * `closed` - true when the pantry does not open that day, `open` and `close` must then be null
+/// * `open` - opening time, local to the pantry
+/// * `close` - closing time, earlier than `open` for a window that runs past midnight
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "DayHoursInput")]
+pub struct DayHours {
+    pub day: DayOfWeek,
+    pub closed: bool,
+    pub open: Option<NaiveTime>,
+    pub close: Option<NaiveTime>,
+}
+
+/// Opening window of a pantry on a specific date, replacing its weekly hours for that date
+///
+/// # Fields
+///
+/// * `date` - the date the override applies to
+/// * `closed` - true when the pantry does not open on the date, `open` and `close` must then be null
+/// * `open` - opening time, local to the pantry
+/// * `close` - closing time, earlier than `open` for a window that runs past midnight
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "HolidayHoursInput")]
+pub struct HolidayHours {
+    pub date: NaiveDate,
+    pub closed: bool,
+    pub open: Option<NaiveTime>,
+    pub close: Option<NaiveTime>,
+}
+
+/// Operating schedule of a pantry
+///
+/// # Fields
+///
+/// * `weekly` - regular hours, at most one entry per weekday; a weekday without an entry is closed
+/// * `holidays` - dated overrides, at most one entry per date
+#[derive(Clone, Debug, Default, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "OperatingHoursInput")]
+pub struct OperatingHours {
+    pub weekly: Vec<DayHours>,
+    pub holidays: Vec<HolidayHours>,
+}
+
+/// Checks one opening window
+///
+/// A closed day must not carry times. An open day needs both times and they may not
+/// be equal; `close` before `open` is allowed and means the window runs overnight.
+fn validate_window(
+    label: &str,
+    closed: bool,
+    open: Option<NaiveTime>,
+    close: Option<NaiveTime>
+) -> Result<(), AppError> {
+    match (closed, open, close) {
+        (true, None, None) => Ok(()),
+        (true, _, _) =>
+            Err(
+                AppError::ValidationError(
+                    format!("{} is marked closed but has opening times", label)
+                )
+            ),
+        (false, Some(open), Some(close)) if open == close =>
+            Err(
+                AppError::ValidationError(
+                    format!("{} opens and closes at the same time", label)
+                )
+            ),
+        (false, Some(_), Some(_)) => Ok(()),
+        (false, _, _) =>
+            Err(AppError::ValidationError(format!("{} needs both an open and a close time", label))),
+    }
+}
+
+/// Builds the db map for one opening window
+fn window_to_item(
+    closed: bool,
+    open: Option<NaiveTime>,
+    close: Option<NaiveTime>
+) -> AttributeValue {
+    let mut window = HashMap::new();
+
+    let closed_str = match closed {
+        true => "true",
+        false => "false",
+    };
+    window.insert("closed".to_string(), AttributeValue::S(closed_str.to_string()));
+
+    if let Some(open) = open {
+        window.insert("open".to_string(), AttributeValue::S(open.format(TIME_FORMAT).to_string()));
+    }
+    if let Some(close) = close {
+        window.insert(
+            "close".to_string(),
+            AttributeValue::S(close.format(TIME_FORMAT).to_string())
+        );
+    }
+
+    AttributeValue::M(window)
+}
+
+/// Reads one opening window from its db map, as `(closed, open, close)`
+fn window_from_item(
+    window: &HashMap<String, AttributeValue>
+) -> Option<(bool, Option<NaiveTime>, Option<NaiveTime>)> {
+    let closed = window.get("closed")?.as_s().ok()? == "true";
+
+    let time = |name: &str| {
+        window
+            .get(name)
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| NaiveTime::parse_from_str(s, TIME_FORMAT).ok())
+    };
+
+    Some((closed, time("open"), time("close")))
+}
+
+impl OperatingHours {
+    /// Gets the window that applies on a date, as `(open, close)`
+    ///
+    /// A holiday override for the date wins over the weekly entry for its weekday.
+    ///
+    /// # Returns
+    ///
+    /// 'some' window if the pantry opens that date, 'none' if it is closed
+    fn window_on(&self, date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+        let (closed, open, close) = match self.holidays.iter().find(|h| h.date == date) {
+            Some(holiday) => (holiday.closed, holiday.open, holiday.close),
+            None => {
+                let day = DayOfWeek::from(date.weekday());
+                let weekly = self.weekly.iter().find(|h| h.day == day)?;
+                (weekly.closed, weekly.open, weekly.close)
+            }
+        };
+
+        if closed {
+            return None;
+        }
+
+        Some((open?, close?))
+    }
+
+    /// Whether the pantry is open at a local date and time
+    ///
+    /// Overnight windows are checked on both sides of midnight: a window opening
+    /// Friday 22:00 and closing 02:00 covers Friday night and early Saturday.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - date and time local to the pantry
+    pub fn is_open_at(&self, at: NaiveDateTime) -> bool {
+        let time = at.time();
+
+        let open_today = self.window_on(at.date()).is_some_and(|(open, close)| {
+            if open < close { open <= time && time < close } else { open <= time }
+        });
+
+        // the tail of an overnight window that opened the day before
+        let open_from_yesterday = at
+            .date()
+            .pred_opt()
+            .and_then(|yesterday| self.window_on(yesterday))
+            .is_some_and(|(open, close)| close < open && time < close);
+
+        open_today || open_from_yesterday
+    }
+
+    /// Validates the schedule before it is saved
+    ///
+    /// # Errors
+    ///
+    /// Returns a ValidationError (400) App error variant if a weekday or date appears
+    /// twice, or if any window is invalid (see `validate_window`)
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut days = HashSet::new();
+        for hours in &self.weekly {
+            if !days.insert(hours.day) {
+                return Err(
+                    AppError::ValidationError(
+                        format!("{:?} has more than one entry in weekly hours", hours.day)
+                    )
+                );
+            }
+            validate_window(&format!("{:?}", hours.day), hours.closed, hours.open, hours.close)?;
+        }
+
+        let mut dates = HashSet::new();
+        for hours in &self.holidays {
+            if !dates.insert(hours.date) {
+                return Err(
+                    AppError::ValidationError(
+                        format!("{} has more than one holiday override", hours.date)
+                    )
+                );
+            }
+            validate_window(&hours.date.to_string(), hours.closed, hours.open, hours.close)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates DynamoDB map attribute from the schedule
+    ///
+    /// # Returns
+    ///
+    /// Map attribute holding the weekly hours keyed by weekday and holidays keyed by date
+    pub fn to_item(&self) -> AttributeValue {
+        let weekly = self.weekly
+            .iter()
+            .map(|h| (h.day.to_str().to_string(), window_to_item(h.closed, h.open, h.close)))
+            .collect::<HashMap<_, _>>();
+
+        let holidays = self.holidays
+            .iter()
+            .map(|h| (
+                h.date.format(DATE_FORMAT).to_string(),
+                window_to_item(h.closed, h.open, h.close),
+            ))
+            .collect::<HashMap<_, _>>();
+
+        AttributeValue::M(
+            HashMap::from([
+                ("weekly".to_string(), AttributeValue::M(weekly)),
+                ("holidays".to_string(), AttributeValue::M(holidays)),
+            ])
+        )
+    }
+
+    /// Creates a schedule from its DynamoDB map attribute
+    ///
+    /// Entries that can't be parsed are skipped rather than failing the whole pantry.
+    ///
+    /// # Returns
+    ///
+    /// 'some' OperatingHours if the attribute is a map, 'none' otherwise
+    pub fn from_item(value: &AttributeValue) -> Option<Self> {
+        let hours = value.as_m().ok()?;
+
+        let mut weekly = hours
+            .get("weekly")
+            .and_then(|v| v.as_m().ok())
+            .map(|weekly| {
+                weekly
+                    .iter()
+                    .filter_map(|(day, window)| {
+                        let day = DayOfWeek::from_string(day)?;
+                        let (closed, open, close) = window_from_item(window.as_m().ok()?)?;
+                        Some(DayHours { day, closed, open, close })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        weekly.sort_by_key(|h| h.day as u8);
+
+        let mut holidays = hours
+            .get("holidays")
+            .and_then(|v| v.as_m().ok())
+            .map(|holidays| {
+                holidays
+                    .iter()
+                    .filter_map(|(date, window)| {
+                        let date = NaiveDate::parse_from_str(date, DATE_FORMAT).ok()?;
+                        let (closed, open, close) = window_from_item(window.as_m().ok()?)?;
+                        Some(HolidayHours { date, closed, open, close })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        holidays.sort_by_key(|h| h.date);
+
+        Some(Self { weekly, holidays })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> Option<NaiveTime> {
+        Some(NaiveTime::parse_from_str(value, TIME_FORMAT).unwrap())
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, DATE_FORMAT).unwrap()
+    }
+
+    fn day(day: DayOfWeek, open: &str, close: &str) -> DayHours {
+        DayHours { day, closed: false, open: time(open), close: time(close) }
+    }
+
+    fn message(result: Result<(), AppError>) -> String {
+        match result {
+            Err(AppError::ValidationError(message)) => message,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_regular_and_overnight_windows() {
+        let hours = OperatingHours {
+            weekly: vec![
+                day(DayOfWeek::Monday, "09:00", "17:00"),
+                day(DayOfWeek::Friday, "22:00", "02:00"),
+                DayHours { day: DayOfWeek::Sunday, closed: true, open: None, close: None }
+            ],
+            holidays: vec![HolidayHours {
+                date: date("2025-12-25"),
+                closed: true,
+                open: None,
+                close: None,
+            }],
+        };
+
+        assert!(hours.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_windows() {
+        let closed_with_times = OperatingHours {
+            weekly: vec![DayHours {
+                day: DayOfWeek::Monday,
+                closed: true,
+                open: time("09:00"),
+                close: None,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            message(closed_with_times.validate()),
+            "Monday is marked closed but has opening times"
+        );
+
+        let same_time = OperatingHours {
+            weekly: vec![day(DayOfWeek::Tuesday, "09:00", "09:00")],
+            ..Default::default()
+        };
+        assert_eq!(message(same_time.validate()), "Tuesday opens and closes at the same time");
+
+        let missing_close = OperatingHours {
+            holidays: vec![HolidayHours {
+                date: date("2025-07-04"),
+                closed: false,
+                open: time("10:00"),
+                close: None,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            message(missing_close.validate()),
+            "2025-07-04 needs both an open and a close time"
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_days_and_dates() {
+        let days = OperatingHours {
+            weekly: vec![
+                day(DayOfWeek::Monday, "09:00", "12:00"),
+                day(DayOfWeek::Monday, "13:00", "17:00")
+            ],
+            ..Default::default()
+        };
+        assert_eq!(message(days.validate()), "Monday has more than one entry in weekly hours");
+
+        let holiday = HolidayHours {
+            date: date("2025-12-25"),
+            closed: true,
+            open: None,
+            close: None,
+        };
+        let dates = OperatingHours { holidays: vec![holiday.clone(), holiday], ..Default::default() };
+        assert_eq!(message(dates.validate()), "2025-12-25 has more than one holiday override");
+    }
+
+    #[test]
+    fn round_trips_through_the_db_item() {
+        let hours = OperatingHours {
+            weekly: vec![
+                day(DayOfWeek::Monday, "09:00", "17:00"),
+                DayHours { day: DayOfWeek::Sunday, closed: true, open: None, close: None }
+            ],
+            holidays: vec![HolidayHours {
+                date: date("2025-12-24"),
+                closed: false,
+                open: time("10:00"),
+                close: time("14:00"),
+            }],
+        };
+
+        let parsed = OperatingHours::from_item(&hours.to_item()).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&hours).unwrap()
+        );
+    }
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn is_open_within_a_window() {
+        // 2025-06-02 is a Monday
+        let hours = OperatingHours {
+            weekly: vec![day(DayOfWeek::Monday, "09:00", "17:00")],
+            ..Default::default()
+        };
+
+        assert!(!hours.is_open_at(at("2025-06-02 08:59")));
+        assert!(hours.is_open_at(at("2025-06-02 09:00")));
+        assert!(!hours.is_open_at(at("2025-06-02 17:00")));
+        assert!(!hours.is_open_at(at("2025-06-03 12:00")));
+    }
+
+    #[test]
+    fn overnight_windows_run_past_midnight() {
+        // 2025-06-06 is a Friday
+        let hours = OperatingHours {
+            weekly: vec![day(DayOfWeek::Friday, "22:00", "02:00")],
+            ..Default::default()
+        };
+
+        assert!(!hours.is_open_at(at("2025-06-06 01:00")));
+        assert!(hours.is_open_at(at("2025-06-06 23:00")));
+        assert!(hours.is_open_at(at("2025-06-07 01:59")));
+        assert!(!hours.is_open_at(at("2025-06-07 02:00")));
+    }
+
+    #[test]
+    fn holidays_replace_the_weekly_hours() {
+        let hours = OperatingHours {
+            weekly: vec![
+                day(DayOfWeek::Wednesday, "09:00", "17:00"),
+                day(DayOfWeek::Thursday, "09:00", "17:00")
+            ],
+            holidays: vec![
+                HolidayHours {
+                    date: date("2025-12-24"),
+                    closed: false,
+                    open: time("09:00"),
+                    close: time("12:00"),
+                },
+                HolidayHours { date: date("2025-12-25"), closed: true, open: None, close: None }
+            ],
+        };
+
+        assert!(!hours.is_open_at(at("2025-12-24 13:00")));
+        assert!(!hours.is_open_at(at("2025-12-25 10:00")));
+        assert!(hours.is_open_at(at("2025-12-31 10:00")));
+    }
+}
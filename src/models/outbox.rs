@@ -0,0 +1,203 @@
+//! Events recorded in the Outbox table, the log of changes partners sync from.
+//!
+//! An event is written in the same transaction as the change it describes, so a change
+//! that lands always has its event and a failed one never does. Every event can be read
+//! back in order with the `changelog` query. With webhooks configured events are also
+//! written pending, and the webhook poller delivers them until the receiver accepts
+//! them, see `webhook`. Events are delivered at least once; receivers drop duplicates by
+//! the event `id`.
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{ DateTime, SecondsFormat, Utc };
+use serde_json::{ json, Value };
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    db::attributes::{ get_datetime, get_opt_i64, get_str },
+    error::AppError,
+};
+
+use super::{ pantry::OptStatusChange, pantry_access::AccessLevel };
+
+/// Value of the `pending` attribute of events not yet delivered
+///
+/// It is the partition key of the Outbox `PendingIndex` GSI, delivered events have no
+/// `pending` attribute so they drop out of the index.
+pub const OUTBOX_PENDING: &str = "PENDING";
+
+/// Value of the `entity_type` attribute written on every event
+///
+/// It is the partition key of the Outbox `CreatedAtIndex` GSI the changelog is read from,
+/// see `ensure_table_exists::outbox`.
+pub const OUTBOX_ENTITY_TYPE: &str = "EVENT";
+
+/// Event name of opt status changes
+pub const OPT_STATUS_CHANGED_EVENT: &str = "pantry.opt_status_changed";
+
+/// Event name of pantry access grants
+pub const ACCESS_CHANGED_EVENT: &str = "pantry.access_changed";
+
+/// Formats a time as a sort key, fixed width so keys sort in time order
+pub fn outbox_timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// An event waiting in, or delivered from, the outbox
+///
+/// # Fields
+///
+/// * `id` - Unique identifier of the event, also sent in the payload for deduplication
+/// * `event` - event name, e.g. `pantry.opt_status_changed`
+/// * `payload` - JSON body sent to the webhook
+/// * `created_at` - when the change happened
+/// * `attempts` - failed deliveries so far
+/// * `next_attempt_at` - when the poller may next try to deliver the event
+/// * `pending` - whether the event still has to be delivered to the webhook
+#[derive(Clone, Debug)]
+pub struct OutboxEvent {
+    pub id: String,
+    pub event: String,
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime<Utc>,
+    pub pending: bool,
+}
+
+impl OutboxEvent {
+    /// Creates an event, pending and due right away
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - event name
+    /// * `fields` - JSON object of the event's data, `id` and `event` are added to it
+    /// * `now` - when the change happened
+    fn new(event: &str, fields: Value, now: DateTime<Utc>) -> Self {
+        let id = Uuid::new_v4().to_string();
+
+        let mut payload = json!({ "id": id, "event": event });
+        if let (Some(payload), Value::Object(fields)) = (payload.as_object_mut(), fields) {
+            payload.extend(fields);
+        }
+
+        Self {
+            id,
+            event: event.to_string(),
+            payload: payload.to_string(),
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            pending: true,
+        }
+    }
+
+    /// Sets whether the event is delivered to the webhook, or only kept for the changelog
+    pub fn delivered_by_webhook(mut self, pending: bool) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Creates the event of a pantry's opt status change
+    pub fn opt_status_changed(pantry_id: &str, pantry_name: &str, change: &OptStatusChange) -> Self {
+        Self::new(
+            OPT_STATUS_CHANGED_EVENT,
+            json!({
+                "pantryId": pantry_id,
+                "pantryName": pantry_name,
+                "from": change.from.to_str(),
+                "to": change.to.to_str(),
+                "changedAt": change.changed_at.to_rfc3339(),
+                "actor": change.actor,
+            }),
+            change.changed_at
+        )
+    }
+
+    /// Creates the event of access levels granted on a pantry
+    ///
+    /// # Arguments
+    ///
+    /// * `pantry_id` - ID of the pantry whose team changed
+    /// * `grants` - users and the access levels they were given
+    /// * `actor` - ID of the user making the change
+    /// * `now` - when the change happened
+    pub fn access_changed(
+        pantry_id: &str,
+        grants: &[(String, AccessLevel)],
+        actor: &str,
+        now: DateTime<Utc>
+    ) -> Self {
+        let grants = grants
+            .iter()
+            .map(|(user_id, level)| json!({ "userId": user_id, "accessLevel": level.to_str() }))
+            .collect::<Vec<_>>();
+
+        Self::new(
+            ACCESS_CHANGED_EVENT,
+            json!({
+                "pantryId": pantry_id,
+                "grants": grants,
+                "changedAt": now.to_rfc3339(),
+                "actor": actor,
+            }),
+            now
+        )
+    }
+
+    /// Creates OutboxEvent instance from DynamoDB item
+    ///
+    /// # Errors
+    ///
+    /// Returns Database Error (500) App error variant naming the first attribute that is
+    /// missing or can't be read
+    pub fn try_from_item(item: &HashMap<String, AttributeValue>) -> Result<Self, AppError> {
+        Ok(Self {
+            id: get_str(item, "id")?,
+            event: get_str(item, "event")?,
+            payload: get_str(item, "payload")?,
+            created_at: get_datetime(item, "created_at")?,
+            attempts: get_opt_i64(item, "attempts")?.unwrap_or_default(),
+            next_attempt_at: get_datetime(item, "next_attempt_at")?,
+            pending: item.contains_key("pending"),
+        })
+    }
+
+    /// Creates OutboxEvent instance from DynamoDB item
+    ///
+    /// # Returns
+    ///
+    /// 'some' OutboxEvent if item fields match, 'none' otherwise
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        Self::try_from_item(item)
+            .map_err(|e| debug!("Outbox item could not be read: {}", e))
+            .ok()
+    }
+
+    /// Creates the DynamoDB item of a new event
+    ///
+    /// Only pending events get the `pending` attribute, so the rest stay out of the
+    /// PendingIndex.
+    pub fn to_item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::from([
+            ("id".to_string(), AttributeValue::S(self.id.clone())),
+            ("event".to_string(), AttributeValue::S(self.event.clone())),
+            ("payload".to_string(), AttributeValue::S(self.payload.clone())),
+            ("created_at".to_string(), AttributeValue::S(outbox_timestamp(self.created_at))),
+            ("attempts".to_string(), AttributeValue::N(self.attempts.to_string())),
+            (
+                "next_attempt_at".to_string(),
+                AttributeValue::S(outbox_timestamp(self.next_attempt_at)),
+            ),
+            ("entity_type".to_string(), AttributeValue::S(OUTBOX_ENTITY_TYPE.to_string())),
+        ]);
+
+        if self.pending {
+            item.insert("pending".to_string(), AttributeValue::S(OUTBOX_PENDING.to_string()));
+        }
+
+        item
+    }
+}
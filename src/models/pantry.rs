@@ -19,13 +19,27 @@
 
 use std::{ collections::HashMap };
 
-use async_graphql::{ Object, SimpleObject };
+use async_graphql::{ Context, Enum, InputObject, Object, Result as GraphQLResult, SimpleObject };
 use aws_sdk_dynamodb::{ types::AttributeValue };
 use chrono::{ DateTime, Utc };
 use serde::{ Deserialize, Serialize };
-use tracing::info;
+use tracing::{ debug, warn };
 
-use crate::error::AppError;
+use crate::{
+    auth::guard::require_pantry_access,
+    db::attributes::{ get_attribute, get_bool, get_datetime, get_opt_datetime, get_opt_str, get_str },
+    error::AppError,
+    schema::context::{ db, now },
+};
+
+use chrono_tz::Tz;
+
+use super::{
+    normalize::{ normalize_name, normalize_text },
+    operating_hours::OperatingHours,
+    pantry_access::AccessLevel,
+    timezone::{ default_timezone, timezone_for_state },
+};
 
 /// Represent variant of Opt-Status for pantry
 ///
@@ -37,30 +51,53 @@ use crate::error::AppError;
 /// * `T3` - opted-in fully; Pantry will have feature flags and inventory
 ///
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, Enum)]
 #[serde(rename_all = "snake_case")]
-enum OptStatus {
+pub enum OptStatus {
     T1,
     T2,
     T3,
 }
 
 impl OptStatus {
-    fn to_string(&self) -> String {
-        match self {
-            OptStatus::T1 => "T1".to_string(),
-            OptStatus::T2 => "T2".to_string(),
-            OptStatus::T3 => "T3".to_string(),
-        }
-    }
-    fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         match self {
             OptStatus::T1 => "T1",
             OptStatus::T2 => "T2",
             OptStatus::T3 => "T3",
         }
     }
-    fn from_string(s: &str) -> Result<OptStatus, AppError> {
+    /// Whether a pantry at this opt status may be moved to another
+    ///
+    /// Rollouts go up one tier at a time, a pantry gets its flags at T2 before it gets
+    /// inventory at T3, so T1 can't skip to T3. Moving down is always allowed, as is
+    /// staying at the same status.
+    pub fn can_transition_to(self, to: OptStatus) -> bool {
+        !matches!((self, to), (OptStatus::T1, OptStatus::T3))
+    }
+
+    /// Checks a change of opt status against `can_transition_to`
+    ///
+    /// # Errors
+    ///
+    /// Returns a ValidationError (400) App error variant if the change isn't allowed
+    pub fn check_transition(self, to: OptStatus) -> Result<(), AppError> {
+        if self.can_transition_to(to) {
+            return Ok(());
+        }
+
+        Err(
+            AppError::ValidationError(
+                format!(
+                    "A pantry can't move from {} to {}, it has to be at T2 first",
+                    self.to_str(),
+                    to.to_str()
+                )
+            )
+        )
+    }
+
+    pub fn from_string(s: &str) -> Result<OptStatus, AppError> {
         match s {
             "T1" => Ok(Self::T1),
             "T2" => Ok(Self::T2),
@@ -74,11 +111,92 @@ impl OptStatus {
     }
 }
 
+/// Gets the `name_lower` attribute of a pantry, the sort key of the `NameIndex` GSI
+///
+/// `"  Westside   Food Bank"` becomes `"westside food bank"`
+pub fn name_search_key(name: &str) -> String {
+    normalize_name(name).to_lowercase()
+}
+
+/// Gets the `name_bucket` attribute of a pantry, the partition key of the `NameIndex` GSI
+///
+/// The first character of its `name_search_key`, so every name starting with a
+/// prefix is in the bucket of the prefix. Empty for an empty key.
+pub fn name_search_bucket(name_key: &str) -> String {
+    name_key.chars().next().map(String::from).unwrap_or_default()
+}
+
+/// Most opt status changes kept on a pantry, older changes are dropped first
+///
+/// Keeps the history from growing the item towards DynamoDB's 400KB limit
+pub const OPT_STATUS_HISTORY_LIMIT: usize = 50;
+
+/// Records one change of a pantry's opt status
+///
+/// # Fields
+///
+/// * `changed_at` - Date and time of the change
+/// * `from` - opt status before the change
+/// * `to` - opt status after the change
+/// * `actor` - ID of the user who made the change
+#[derive(Clone, Debug, Serialize, Deserialize, SimpleObject)]
+pub struct OptStatusChange {
+    pub changed_at: DateTime<Utc>,
+    pub from: OptStatus,
+    pub to: OptStatus,
+    pub actor: String,
+}
+
+impl OptStatusChange {
+    /// Creates the DynamoDB map attribute stored in a pantry's `opt_status_history` list
+    pub fn to_attribute(&self) -> AttributeValue {
+        AttributeValue::M(
+            HashMap::from([
+                ("changed_at".to_string(), AttributeValue::S(self.changed_at.to_string())),
+                ("from".to_string(), AttributeValue::S(self.from.to_str().to_string())),
+                ("to".to_string(), AttributeValue::S(self.to.to_str().to_string())),
+                ("actor".to_string(), AttributeValue::S(self.actor.clone())),
+            ])
+        )
+    }
+
+    /// Creates an OptStatusChange from its DynamoDB map attribute
+    ///
+    /// # Returns
+    ///
+    /// 'some' OptStatusChange if the map fields match, 'none' otherwise
+    pub fn from_attribute(value: &AttributeValue) -> Option<Self> {
+        let change = value.as_m().ok()?;
+
+        Some(Self {
+            changed_at: get_datetime(change, "changed_at").ok()?,
+            from: OptStatus::from_string(&get_str(change, "from").ok()?).ok()?,
+            to: OptStatus::from_string(&get_str(change, "to").ok()?).ok()?,
+            actor: get_str(change, "actor").ok()?,
+        })
+    }
+
+    /// Appends a change to a history, dropping the oldest entries beyond `OPT_STATUS_HISTORY_LIMIT`
+    pub fn append_to(self, mut history: Vec<Self>) -> Vec<Self> {
+        history.push(self);
+        if history.len() > OPT_STATUS_HISTORY_LIMIT {
+            history.drain(..history.len() - OPT_STATUS_HISTORY_LIMIT);
+        }
+        history
+    }
+
+    /// Creates the DynamoDB list attribute holding a whole history, oldest first
+    pub fn history_to_attribute(history: &[Self]) -> AttributeValue {
+        AttributeValue::L(history.iter().map(Self::to_attribute).collect())
+    }
+}
+
 /// Represents a Food Pantry involved in program
 ///
 /// # Fields
 ///
 /// * `id` - Unique identifier for the pantry
+/// * `code` - short human-friendly code, e.g. `PAN-00042`, assigned on creation
 /// * `name` - Name of food pantry
 /// * `agent` - ID of user designated as agent for pantry
 /// * `opt_status` - Value from OptStatus enum representing involvement level in program
@@ -86,10 +204,23 @@ impl OptStatus {
 /// * `address` - Address of Pantry
 /// * `created_at` - Date and time of creation
 /// * `updated_at` - Date and time of last update
+/// * `hours` - optional operating schedule
+/// * `timezone` - IANA timezone the pantry's hours are local to
+/// * `languages` - ISO 639-1 codes of the languages spoken at the pantry, sorted
+/// * `image_url` - http(s) URL of the pantry's logo or photo, see `models::image_url`
+/// * `appointment_required` - whether clients must book a visit instead of walking in
+/// * `booking_url` - http(s) URL to book a visit at, only set when `appointment_required` is
+/// * `is_temporarily_closed` - whether the pantry is closed outside its hours, e.g. for a holiday
+/// * `closed_reason` - why the pantry is closed, only set while `is_temporarily_closed` is
+/// * `reopens_at` - when a temporary closure ends on its own, 'none' until reopened by hand
+/// * `opt_status_history` - changes of `opt_status`, oldest first, capped at `OPT_STATUS_HISTORY_LIMIT`
+/// * `search_origin` - point a radius query measured from, never persisted
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Pantry {
     pub id: String,
+    #[serde(default)]
+    pub code: Option<String>,
     pub name: String,
     pub is_self_managed: String,
     pub opt_status: OptStatus,
@@ -99,6 +230,27 @@ pub struct Pantry {
     pub address: Address,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub hours: Option<OperatingHours>,
+    pub timezone: Tz,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub appointment_required: bool,
+    #[serde(default)]
+    pub booking_url: Option<String>,
+    #[serde(default)]
+    pub is_temporarily_closed: bool,
+    #[serde(default)]
+    pub closed_reason: Option<String>,
+    #[serde(default)]
+    pub reopens_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub opt_status_history: Vec<OptStatusChange>,
+    #[serde(skip)]
+    pub search_origin: Option<GeoPoint>,
 }
 
 /// Represents a physical street address using format for united states
@@ -110,13 +262,168 @@ pub struct Pantry {
 /// * `city` - the city
 /// * `state` - the state
 /// * `zipcode` - zipcode of address
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// * `geo` - optional coordinates of the address, used by radius queries
+#[derive(Clone, Debug, Serialize, Deserialize, InputObject)]
+#[graphql(input_name = "AddressInput")]
 pub struct Address {
     pub street: String,
     pub unit: Option<String>,
     pub city: String,
     pub state: String,
     pub zipcode: String,
+    #[serde(default)]
+    pub geo: Option<GeoPoint>,
+}
+
+/// Represents a point on the earth in decimal degrees
+///
+/// # Fields
+///
+/// * `lat` - latitude, -90 to 90
+/// * `lng` - longitude, -180 to 180
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, SimpleObject, InputObject)]
+#[graphql(input_name = "GeoPointInput")]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl GeoPoint {
+    /// Mean radius of the earth in kilometers
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+
+    /// Calculates the great-circle distance to another point with the haversine formula
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - point to measure to
+    ///
+    /// # Returns
+    ///
+    /// Distance in kilometers
+    pub fn distance_km(&self, other: &GeoPoint) -> f64 {
+        let d_lat = (other.lat - self.lat).to_radians();
+        let d_lng = (other.lng - self.lng).to_radians();
+
+        let a =
+            (d_lat / 2.0).sin().powi(2) +
+            self.lat.to_radians().cos() * other.lat.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
+
+        2.0 * Self::EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+impl Address {
+    /// Trims every text field of the address, an empty unit is dropped
+    pub fn normalized(self) -> Self {
+        Self {
+            street: normalize_name(&self.street),
+            unit: self.unit
+                .map(|unit| normalize_name(&unit))
+                .filter(|unit| !unit.is_empty()),
+            city: normalize_name(&self.city),
+            state: normalize_text(&self.state),
+            zipcode: normalize_text(&self.zipcode),
+            geo: self.geo,
+        }
+    }
+
+    /// Creates the DynamoDB map attribute stored as a pantry's `address`
+    ///
+    /// # Returns
+    ///
+    /// Map attribute holding the address fields, without `unit` or `geo` when absent
+    pub fn to_attribute(&self) -> AttributeValue {
+        let mut address = HashMap::new();
+
+        // convert nested address fields to Attribute Values and put in address map
+        address.insert("street".to_string(), AttributeValue::S(self.street.clone()));
+
+        // unit is optional, the field will not be created in the db item if not present on struct
+        if let Some(unit) = &self.unit {
+            address.insert("unit".to_string(), AttributeValue::S(unit.clone()));
+        }
+
+        address.insert("city".to_string(), AttributeValue::S(self.city.clone()));
+        address.insert("state".to_string(), AttributeValue::S(self.state.clone()));
+
+        address.insert("zipcode".to_string(), AttributeValue::S(self.zipcode.clone()));
+
+        // geo is optional, stored as a nested map of numbers
+        if let Some(geo) = &self.geo {
+            let mut geo_map = HashMap::new();
+            geo_map.insert("lat".to_string(), AttributeValue::N(geo.lat.to_string()));
+            geo_map.insert("lng".to_string(), AttributeValue::N(geo.lng.to_string()));
+            address.insert("geo".to_string(), AttributeValue::M(geo_map));
+        }
+
+        AttributeValue::M(address)
+    }
+
+    /// Creates an address from a pantry's `address` map attribute
+    ///
+    /// Only `street` is required. Legacy rows missing `city`, `state` or `zipcode` still load,
+    /// with the missing fields empty and a warning naming the pantry, so one bad row doesn't
+    /// hide the pantry.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the `address` attribute
+    /// * `pantry_id` - ID of the pantry the address belongs to, for the warning
+    ///
+    /// # Returns
+    ///
+    /// 'some' Address if the attribute is a map with a street, 'none' otherwise
+    pub fn from_attribute(value: &AttributeValue, pantry_id: &str) -> Option<Self> {
+        let address = value.as_m().ok()?;
+
+        let text = |name: &str| {
+            address
+                .get(name)
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+        };
+
+        let mut missing = Vec::new();
+        let mut defaulted = |name: &'static str| {
+            text(name).unwrap_or_else(|| {
+                missing.push(name);
+                String::new()
+            })
+        };
+
+        let city = defaulted("city");
+        let state = defaulted("state");
+        let zipcode = defaulted("zipcode");
+
+        if !missing.is_empty() {
+            warn!("Pantry {} address is missing {}, left empty", pantry_id, missing.join(", "));
+        }
+
+        Some(Self {
+            street: text("street")?,
+            unit: text("unit"),
+            city,
+            state,
+            zipcode,
+            geo: address
+                .get("geo")
+                .and_then(|v| v.as_m().ok())
+                .and_then(|geo| {
+                    Some(GeoPoint {
+                        lat: geo.get("lat")?.as_n().ok()?.parse().ok()?,
+                        lng: geo.get("lng")?.as_n().ok()?.parse().ok()?,
+                    })
+                }),
+        })
+    }
+
+    /// Gets the timezone the address is in
+    ///
+    /// Derived from the state, falling back to the configured default timezone
+    pub fn timezone(&self) -> Tz {
+        timezone_for_state(&self.state).unwrap_or_else(default_timezone)
+    }
 }
 
 /// Defines methods for Pantry
@@ -138,7 +445,7 @@ impl Pantry {
     ///
     /// # Returns
     ///
-    /// New Pantry instance
+    /// New Pantry instance without operating hours, in the timezone derived from the address
     ///
     ///
     pub fn new(
@@ -153,6 +460,8 @@ impl Pantry {
     ) -> Result<Self, String> {
         let now = Utc::now();
 
+        let timezone = address.timezone();
+
         let is_self_managed_str = match is_self_managed {
             true => "true",
             false => "false",
@@ -160,6 +469,7 @@ impl Pantry {
 
         Ok(Self {
             id,
+            code: None,
             name,
             opt_status,
             address,
@@ -168,8 +478,41 @@ impl Pantry {
             email,
             created_at: now,
             updated_at: now,
+            hours: None,
+            timezone,
+            languages: Vec::new(),
+            image_url: None,
+            appointment_required: false,
+            booking_url: None,
+            is_temporarily_closed: false,
+            closed_reason: None,
+            reopens_at: None,
+            opt_status_history: Vec::new(),
+            search_origin: None,
         })
     }
+
+    /// Whether a temporary closure is in effect at a point in time
+    ///
+    /// A closure with a `reopens_at` lapses once that time has passed, without another write.
+    pub fn is_closed_at(&self, now: DateTime<Utc>) -> bool {
+        self.is_temporarily_closed && self.reopens_at.is_none_or(|reopens_at| now < reopens_at)
+    }
+
+    /// Whether the pantry is open at a point in time, checked against its hours in its timezone
+    ///
+    /// # Returns
+    ///
+    /// 'some' false while temporarily closed, 'some' whether the hours cover `now` otherwise;
+    /// 'none' if the pantry has no hours set
+    pub fn is_open_at(&self, now: DateTime<Utc>) -> Option<bool> {
+        if self.is_closed_at(now) {
+            return Some(false);
+        }
+
+        let hours = self.hours.as_ref()?;
+        Some(hours.is_open_at(now.with_timezone(&self.timezone).naive_local()))
+    }
     /// Creates Pantry instance from DynamoDB item
     ///
     /// # Arguments
@@ -178,52 +521,95 @@ impl Pantry {
     ///
     /// # Returns
     ///
-    /// 'some' Pantry if item fields match, 'none' otherwise
+    /// 'some' Pantry if item fields match, 'none' otherwise, see `try_from_item` for why
 
     pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
-        info!("calling from_item with: {:?}", &item);
+        debug!("calling from_item with: {:?}", &item);
 
-        let id = item.get("id")?.as_s().ok()?.to_string();
+        let res = Self::try_from_item(item)
+            .map_err(|e| debug!("Pantry item could not be read: {}", e))
+            .ok();
 
-        let name = item.get("name")?.as_s().ok()?.to_string();
+        debug!("result of from_item on pantry: {:?}", res);
+        res
+    }
 
-        // let agent_id = item.get("agent_id")?.as_s().ok()?.to_string();
-        let item_address = item.get("address")?.as_m().ok()?;
-        let address = Address {
-            street: item_address.get("street")?.as_s().ok()?.to_string(),
-            unit: item_address.get("unit")?.as_s().ok().cloned(),
-            city: item_address.get("city")?.as_s().ok()?.to_string(),
-            state: item_address.get("state")?.as_s().ok()?.to_string(),
-            zipcode: item_address.get("zipcode")?.as_s().ok()?.to_string(),
-        };
+    /// Creates Pantry instance from DynamoDB item
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The dynamo db item
+    ///
+    /// # Errors
+    ///
+    /// Returns Database Error (500) App error variant naming the first attribute that is
+    /// missing or can't be read
+    pub fn try_from_item(item: &HashMap<String, AttributeValue>) -> Result<Self, AppError> {
+        let id = get_str(item, "id")?;
 
-        let is_self_managed = item.get("is_self_managed")?.as_s().ok()?.to_string();
+        let name = get_str(item, "name")?;
 
-        let phone = item.get("phone")?.as_s().ok()?.to_string();
+        let address = Address::from_attribute(get_attribute(item, "address")?, &id).ok_or_else(||
+            AppError::DatabaseError("Attribute address is not a map with a street".to_string())
+        )?;
 
-        let email = item.get("email")?.as_s().ok()?.to_string();
+        let is_self_managed = get_str(item, "is_self_managed")?;
 
-        let opt_status_str = item.get("opt_status")?.as_s().ok()?;
+        let phone = get_str(item, "phone")?;
 
-        // Turns opt_status_str received on pantry from db into OptStatus enum value
-        let opt_status = OptStatus::from_string(&opt_status_str)
-            .map_err(|e| e)
-            .ok()?;
+        let email = get_str(item, "email")?;
 
-        let created_at = item
-            .get("created_at")
-            .and_then(|v| v.as_s().ok())
-            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
-            .unwrap_or_else(|| Utc::now());
+        // Turns opt_status received on pantry from db into OptStatus enum value
+        let opt_status = OptStatus::from_string(&get_str(item, "opt_status")?).map_err(|_|
+            AppError::DatabaseError("Attribute opt_status is not T1, T2 or T3".to_string())
+        )?;
+
+        let created_at = get_opt_datetime(item, "created_at")?.unwrap_or_else(Utc::now);
+
+        let updated_at = get_opt_datetime(item, "updated_at")?.unwrap_or_else(Utc::now);
 
-        let updated_at = item
-            .get("updated_at")
+        let hours = item.get("hours").and_then(OperatingHours::from_item);
+
+        // pantries saved before timezones were stored fall back to one derived from the address
+        let timezone = item
+            .get("timezone")
             .and_then(|v| v.as_s().ok())
-            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
-            .unwrap_or_else(|| Utc::now());
+            .and_then(|s| s.parse::<Tz>().ok())
+            .unwrap_or_else(|| address.timezone());
+
+        let mut languages = item
+            .get("languages")
+            .and_then(|v| v.as_ss().ok())
+            .cloned()
+            .unwrap_or_default();
+        languages.sort();
+
+        let opt_status_history = item
+            .get("opt_status_history")
+            .and_then(|v| v.as_l().ok())
+            .map(|changes| changes.iter().filter_map(OptStatusChange::from_attribute).collect())
+            .unwrap_or_default();
+
+        // pantries created before codes were assigned have none
+        let code = get_opt_str(item, "code")?;
 
-        let res = Some(Self {
+        let image_url = get_opt_str(item, "image_url")?;
+
+        // pantries saved before appointments were tracked take walk-ins
+        let appointment_required = get_bool(item, "appointment_required", false)?;
+
+        let booking_url = get_opt_str(item, "booking_url")?;
+
+        // pantries saved before closures were tracked are open
+        let is_temporarily_closed = get_bool(item, "is_temporarily_closed", false)?;
+
+        let closed_reason = get_opt_str(item, "closed_reason")?;
+
+        let reopens_at = get_opt_datetime(item, "reopens_at")?;
+
+        Ok(Self {
             id,
+            code,
             name,
             address,
             is_self_managed,
@@ -232,14 +618,24 @@ impl Pantry {
             opt_status,
             created_at,
             updated_at,
-        });
-
-        info!("result of from_item on pantry: {:?}", res);
-        res
+            hours,
+            timezone,
+            languages,
+            image_url,
+            appointment_required,
+            booking_url,
+            is_temporarily_closed,
+            closed_reason,
+            reopens_at,
+            opt_status_history,
+            search_origin: None,
+        })
     }
 
     /// Creates DynamoDB item from Pantry instance
     ///
+    /// Does not modify `updated_at`, updates of an existing pantry go through `UpdateBuilder::touch`
+    ///
     /// # Arguments
     ///
     /// * `self` - borrowed instance of self
@@ -247,45 +643,81 @@ impl Pantry {
     /// # Returns
     ///
     ///   HashMap representing DB item for Pantry instance
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the serde_json::to_string() function does not complete
-    /// successfully on self.opt_status
-
     pub fn to_item(&self) -> HashMap<String, AttributeValue> {
         let mut item = HashMap::new();
-        let mut address = HashMap::new();
-
-        let opt_status_string = serde_json
-            ::to_string::<OptStatus>(&self.opt_status)
-            .map_err(|e| AppError::InternalServerError(e.to_string()))
-            .ok();
 
         item.insert("id".to_string(), AttributeValue::S(self.id.clone()));
         item.insert("name".to_string(), AttributeValue::S(self.name.clone()));
+        // NameIndex keys, an empty bucket is an invalid key so the pantry stays out of the index
+        let name_key = name_search_key(&self.name);
+        let name_bucket = name_search_bucket(&name_key);
+        if !name_bucket.is_empty() {
+            item.insert("name_bucket".to_string(), AttributeValue::S(name_bucket));
+            item.insert("name_lower".to_string(), AttributeValue::S(name_key));
+        }
+
+        // the CodeIndex key can't be an empty string, so the field is left out when there's no code
+        if let Some(code) = &self.code {
+            item.insert("code".to_string(), AttributeValue::S(code.clone()));
+        }
+
         item.insert("is_self_managed".to_string(), AttributeValue::S(self.is_self_managed.clone()));
         item.insert("phone".to_string(), AttributeValue::S(self.phone.clone()));
         item.insert("email".to_string(), AttributeValue::S(self.email.clone()));
 
-        // convert nested address fields to Attribute Values and put in address map
-        address.insert("street".to_string(), AttributeValue::S(self.address.street.clone()));
+        // insert address map into item map
+        item.insert("address".to_string(), self.address.to_attribute());
 
-        // unit is optional, the field will not be created in the db item if not present on struct
-        if let Some(unit) = &self.address.unit {
-            address.insert("unit".to_string(), AttributeValue::S(unit.clone()));
+        // stored in the same "T1" form from_item reads back
+        item.insert(
+            "opt_status".to_string(),
+            AttributeValue::S(self.opt_status.to_str().to_string())
+        );
+
+        // hours are optional, the field will not be created in the db item if not present on struct
+        if let Some(hours) = &self.hours {
+            item.insert("hours".to_string(), hours.to_item());
         }
 
-        address.insert("city".to_string(), AttributeValue::S(self.address.city.clone()));
-        address.insert("state".to_string(), AttributeValue::S(self.address.state.clone()));
+        item.insert("timezone".to_string(), AttributeValue::S(self.timezone.name().to_string()));
 
-        address.insert("zipcode".to_string(), AttributeValue::S(self.address.zipcode.clone()));
+        // DynamoDB rejects empty sets, so a pantry without languages has no attribute
+        if !self.languages.is_empty() {
+            item.insert("languages".to_string(), AttributeValue::Ss(self.languages.clone()));
+        }
 
-        // insert address map into item map
-        item.insert("address".to_string(), AttributeValue::M(address));
+        if let Some(image_url) = &self.image_url {
+            item.insert("image_url".to_string(), AttributeValue::S(image_url.clone()));
+        }
+
+        item.insert(
+            "appointment_required".to_string(),
+            AttributeValue::S(self.appointment_required.to_string())
+        );
 
-        if let Some(s) = opt_status_string {
-            item.insert("opt_status".to_string(), AttributeValue::S(s));
+        if let Some(booking_url) = &self.booking_url {
+            item.insert("booking_url".to_string(), AttributeValue::S(booking_url.clone()));
+        }
+
+        item.insert(
+            "is_temporarily_closed".to_string(),
+            AttributeValue::S(self.is_temporarily_closed.to_string())
+        );
+
+        if let Some(closed_reason) = &self.closed_reason {
+            item.insert("closed_reason".to_string(), AttributeValue::S(closed_reason.clone()));
+        }
+
+        if let Some(reopens_at) = &self.reopens_at {
+            item.insert("reopens_at".to_string(), AttributeValue::S(reopens_at.to_string()));
+        }
+
+        // the history is only written once the opt status has changed
+        if !self.opt_status_history.is_empty() {
+            item.insert(
+                "opt_status_history".to_string(),
+                OptStatusChange::history_to_attribute(&self.opt_status_history)
+            );
         }
 
         item.insert("created_at".to_string(), AttributeValue::S(self.created_at.to_string()));
@@ -300,6 +732,9 @@ impl Pantry {
     async fn id(&self) -> &str {
         &self.id
     }
+    async fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
     async fn name(&self) -> &str {
         &self.name
     }
@@ -307,7 +742,16 @@ impl Pantry {
         &self.is_self_managed
     }
     async fn opt_status(&self) -> &str {
-        OptStatus::to_str(&self.opt_status)
+        self.opt_status.to_str()
+    }
+
+    // Changes of opt status, oldest first, for Managers of the pantry and Admins
+    async fn opt_status_history(&self, ctx: &Context<'_>) -> GraphQLResult<&[OptStatusChange]> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &self.id, AccessLevel::Manager).await?;
+
+        Ok(&self.opt_status_history)
     }
     async fn phone(&self) -> &str {
         &self.phone
@@ -327,6 +771,63 @@ impl Pantry {
     async fn updated_at(&self) -> &DateTime<Utc> {
         &self.updated_at
     }
+
+    async fn hours(&self) -> Option<&OperatingHours> {
+        self.hours.as_ref()
+    }
+
+    // IANA timezone name, e.g. America/Chicago
+    async fn timezone(&self) -> &str {
+        self.timezone.name()
+    }
+
+    // ISO 639-1 codes of the languages spoken at the pantry, e.g. ["en", "es"]
+    async fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    // URL of the pantry's logo or photo, null if none is set
+    async fn image_url(&self) -> Option<&str> {
+        self.image_url.as_deref()
+    }
+
+    // Whether clients must book a visit instead of walking in
+    async fn appointment_required(&self) -> bool {
+        self.appointment_required
+    }
+
+    // URL to book a visit at, null if the pantry takes walk-ins or books by phone
+    async fn booking_url(&self) -> Option<&str> {
+        self.booking_url.as_deref()
+    }
+
+    // Whether the pantry is temporarily closed right now, false once its reopening time has passed
+    async fn is_temporarily_closed(&self, ctx: &Context<'_>) -> bool {
+        self.is_closed_at(now(ctx))
+    }
+
+    // Why the pantry is temporarily closed, null while it isn't
+    async fn closed_reason(&self, ctx: &Context<'_>) -> Option<&str> {
+        self.closed_reason.as_deref().filter(|_| self.is_closed_at(now(ctx)))
+    }
+
+    // When the temporary closure ends, null while the pantry isn't closed or has no set reopening
+    async fn reopens_at(&self, ctx: &Context<'_>) -> Option<DateTime<Utc>> {
+        self.reopens_at.filter(|_| self.is_closed_at(now(ctx)))
+    }
+
+    // Whether the pantry is open right now in its local time, null if it has no hours set
+    // and isn't temporarily closed
+    async fn is_open_now(&self, ctx: &Context<'_>) -> Option<bool> {
+        self.is_open_at(now(ctx))
+    }
+
+    // Distance from the point a radius query searched from, null outside of radius queries
+    async fn distance_km(&self) -> Option<f64> {
+        let origin = self.search_origin?;
+        let geo = self.address.geo?;
+        Some(origin.distance_km(&geo))
+    }
 }
 
 #[Object]
@@ -349,4 +850,179 @@ impl Address {
     async fn zipcode(&self) -> &str {
         &self.zipcode
     }
+    async fn geo(&self) -> Option<GeoPoint> {
+        self.geo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_graphql::{ EmptyMutation, EmptySubscription, Schema };
+    use serde_json::{ json, Value };
+
+    use crate::clock::{ FixedClock, SharedClock };
+    use crate::models::operating_hours::{ DayHours, DayOfWeek };
+
+    use super::*;
+
+    struct TestQuery(Pantry);
+
+    #[Object]
+    impl TestQuery {
+        async fn pantry(&self) -> &Pantry {
+            &self.0
+        }
+    }
+
+    // a Wisconsin pantry open Mondays 09:00 to 17:00 Central time
+    fn pantry() -> Pantry {
+        let address = Address {
+            street: "1 Main St".to_string(),
+            unit: None,
+            city: "Madison".to_string(),
+            state: "WI".to_string(),
+            zipcode: "53703".to_string(),
+            geo: None,
+        };
+        let mut pantry = Pantry::new(
+            "pantry-1".to_string(),
+            "Food Shelf".to_string(),
+            OptStatus::T1,
+            address,
+            false,
+            "555-0100".to_string(),
+            "pantry@example.com".to_string()
+        ).unwrap();
+        pantry.hours = Some(OperatingHours {
+            weekly: vec![DayHours {
+                day: DayOfWeek::Monday,
+                closed: false,
+                open: "09:00".parse().ok(),
+                close: "17:00".parse().ok(),
+            }],
+            ..Default::default()
+        });
+        pantry
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    async fn query(pantry: Pantry, at: &str) -> Value {
+        let response = Schema::build(TestQuery(pantry), EmptyMutation, EmptySubscription)
+            .data::<SharedClock>(Arc::new(FixedClock(utc(at))))
+            .finish()
+            .execute("{ pantry { isOpenNow isTemporarilyClosed closedReason } }").await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()["pantry"].clone()
+    }
+
+    #[test]
+    fn opt_status_moves_up_one_tier_at_a_time() {
+        use OptStatus::{ T1, T2, T3 };
+
+        let allowed = [(T1, T1), (T1, T2), (T2, T3), (T3, T2), (T3, T1), (T2, T1), (T3, T3)];
+        for (from, to) in allowed {
+            assert!(from.check_transition(to).is_ok(), "{:?} -> {:?}", from, to);
+        }
+
+        match T1.check_transition(T3) {
+            Err(AppError::ValidationError(message)) =>
+                assert_eq!(message, "A pantry can't move from T1 to T3, it has to be at T2 first"),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn is_open_now_reads_the_schema_clock_in_local_time() {
+        // 2025-06-02 is a Monday, Madison is at UTC-5 in June
+        assert_eq!(query(pantry(), "2025-06-02T13:59:00Z").await["isOpenNow"], json!(false));
+        assert_eq!(query(pantry(), "2025-06-02T14:00:00Z").await["isOpenNow"], json!(true));
+        assert_eq!(query(pantry(), "2025-06-02T22:00:00Z").await["isOpenNow"], json!(false));
+    }
+
+    #[tokio::test]
+    async fn temporary_closures_lapse_at_reopens_at() {
+        let mut closed = pantry();
+        closed.is_temporarily_closed = true;
+        closed.closed_reason = Some("Inventory".to_string());
+        closed.reopens_at = Some(utc("2025-06-02T16:00:00Z"));
+
+        let during = query(closed.clone(), "2025-06-02T15:00:00Z").await;
+        assert_eq!(during["isOpenNow"], json!(false));
+        assert_eq!(during["closedReason"], json!("Inventory"));
+
+        let after = query(closed, "2025-06-02T16:00:00Z").await;
+        assert_eq!(after["isOpenNow"], json!(true));
+        assert_eq!(after["isTemporarilyClosed"], json!(false));
+        assert_eq!(after["closedReason"], Value::Null);
+    }
+
+    fn change(actor: &str, from: OptStatus, to: OptStatus) -> OptStatusChange {
+        OptStatusChange {
+            changed_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            from,
+            to,
+            actor: actor.to_string(),
+        }
+    }
+
+    fn actors(history: &[OptStatusChange]) -> Vec<&str> {
+        history.iter().map(|change| change.actor.as_str()).collect()
+    }
+
+    #[test]
+    fn history_keeps_changes_in_order() {
+        let history = change("first", OptStatus::T1, OptStatus::T2).append_to(Vec::new());
+        let history = change("second", OptStatus::T2, OptStatus::T3).append_to(history);
+
+        assert_eq!(actors(&history), ["first", "second"]);
+        assert_eq!(history[1].from, OptStatus::T2);
+        assert_eq!(history[1].to, OptStatus::T3);
+    }
+
+    #[test]
+    fn history_drops_the_oldest_changes_past_the_limit() {
+        let full = (0..OPT_STATUS_HISTORY_LIMIT)
+            .map(|i| change(&i.to_string(), OptStatus::T1, OptStatus::T2))
+            .collect::<Vec<OptStatusChange>>();
+
+        let history = change("newest", OptStatus::T2, OptStatus::T1).append_to(full);
+
+        assert_eq!(history.len(), OPT_STATUS_HISTORY_LIMIT);
+        assert_eq!(history[0].actor, "1");
+        assert_eq!(history.last().unwrap().actor, "newest");
+    }
+
+    #[test]
+    fn pantries_with_a_partial_address_still_load() {
+        let mut item = pantry().to_item();
+        let street_only = HashMap::from([
+            ("street".to_string(), AttributeValue::S("1 Main St".to_string())),
+            ("city".to_string(), AttributeValue::S("Madison".to_string())),
+        ]);
+        item.insert("address".to_string(), AttributeValue::M(street_only));
+
+        let loaded = Pantry::from_item(&item).expect("a partial address should load");
+
+        assert_eq!(loaded.address.street, "1 Main St");
+        assert_eq!(loaded.address.city, "Madison");
+        assert_eq!(loaded.address.unit, None);
+        assert_eq!(loaded.address.state, "");
+        assert_eq!(loaded.address.zipcode, "");
+    }
+
+    #[test]
+    fn pantries_without_a_street_are_skipped() {
+        let mut item = pantry().to_item();
+        let no_street =
+            HashMap::from([("city".to_string(), AttributeValue::S("Madison".to_string()))]);
+        item.insert("address".to_string(), AttributeValue::M(no_street));
+
+        assert!(Pantry::from_item(&item).is_none());
+    }
 }
@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+
+use async_graphql::{ Enum, Object };
+use aws_sdk_dynamodb::types::AttributeValue;
 use chrono::{ DateTime, Utc };
 use serde::{ Deserialize, Serialize };
+use tracing::debug;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -13,10 +18,174 @@ pub struct User {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Represents a level of access a user has to a pantry, highest first
+///
+/// # Variants
+///
+/// * `Admin` - full control of the pantry, including its team
+/// * `Manager` - manages the pantry and its team
+/// * `Staff` - day to day updates such as inventory
+/// * `Viewer` - read only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
 pub enum AccessLevel {
     Admin,
     Manager,
     Staff,
     Viewer,
 }
+
+impl AccessLevel {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            AccessLevel::Admin => "Admin",
+            AccessLevel::Manager => "Manager",
+            AccessLevel::Staff => "Staff",
+            AccessLevel::Viewer => "Viewer",
+        }
+    }
+
+    pub fn from_string(s: &str) -> Option<AccessLevel> {
+        match s {
+            "Admin" => Some(Self::Admin),
+            "Manager" => Some(Self::Manager),
+            "Staff" => Some(Self::Staff),
+            "Viewer" => Some(Self::Viewer),
+            _ => None,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            AccessLevel::Admin => 3,
+            AccessLevel::Manager => 2,
+            AccessLevel::Staff => 1,
+            AccessLevel::Viewer => 0,
+        }
+    }
+
+    /// Whether this level grants at least the access of `minimum`
+    pub fn at_least(&self, minimum: AccessLevel) -> bool {
+        self.rank() >= minimum.rank()
+    }
+
+    /// Gets the higher of two levels
+    pub fn max(self, other: AccessLevel) -> AccessLevel {
+        if other.rank() > self.rank() { other } else { self }
+    }
+}
+
+/// Represents a user's access to a pantry, one row per (pantry, user) pair
+///
+/// # Fields
+///
+/// * `pantry_id` - ID of the pantry
+/// * `user_id` - ID of the user
+/// * `access_level` - level of access the user has to the pantry
+/// * `is_contact_agent` - whether the user is a contact agent for the pantry
+/// * `created_at` - Date and time access was first granted
+/// * `updated_at` - Date and time of last update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PantryAccess {
+    pub pantry_id: String,
+    pub user_id: String,
+    pub access_level: AccessLevel,
+    pub is_contact_agent: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Defines methods for PantryAccess
+impl PantryAccess {
+    /// Creates PantryAccess instance from DynamoDB item
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The dynamo db item
+    ///
+    /// # Returns
+    ///
+    /// 'some' PantryAccess if item fields match, 'none' otherwise
+    pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        debug!("calling from_item with: {:?}", &item);
+
+        let pantry_id = item.get("pantry_id")?.as_s().ok()?.to_string();
+
+        let user_id = item.get("user_id")?.as_s().ok()?.to_string();
+
+        let access_level = AccessLevel::from_string(item.get("access_level")?.as_s().ok()?)?;
+
+        // stored as a string so it can be used as a GSI sort key
+        let is_contact_agent = item
+            .get("is_contact_agent")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s == "true")
+            .unwrap_or(false);
+
+        let created_at = item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+            .unwrap_or_else(Utc::now);
+
+        let updated_at = item
+            .get("updated_at")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+            .unwrap_or_else(Utc::now);
+
+        Some(Self {
+            pantry_id,
+            user_id,
+            access_level,
+            is_contact_agent,
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Creates DynamoDB item from PantryAccess instance
+    ///
+    /// # Returns
+    ///
+    ///   HashMap representing DB item for PantryAccess instance
+    pub fn to_item(&self) -> HashMap<String, AttributeValue> {
+        let mut item = HashMap::new();
+
+        item.insert("pantry_id".to_string(), AttributeValue::S(self.pantry_id.clone()));
+        item.insert("user_id".to_string(), AttributeValue::S(self.user_id.clone()));
+        item.insert(
+            "access_level".to_string(),
+            AttributeValue::S(self.access_level.to_str().to_string())
+        );
+        item.insert(
+            "is_contact_agent".to_string(),
+            AttributeValue::S(self.is_contact_agent.to_string())
+        );
+        item.insert("created_at".to_string(), AttributeValue::S(self.created_at.to_string()));
+        item.insert("updated_at".to_string(), AttributeValue::S(self.updated_at.to_string()));
+
+        item
+    }
+}
+
+#[Object]
+impl PantryAccess {
+    async fn pantry_id(&self) -> &str {
+        &self.pantry_id
+    }
+    async fn user_id(&self) -> &str {
+        &self.user_id
+    }
+    async fn access_level(&self) -> AccessLevel {
+        self.access_level
+    }
+    async fn is_contact_agent(&self) -> bool {
+        self.is_contact_agent
+    }
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
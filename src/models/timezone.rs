@@ -0,0 +1,100 @@
+//! Timezones for pantry schedules.
+//!
+//! Operating hours are local to the pantry, so evaluating them needs the
+//! pantry's IANA timezone. A pantry created without an explicit timezone gets
+//! one derived from its address state, using the zone most of the state
+//! observes. States split across zones (e.g. Kentucky, Tennessee) get their
+//! majority zone, so pantries in the minority zone should set it explicitly.
+
+use std::env;
+
+use chrono_tz::Tz;
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// Timezone used when the state is unknown and `DEFAULT_PANTRY_TIMEZONE` is unset
+const FALLBACK_TIMEZONE: Tz = chrono_tz::America::Chicago;
+
+/// Parses an IANA timezone name such as `America/Chicago`
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the name isn't a known timezone
+pub fn parse_timezone(name: &str) -> Result<Tz, AppError> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| AppError::ValidationError(format!("{} is not a valid IANA timezone", name)))
+}
+
+/// Gets the timezone used for pantries whose timezone can't be derived
+///
+/// Read from the `DEFAULT_PANTRY_TIMEZONE` env var, falling back to
+/// `America/Chicago` if it is unset or not a valid IANA timezone name.
+pub fn default_timezone() -> Tz {
+    match env::var("DEFAULT_PANTRY_TIMEZONE") {
+        Ok(name) =>
+            name.parse::<Tz>().unwrap_or_else(|_| {
+                warn!(
+                    "DEFAULT_PANTRY_TIMEZONE {:?} is not a valid timezone, using {}",
+                    name,
+                    FALLBACK_TIMEZONE
+                );
+                FALLBACK_TIMEZONE
+            }),
+        Err(_) => FALLBACK_TIMEZONE,
+    }
+}
+
+/// Gets the majority timezone of a US state
+///
+/// # Arguments
+///
+/// * `state` - two letter state or territory abbreviation, case insensitive
+///
+/// # Returns
+///
+/// 'some' timezone for a known abbreviation, 'none' otherwise
+pub fn timezone_for_state(state: &str) -> Option<Tz> {
+    use chrono_tz::{ America, Pacific };
+
+    let tz = match state.trim().to_ascii_uppercase().as_str() {
+        "CT" | "DC" | "DE" | "FL" | "GA" | "KY" | "MA" | "MD" | "ME" | "NC" => America::New_York,
+        "NH" | "NJ" | "NY" | "OH" | "PA" | "RI" | "SC" | "VA" | "VT" | "WV" => America::New_York,
+        "IN" => America::Indiana::Indianapolis,
+        "MI" => America::Detroit,
+        "AL" | "AR" | "IA" | "IL" | "KS" | "LA" | "MN" | "MO" => America::Chicago,
+        "MS" | "ND" | "NE" | "OK" | "SD" | "TN" | "TX" | "WI" => America::Chicago,
+        "CO" | "ID" | "MT" | "NM" | "UT" | "WY" => America::Denver,
+        "AZ" => America::Phoenix,
+        "CA" | "NV" | "OR" | "WA" => America::Los_Angeles,
+        "AK" => America::Anchorage,
+        "HI" => Pacific::Honolulu,
+        "PR" => America::Puerto_Rico,
+        _ => {
+            return None;
+        }
+    };
+
+    Some(tz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_iana_names() {
+        assert_eq!(parse_timezone(" America/Chicago ").unwrap(), chrono_tz::America::Chicago);
+        assert!(matches!(parse_timezone("Central"), Err(AppError::ValidationError(_))));
+        assert!(parse_timezone("").is_err());
+    }
+
+    #[test]
+    fn derives_the_majority_zone_of_a_state() {
+        assert_eq!(timezone_for_state("wi"), Some(chrono_tz::America::Chicago));
+        assert_eq!(timezone_for_state(" NY "), Some(chrono_tz::America::New_York));
+        assert_eq!(timezone_for_state("AZ"), Some(chrono_tz::America::Phoenix));
+        assert_eq!(timezone_for_state("XX"), None);
+    }
+}
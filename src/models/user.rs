@@ -2,8 +2,13 @@ use async_graphql::{ Context, Object, ID, Result as GraphQLResult };
 use aws_sdk_dynamodb::types::AttributeValue;
 use chrono::{ DateTime, Utc };
 use serde::{ Deserialize, Serialize };
-use tracing::info;
-use std::collections::HashMap;
+use tracing::debug;
+
+use crate::db::attributes::{ get_bool, get_opt_datetime, get_opt_i64, get_opt_str, get_str };
+use crate::db::logging::redact_item;
+use crate::error::AppError;
+use crate::models::normalize::normalize_email;
+use std::{ collections::HashMap, env, sync::OnceLock };
 use argon2::{
     password_hash::{
         rand_core::OsRng,
@@ -16,6 +21,140 @@ use argon2::{
     Argon2,
 };
 
+static PASSWORD_PEPPER: OnceLock<Option<String>> = OnceLock::new();
+
+/// Gets the application side secret mixed into every password, read once from the
+/// `PASSWORD_PEPPER` env var
+///
+/// The pepper lives only in the app's environment, so hashes leaked from the database
+/// alone can't be cracked offline. Hashes don't record which pepper they were made
+/// with. Hashes made before a pepper was set still verify, see `verify_password_hash`,
+/// but changing or removing the pepper makes every peppered password fail to verify,
+/// so rotating it means resetting all passwords. To rotate without that, store a pepper
+/// version next to `password_hash` and keep the old pepper until each user has logged
+/// in and been rehashed.
+///
+/// # Returns
+///
+/// 'some' pepper if the var is set and not blank, 'none' otherwise
+fn password_pepper() -> Option<&'static str> {
+    PASSWORD_PEPPER.get_or_init(|| {
+        env::var("PASSWORD_PEPPER")
+            .ok()
+            .filter(|pepper| !pepper.trim().is_empty())
+    }).as_deref()
+}
+
+/// Gets the bytes hashed for a password, the password followed by the pepper if one is
+/// given, see `password_pepper`
+fn peppered(password: &str, pepper: Option<&str>) -> Vec<u8> {
+    let mut bytes = password.as_bytes().to_vec();
+    if let Some(pepper) = pepper {
+        bytes.extend_from_slice(pepper.as_bytes());
+    }
+    bytes
+}
+
+/// Hashes a password with a fresh salt
+///
+/// # Arguments
+///
+/// * `password` - the plain text password
+/// * `pepper` - the secret appended to the password, see `password_pepper`
+///
+/// # Returns
+///
+/// The Argon2 hash in PHC string format, as stored in `password_hash`
+///
+/// # Errors
+///
+/// Returns a message if Argon2 fails to hash the password
+fn hash_password(password: &str, pepper: Option<&str>) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(&peppered(password, pepper), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Failed to hash password: {}", e))
+}
+
+/// Checks a password against a stored hash
+///
+/// With a pepper, the password is checked with the pepper first and then on its own,
+/// so hashes made before `PASSWORD_PEPPER` was set keep verifying.
+///
+/// # Arguments
+///
+/// * `password_hash` - the stored hash, see `hash_password`
+/// * `password` - the plain text password to check
+/// * `pepper` - the secret appended to the password, see `password_pepper`
+///
+/// # Returns
+///
+/// True if the password matches, false if it doesn't or the hash can't be parsed
+fn verify_password_hash(password_hash: &str, password: &str, pepper: Option<&str>) -> bool {
+    let parsed_hash = match PasswordHash::new(password_hash) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return false;
+        }
+    };
+    let matches = |pepper| {
+        Argon2::default().verify_password(&peppered(password, pepper), &parsed_hash).is_ok()
+    };
+
+    matches(pepper) || (pepper.is_some() && matches(None))
+}
+
+/// Value of the `entity_type` attribute written on every user item
+///
+/// It is the partition key of the Users `CreatedAtIndex` GSI, see `ensure_table_exists::users`
+pub const USER_ENTITY_TYPE: &str = "USER";
+
+/// Prefix of the `id` of email ownership items in the Users table
+///
+/// GSIs don't enforce uniqueness, so each user's email is claimed by an item with id
+/// `EMAIL#<email>`, written in the same transaction as the user. These items carry only
+/// `id` and `user_id`, so they stay out of the EmailIndex and CreatedAtIndex, but scans
+/// of the Users table have to skip them.
+pub const EMAIL_OWNER_PREFIX: &str = "EMAIL#";
+
+/// Gets the id of the item claiming an email address
+pub fn email_owner_id(email: &str) -> String {
+    format!("{}{}", EMAIL_OWNER_PREFIX, normalize_email(email))
+}
+
+/// GraphQL fields of User paired with the item attribute each one reads
+///
+/// Used to build projection expressions, see `db::projection`
+pub const USER_FIELD_ATTRIBUTES: &[(&str, &str)] = &[
+    ("id", "id"),
+    ("email", "email"),
+    ("firstName", "first_name"),
+    ("lastName", "last_name"),
+    ("role", "role"),
+    ("pantryId", "pantry_id"),
+    ("createdAt", "created_at"),
+    ("updatedAt", "updated_at"),
+    ("deletedAt", "deleted_at"),
+    ("isActive", "is_active"),
+];
+
+/// Reads the stored `is_active` flag, users saved before the flag existed are active
+pub fn is_active_attribute(item: &HashMap<String, AttributeValue>) -> bool {
+    item.get("is_active").and_then(|v| v.as_s().ok()).is_none_or(|s| s != "false")
+}
+
+/// Reads the stored `token_version`, users whose sessions were never revoked are at 0
+pub fn token_version_attribute(item: &HashMap<String, AttributeValue>) -> i64 {
+    get_opt_i64(item, "token_version").ok().flatten().unwrap_or_default()
+}
+
+/// Serde default of `User::is_active`
+fn active_by_default() -> bool {
+    true
+}
+
 /// Represents user in system
 ///
 /// # Fields
@@ -28,8 +167,13 @@ use argon2::{
 /// * `pantry_id` - ID of food pantry table row where user is agent
 /// * `created_at` - Date and time of creation
 /// * `updated_at` - Date and Time of creation
+/// * `deleted_at` - Date and time the user was soft deleted, e.g. merged into another user
+/// * `is_active` - false while an Admin has deactivated the user, which blocks login but
+///   keeps the account intact
+/// * `password_changed_at` - when the password was last changed, tokens issued before it are rejected
+/// * `token_version` - bumped to revoke every session, tokens issued at an older version are rejected
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: String,
     pub email: String,
@@ -37,9 +181,17 @@ pub struct User {
     pub first_name: String,
     pub last_name: String,
     pub role: String,
+    pub pantry_id: Option<String>,
 
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default = "active_by_default")]
+    pub is_active: bool,
+    #[serde(default)]
+    pub password_changed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub token_version: i64,
 }
 
 /// Defines methods for User
@@ -52,6 +204,7 @@ impl User {
     /// * `email` - user email address
     /// * `password` - user password
     /// * `first_name` - user's first name
+    /// * `role` - user's own role, the name of an AccessLevel
     /// * `last_name` - user's last name
     ///
     /// # Returns
@@ -68,17 +221,8 @@ impl User {
     ) -> Result<Self, String> {
         let now = Utc::now();
 
-        // Generate a salt for password
-        let salt = SaltString::generate(&mut OsRng);
-
-        // Configure Argon2 with default parameters
-        let argon2 = Argon2::default();
-
         // hash password
-        let password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| format!("Failed to hash password: {}", e))?
-            .to_string();
+        let password_hash = hash_password(password, password_pepper())?;
 
         Ok(Self {
             id,
@@ -87,8 +231,13 @@ impl User {
             first_name,
             last_name,
             role,
+            pantry_id: None,
             created_at: now,
             updated_at: now,
+            deleted_at: None,
+            is_active: true,
+            password_changed_at: None,
+            token_version: 0,
         })
     }
     /// Creates User instance from DynamoDB item
@@ -99,57 +248,124 @@ impl User {
     ///
     /// # Returns
     ///
-    /// 'some' User if item fields match, 'none' otherwise
+    /// 'some' User if item fields match, 'none' otherwise, see `try_from_item` for why
 
     pub fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
-        info!("calling from_item with: {:?}", &item);
+        debug!("calling from_item with: {:?}", redact_item(item));
+
+        let res = Self::try_from_item(item)
+            .map_err(|e| debug!("User item could not be read: {}", e))
+            .ok();
+
+        debug!("result of from_item: {:?}", &res);
+        res
+    }
 
-        let id = item.get("id")?.as_s().ok()?.to_string();
-        info!("got id: {}", id);
+    /// Creates User instance from DynamoDB item
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The dynamo db item
+    ///
+    /// # Errors
+    ///
+    /// Returns Database Error (500) App error variant naming the first attribute that is
+    /// missing or can't be read
+    pub fn try_from_item(item: &HashMap<String, AttributeValue>) -> Result<Self, AppError> {
+        let id = get_str(item, "id")?;
 
-        let email = item.get("email")?.as_s().ok()?.to_string();
-        info!("got email: {}", email);
+        let email = get_str(item, "email")?;
 
-        let password_hash = item.get("password_hash")?.as_s().ok()?.to_string();
-        info!("got password hash");
+        let password_hash = get_str(item, "password_hash")?;
 
-        let first_name = item.get("first_name")?.as_s().ok()?.to_string();
-        info!("got first_name: {}", first_name);
+        let first_name = get_str(item, "first_name")?;
 
-        let last_name = item.get("last_name")?.as_s().ok()?.to_string();
-        info!("got last_name: {}", last_name);
+        let last_name = get_str(item, "last_name")?;
 
-        let role = item.get("role")?.as_s().ok()?.to_string();
+        let role = get_str(item, "role")?;
 
-        let created_at = item
-            .get("created_at")
-            .and_then(|v| v.as_s().ok())
-            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
-            .unwrap_or_else(|| Utc::now());
+        let pantry_id = get_opt_str(item, "pantry_id")?;
 
-        let updated_at = item
-            .get("updated_at")
-            .and_then(|v| v.as_s().ok())
-            .and_then(|s| s.parse::<DateTime<Utc>>().ok())
-            .unwrap_or_else(|| Utc::now());
+        let created_at = get_opt_datetime(item, "created_at")?.unwrap_or_else(Utc::now);
 
-        let res = Some(Self {
+        let updated_at = get_opt_datetime(item, "updated_at")?.unwrap_or_else(Utc::now);
+
+        let deleted_at = get_opt_datetime(item, "deleted_at")?;
+
+        let password_changed_at = get_opt_datetime(item, "password_changed_at")?;
+
+        Ok(Self {
             id,
             email,
             password_hash,
             first_name,
             last_name,
             role,
+            pantry_id,
             created_at,
             updated_at,
-        });
+            deleted_at,
+            is_active: get_bool(item, "is_active", true)?,
+            password_changed_at,
+            token_version: token_version_attribute(item),
+        })
+    }
 
-        info!("result of from_item: {:?}", &res);
-        res
+    /// Creates User instance from a DynamoDB item read with a projection expression
+    ///
+    /// Only `id` is required, absent attributes are left empty. The result is only fit
+    /// for resolving the GraphQL fields that were projected, never for saving back or
+    /// for checking a password.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The partial dynamo db item
+    ///
+    /// # Returns
+    ///
+    /// 'some' User if the item has an id, 'none' otherwise
+    pub fn from_projected_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+        let string = |name: &str| {
+            item.get(name)
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        let timestamp = |name: &str| {
+            item.get(name)
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+                .unwrap_or_default()
+        };
+
+        Some(Self {
+            id: item.get("id")?.as_s().ok()?.to_string(),
+            email: string("email"),
+            password_hash: String::new(),
+            first_name: string("first_name"),
+            last_name: string("last_name"),
+            role: string("role"),
+            pantry_id: item.get("pantry_id").and_then(|v| v.as_s().ok()).cloned(),
+            created_at: timestamp("created_at"),
+            updated_at: timestamp("updated_at"),
+            deleted_at: item
+                .get("deleted_at")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+            is_active: is_active_attribute(item),
+            password_changed_at: item
+                .get("password_changed_at")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| s.parse::<DateTime<Utc>>().ok()),
+            token_version: token_version_attribute(item),
+        })
     }
 
     /// Creates DynamoDB item from User instance
     ///
+    /// Does not modify `updated_at`, call `touch` first when saving changes
+    ///
     /// # Arguments
     ///
     /// * `self` - borrowed instance of self
@@ -167,9 +383,40 @@ impl User {
         item.insert("first_name".to_string(), AttributeValue::S(self.first_name.clone()));
         item.insert("last_name".to_string(), AttributeValue::S(self.last_name.clone()));
         item.insert("role".to_string(), AttributeValue::S(self.role.to_string()));
+
+        // pantry_id is optional, the field will not be created in the db item if not present on struct
+        if let Some(pantry_id) = &self.pantry_id {
+            item.insert("pantry_id".to_string(), AttributeValue::S(pantry_id.clone()));
+        }
+
         item.insert("created_at".to_string(), AttributeValue::S(self.created_at.to_string()));
         item.insert("updated_at".to_string(), AttributeValue::S(self.updated_at.to_string()));
 
+        // deleted_at is only present on soft deleted users
+        if let Some(deleted_at) = &self.deleted_at {
+            item.insert("deleted_at".to_string(), AttributeValue::S(deleted_at.to_string()));
+        }
+
+        item.insert("is_active".to_string(), AttributeValue::S(self.is_active.to_string()));
+
+        // password_changed_at is only present once the password has been changed
+        if let Some(password_changed_at) = &self.password_changed_at {
+            item.insert(
+                "password_changed_at".to_string(),
+                AttributeValue::S(password_changed_at.to_string())
+            );
+        }
+
+        // token_version is only present once sessions have been revoked
+        if self.token_version > 0 {
+            item.insert(
+                "token_version".to_string(),
+                AttributeValue::N(self.token_version.to_string())
+            );
+        }
+
+        item.insert("entity_type".to_string(), AttributeValue::S(USER_ENTITY_TYPE.to_string()));
+
         item
     }
 
@@ -184,31 +431,49 @@ impl User {
     ///   HashMap representing DB item for Pantry instance
 
     pub fn verify_password(&self, password: &str) -> bool {
-        // parse password hash
-        let parsed_hash = match PasswordHash::new(&self.password_hash) {
-            Ok(hash) => hash,
-            Err(e) => {
-                return false;
-            }
-        };
-
-        Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
+        verify_password_hash(&self.password_hash, password, password_pepper())
     }
 
+    /// Hashes and sets a new password, marking when it changed
+    ///
+    /// Setting `password_changed_at` makes tokens issued before the change invalid, see
+    /// `auth::middleware::ensure_token_current`.
     pub fn update_password(&mut self, password: &str) -> Result<(), String> {
-        // generate salt
-        let salt = SaltString::generate(OsRng);
+        self.password_hash = hash_password(password, password_pepper())?;
 
-        let argon2 = Argon2::default();
+        self.touch();
+        self.password_changed_at = Some(self.updated_at);
 
-        self.password_hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| format!("Failed to hash password: {}", e))?
-            .to_string();
+        Ok(())
+    }
 
+    /// Marks the user as modified now
+    ///
+    /// Every write path that saves an existing user must call this before `to_item`,
+    /// so `updated_at` stays meaningful for reporting filters. `created_at` is never changed.
+    pub fn touch(&mut self) {
         self.updated_at = Utc::now();
+    }
+}
 
-        Ok(())
+// Debug by hand so the password hash never ends up in logs
+impl std::fmt::Debug for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("email", &self.email)
+            .field("password_hash", &"[REDACTED]")
+            .field("first_name", &self.first_name)
+            .field("last_name", &self.last_name)
+            .field("role", &self.role)
+            .field("pantry_id", &self.pantry_id)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .field("deleted_at", &self.deleted_at)
+            .field("is_active", &self.is_active)
+            .field("password_changed_at", &self.password_changed_at)
+            .field("token_version", &self.token_version)
+            .finish()
     }
 }
 
@@ -233,10 +498,48 @@ impl User {
     async fn role(&self) -> &str {
         &self.role
     }
+    async fn pantry_id(&self) -> Option<&str> {
+        self.pantry_id.as_deref()
+    }
     async fn created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
     async fn updated_at(&self) -> DateTime<Utc> {
         self.updated_at
     }
+    async fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        self.deleted_at
+    }
+    async fn is_active(&self) -> bool {
+        self.is_active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peppered_hashes_verify_only_with_the_pepper() {
+        let hash = hash_password("correct horse", Some("pepper")).unwrap();
+
+        assert!(verify_password_hash(&hash, "correct horse", Some("pepper")));
+        assert!(!verify_password_hash(&hash, "correct horse", None));
+        assert!(!verify_password_hash(&hash, "correct horse", Some("other pepper")));
+        assert!(!verify_password_hash(&hash, "wrong horse", Some("pepper")));
+    }
+
+    #[test]
+    fn hashes_made_before_the_pepper_still_verify() {
+        let hash = hash_password("correct horse", None).unwrap();
+
+        assert!(verify_password_hash(&hash, "correct horse", None));
+        assert!(verify_password_hash(&hash, "correct horse", Some("pepper")));
+        assert!(!verify_password_hash(&hash, "wrong horse", Some("pepper")));
+    }
+
+    #[test]
+    fn unparsable_hashes_never_verify() {
+        assert!(!verify_password_hash("not a hash", "", None));
+    }
 }
@@ -0,0 +1,174 @@
+//! Cross-check of an address's zipcode against its state.
+//!
+//! The first three digits of a ZIP code pick a sectional center facility, and every
+//! facility serves a single state, so a zipcode whose prefix belongs to another state
+//! means one of the two was mistyped. Prefixes are matched against a static table of
+//! USPS ranges; the few facilities that serve more than one state or territory list
+//! each of them.
+
+use crate::error::AppError;
+
+/// Three digit ZIP prefix ranges, inclusive, and the state abbreviations they serve
+///
+/// Ranges are sorted and don't overlap. Unassigned prefixes are left out, so a zipcode
+/// in a gap is never reported as a mismatch.
+const ZIP_PREFIX_STATES: &[(u16, u16, &[&str])] = &[
+    (5, 5, &["NY"]),
+    (6, 7, &["PR"]),
+    (8, 8, &["VI"]),
+    (9, 9, &["PR"]),
+    (10, 27, &["MA"]),
+    (28, 29, &["RI"]),
+    (30, 38, &["NH"]),
+    (39, 49, &["ME"]),
+    (50, 54, &["VT"]),
+    (55, 55, &["MA"]),
+    (56, 59, &["VT"]),
+    (60, 69, &["CT"]),
+    (70, 89, &["NJ"]),
+    (90, 98, &["AE"]),
+    (100, 149, &["NY"]),
+    (150, 196, &["PA"]),
+    (197, 199, &["DE"]),
+    (200, 200, &["DC"]),
+    (201, 201, &["VA"]),
+    (202, 205, &["DC"]),
+    (206, 219, &["MD"]),
+    (220, 246, &["VA"]),
+    (247, 268, &["WV"]),
+    (270, 289, &["NC"]),
+    (290, 299, &["SC"]),
+    (300, 319, &["GA"]),
+    (320, 339, &["FL"]),
+    (340, 340, &["AA"]),
+    (341, 349, &["FL"]),
+    (350, 369, &["AL"]),
+    (370, 385, &["TN"]),
+    (386, 397, &["MS"]),
+    (398, 399, &["GA"]),
+    (400, 427, &["KY"]),
+    (430, 459, &["OH"]),
+    (460, 479, &["IN"]),
+    (480, 499, &["MI"]),
+    (500, 528, &["IA"]),
+    (530, 549, &["WI"]),
+    (550, 567, &["MN"]),
+    (569, 569, &["DC"]),
+    (570, 577, &["SD"]),
+    (580, 588, &["ND"]),
+    (590, 599, &["MT"]),
+    (600, 629, &["IL"]),
+    (630, 658, &["MO"]),
+    (660, 679, &["KS"]),
+    (680, 693, &["NE"]),
+    (700, 714, &["LA"]),
+    (716, 729, &["AR"]),
+    (730, 732, &["OK"]),
+    (733, 733, &["TX"]),
+    (734, 749, &["OK"]),
+    (750, 799, &["TX"]),
+    (800, 816, &["CO"]),
+    (820, 831, &["WY"]),
+    (832, 838, &["ID"]),
+    (840, 847, &["UT"]),
+    (850, 865, &["AZ"]),
+    (870, 884, &["NM"]),
+    (885, 885, &["TX"]),
+    (889, 898, &["NV"]),
+    (900, 961, &["CA"]),
+    (962, 966, &["AP"]),
+    (967, 968, &["HI"]),
+    (969, 969, &["GU", "MP", "PW", "FM", "MH"]),
+    (970, 979, &["OR"]),
+    (980, 994, &["WA"]),
+    (995, 999, &["AK"]),
+];
+
+/// Gets the states a zipcode's prefix is assigned to
+///
+/// # Arguments
+///
+/// * `zipcode` - five digit ZIP code, optionally followed by `-` and the ZIP+4 digits
+///
+/// # Returns
+///
+/// 'some' state abbreviations if the prefix is assigned, 'none' for an unassigned prefix
+/// or a zipcode that doesn't start with five digits
+pub fn states_for_zipcode(zipcode: &str) -> Option<&'static [&'static str]> {
+    let digits = zipcode.trim().get(..5)?;
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let prefix = digits[..3].parse::<u16>().ok()?;
+    ZIP_PREFIX_STATES.iter()
+        .find(|(first, last, _)| (*first..=*last).contains(&prefix))
+        .map(|(_, _, states)| *states)
+}
+
+/// Checks that a zipcode belongs to a state
+///
+/// Zipcodes the table can't place pass, only a known prefix in another state fails.
+///
+/// # Arguments
+///
+/// * `zipcode` - ZIP code of the address
+/// * `state` - two letter state abbreviation of the address, case insensitive
+///
+/// # Errors
+///
+/// Returns a ValidationError (400) App error variant if the zipcode's prefix is assigned
+/// to other states
+pub fn check_zipcode_state(zipcode: &str, state: &str) -> Result<(), AppError> {
+    let states = match states_for_zipcode(zipcode) {
+        Some(states) => states,
+        None => {
+            return Ok(());
+        }
+    };
+
+    let state = state.trim();
+    if states.iter().any(|expected| expected.eq_ignore_ascii_case(state)) {
+        return Ok(());
+    }
+
+    Err(
+        AppError::ValidationError(
+            format!(
+                "Zipcode {} is not in {}, it belongs to {}",
+                zipcode.trim(),
+                state,
+                states.join("/")
+            )
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_zipcodes_to_states() {
+        assert_eq!(states_for_zipcode("53703"), Some(&["WI"][..]));
+        assert_eq!(states_for_zipcode(" 53703-1234 "), Some(&["WI"][..]));
+        assert_eq!(states_for_zipcode("96910").map(|states| states.len()), Some(5));
+        assert_eq!(states_for_zipcode("00100"), None);
+        assert_eq!(states_for_zipcode("5370"), None);
+        assert_eq!(states_for_zipcode("5a703"), None);
+    }
+
+    #[test]
+    fn checks_the_state_of_a_zipcode() {
+        assert!(check_zipcode_state("53703", "wi").is_ok());
+        assert!(check_zipcode_state("96910", "GU").is_ok());
+        // zipcodes we have no prefix for can't be checked
+        assert!(check_zipcode_state("00100", "WI").is_ok());
+
+        match check_zipcode_state("53703", "MN") {
+            Err(AppError::ValidationError(message)) =>
+                assert_eq!(message, "Zipcode 53703 is not in MN, it belongs to WI"),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+}
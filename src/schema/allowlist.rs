@@ -0,0 +1,259 @@
+//! Allowlist of the operations a locked-down deployment serves.
+//!
+//! Setting `GRAPHQL_ALLOWLIST_FILE` to a JSON object mapping the SHA-256 hex hash of each
+//! allowed query document to the document itself, e.g. one generated from the frontend's
+//! operations at build time, turns the allowlist on. Every request must then be one of those
+//! documents, character for character; anything else is rejected as Forbidden before it
+//! is parsed.
+//!
+//! Clients can send just the hash, in the Apollo persisted query form
+//! `"extensions": { "persistedQuery": { "version": 1, "sha256Hash": "..." } }`, and the
+//! document is taken from the allowlist. Unlike plain APQ, unknown hashes are never
+//! registered, so a client can't add a query by sending it once.
+//!
+//! If the file can't be read or parsed the allowlist is empty and every request is
+//! rejected, the deployment fails closed rather than open.
+
+use std::{ collections::HashMap, env, fs, sync::Arc };
+
+use async_graphql::{
+    extensions::{ Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest },
+    Request,
+    ServerError,
+    ServerResult,
+};
+use sha2::{ Digest, Sha256 };
+use tracing::{ error, info, warn };
+
+use crate::error::AppError;
+
+/// Name of the request extension carrying a persisted query hash
+const PERSISTED_QUERY_EXTENSION: &str = "persistedQuery";
+
+/// Gets the SHA-256 hex hash a query document is allowlisted under
+pub fn query_hash(query: &str) -> String {
+    hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+/// Query documents allowed to run, keyed by their `query_hash`
+#[derive(Debug, Default)]
+pub struct OperationAllowlist {
+    queries: HashMap<String, String>,
+}
+
+impl OperationAllowlist {
+    /// Builds the allowlist from `hash -> query` pairs
+    ///
+    /// Pairs whose hash isn't the `query_hash` of their query are dropped with a warning,
+    /// so a stale manifest can't allow a document under another document's hash.
+    pub fn new(queries: HashMap<String, String>) -> Self {
+        let queries = queries
+            .into_iter()
+            .filter(|(hash, query)| {
+                let matches = hash.eq_ignore_ascii_case(&query_hash(query));
+                if !matches {
+                    warn!("Allowlisted query {} does not match its hash, skipping it", hash);
+                }
+                matches
+            })
+            .map(|(hash, query)| (hash.to_lowercase(), query))
+            .collect();
+
+        Self { queries }
+    }
+
+    /// Loads the allowlist from the file named by `GRAPHQL_ALLOWLIST_FILE`
+    ///
+    /// # Returns
+    ///
+    /// 'some' OperationAllowlist if `GRAPHQL_ALLOWLIST_FILE` is set, empty if the file can't
+    /// be loaded; 'none' when the allowlist is off
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("GRAPHQL_ALLOWLIST_FILE").ok()?.trim().to_string();
+        if path.is_empty() {
+            return None;
+        }
+
+        let queries = fs
+            ::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                serde_json::from_str::<HashMap<String, String>>(&contents).map_err(|e| e.to_string())
+            });
+
+        let allowlist = match queries {
+            Ok(queries) => Self::new(queries),
+            Err(e) => {
+                error!("Failed to load GRAPHQL_ALLOWLIST_FILE {}, rejecting every request: {}", path, e);
+                Self::default()
+            }
+        };
+
+        info!(path, operations = allowlist.queries.len(), "Operation allowlist enabled");
+        Some(allowlist)
+    }
+
+    /// Gets the allowlisted document with a hash
+    fn query(&self, hash: &str) -> Option<&str> {
+        self.queries.get(&hash.to_lowercase()).map(String::as_str)
+    }
+
+    /// Fills in or checks the query of a request against the allowlist
+    ///
+    /// A request with a persisted query hash and no query gets the allowlisted document;
+    /// one with both must send the document the hash names. A request with only a query
+    /// must send an allowlisted document.
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the operation isn't allowlisted
+    ///
+    /// Returns Validation Error (400) App error variant if the persisted query extension is
+    /// malformed or doesn't match the query sent with it
+    pub fn resolve(&self, mut request: Request) -> Result<Request, AppError> {
+        let not_allowed = || AppError::Forbidden("This operation is not allowed".to_string());
+
+        let hash = match request.extensions.remove(PERSISTED_QUERY_EXTENSION) {
+            Some(persisted) => {
+                let hash = persisted
+                    .into_json()
+                    .ok()
+                    .and_then(|value| value.get("sha256Hash")?.as_str().map(str::to_string))
+                    .ok_or_else(|| {
+                        AppError::ValidationError(
+                            "persistedQuery extension must have a sha256Hash".to_string()
+                        )
+                    })?;
+
+                if request.query.trim().is_empty() {
+                    request.query = self.query(&hash).ok_or_else(not_allowed)?.to_string();
+                    return Ok(request);
+                }
+
+                if !hash.eq_ignore_ascii_case(&query_hash(&request.query)) {
+                    return Err(
+                        AppError::ValidationError(
+                            "persistedQuery sha256Hash does not match the query".to_string()
+                        )
+                    );
+                }
+                hash
+            }
+            None => query_hash(&request.query),
+        };
+
+        match self.query(&hash) {
+            Some(_) => Ok(request),
+            None => Err(not_allowed()),
+        }
+    }
+}
+
+/// Rejects requests for operations missing from the `OperationAllowlist`
+///
+/// Registered by `build_schema` ahead of the other extensions, so a hash-only request
+/// has its query filled in before anything checks it.
+pub struct AllowlistedOperations(pub Arc<OperationAllowlist>);
+
+impl ExtensionFactory for AllowlistedOperations {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(AllowlistedOperationsExtension(Arc::clone(&self.0)))
+    }
+}
+
+struct AllowlistedOperationsExtension(Arc<OperationAllowlist>);
+
+#[async_trait::async_trait]
+impl Extension for AllowlistedOperationsExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>
+    ) -> ServerResult<Request> {
+        let request = self.0.resolve(request).map_err(|e| {
+            let error = e.to_graphql_error();
+            let mut server_error = ServerError::new(error.message, None);
+            server_error.extensions = error.extensions;
+            server_error
+        })?;
+
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::Value;
+    use serde_json::json;
+
+    use super::*;
+
+    const QUERY: &str = "{ pantries { nodes { id } } }";
+
+    fn allowlist() -> OperationAllowlist {
+        OperationAllowlist::new(
+            HashMap::from([
+                (query_hash(QUERY).to_uppercase(), QUERY.to_string()),
+                (query_hash("{ users { id } }"), "{ me { id } }".to_string()),
+            ])
+        )
+    }
+
+    fn persisted(query: &str, hash: &str) -> Request {
+        let mut request = Request::new(query);
+        request.extensions.insert(
+            PERSISTED_QUERY_EXTENSION.to_string(),
+            Value::from_json(json!({ "version": 1, "sha256Hash": hash })).unwrap()
+        );
+        request
+    }
+
+    #[test]
+    fn drops_entries_that_do_not_match_their_hash() {
+        assert_eq!(allowlist().queries.len(), 1);
+    }
+
+    #[test]
+    fn allows_allowlisted_documents() {
+        assert!(allowlist().resolve(Request::new(QUERY)).is_ok());
+        assert!(
+            matches!(allowlist().resolve(Request::new("{ me { id } }")), Err(AppError::Forbidden(_)))
+        );
+    }
+
+    #[test]
+    fn fills_in_the_document_of_a_hash_only_request() {
+        let request = allowlist().resolve(persisted("", &query_hash(QUERY))).unwrap();
+
+        assert_eq!(request.query, QUERY);
+        assert!(!request.extensions.contains_key(PERSISTED_QUERY_EXTENSION));
+        assert!(
+            matches!(
+                allowlist().resolve(persisted("", &query_hash("{ me { id } }"))),
+                Err(AppError::Forbidden(_))
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_a_hash_that_does_not_match_the_query() {
+        assert!(
+            matches!(
+                allowlist().resolve(persisted(QUERY, &query_hash("{ me { id } }"))),
+                Err(AppError::ValidationError(_))
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_a_persisted_query_without_a_hash() {
+        let mut request = Request::new(QUERY);
+        request.extensions.insert(
+            PERSISTED_QUERY_EXTENSION.to_string(),
+            Value::from_json(json!({ "version": 1 })).unwrap()
+        );
+
+        assert!(matches!(allowlist().resolve(request), Err(AppError::ValidationError(_))));
+    }
+}
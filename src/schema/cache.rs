@@ -0,0 +1,221 @@
+//! Short-lived cache of `pantries` pages.
+//!
+//! The pantry list is public, read far more often than it changes, and every page
+//! is a scan. Pages are kept for `PANTRY_LIST_CACHE_TTL_SECS` seconds (60 when unset,
+//! 0 disables the cache) and dropped whenever a mutation writes a pantry.
+//!
+//! The cache lives in the process, so every Lambda instance has its own and only sees
+//! its own writes; another instance may serve a page up to the TTL old.
+//!
+//! A read that started before a write may finish after the write emptied the cache, with
+//! a page from before the write. Every emptying starts a new generation, a lookup tells
+//! the reader the generation it missed in, and a page read in an older generation is
+//! never cached.
+//!
+//! Ages are measured with the time read from the schema's clock, see `context::now`.
+
+use std::{ collections::HashMap, env, sync::Mutex, time::Duration };
+
+use async_graphql::Context;
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use super::types::PantryConnection;
+
+/// Seconds a page is kept when `PANTRY_LIST_CACHE_TTL_SECS` is unset
+const DEFAULT_PANTRY_LIST_CACHE_TTL_SECS: u64 = 60;
+
+/// Most pages kept at once, the cache is emptied when it would grow past this
+const MAX_CACHED_PAGES: usize = 256;
+
+/// Outcome of looking a page up in the cache
+///
+/// # Variants
+///
+/// * `Hit` - the page cached less than the TTL ago
+/// * `Miss` - no fresh page, `generation` is passed back to `insert` with the page read instead
+#[derive(Debug)]
+pub enum CacheLookup {
+    Hit(PantryConnection),
+    Miss {
+        generation: u64,
+    },
+}
+
+/// Cached pages and the generation they were read in
+#[derive(Debug, Default)]
+struct CachedPages {
+    generation: u64,
+    pages: HashMap<String, (DateTime<Utc>, PantryConnection)>,
+}
+
+/// Cached pages of the `pantries` query, attached to the schema by `build_schema`
+#[derive(Debug)]
+pub struct PantryListCache {
+    ttl: Duration,
+    pages: Mutex<CachedPages>,
+}
+
+impl PantryListCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, pages: Mutex::new(CachedPages::default()) }
+    }
+
+    /// Creates the cache with the TTL read from `PANTRY_LIST_CACHE_TTL_SECS`
+    ///
+    /// Falls back to `DEFAULT_PANTRY_LIST_CACHE_TTL_SECS` if it is unset or not a number.
+    pub fn from_env() -> Self {
+        let secs = match env::var("PANTRY_LIST_CACHE_TTL_SECS") {
+            Ok(value) =>
+                value.trim().parse::<u64>().unwrap_or_else(|_| {
+                    warn!(
+                        "PANTRY_LIST_CACHE_TTL_SECS {:?} is not a number, using {}",
+                        value,
+                        DEFAULT_PANTRY_LIST_CACHE_TTL_SECS
+                    );
+                    DEFAULT_PANTRY_LIST_CACHE_TTL_SECS
+                }),
+            Err(_) => DEFAULT_PANTRY_LIST_CACHE_TTL_SECS,
+        };
+
+        Self::new(Duration::from_secs(secs))
+    }
+
+    /// Whether a page cached at `cached_at` is younger than the TTL at `now`
+    ///
+    /// A page cached after `now`, e.g. when the clock was set back, counts as stale.
+    fn is_fresh(&self, cached_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        (now - cached_at).to_std().is_ok_and(|age| age < self.ttl)
+    }
+
+    /// Gets a page cached less than the TTL before `now`, or the current generation on a miss
+    pub fn get(&self, key: &str, now: DateTime<Utc>) -> CacheLookup {
+        let cached = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+        match cached.pages.get(key).filter(|(cached_at, _)| self.is_fresh(*cached_at, now)) {
+            Some((_, page)) => CacheLookup::Hit(page.clone()),
+            None => CacheLookup::Miss { generation: cached.generation },
+        }
+    }
+
+    /// Caches a page, unless the cache is disabled or emptied since the page's lookup
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key the page was looked up with
+    /// * `page` - the page read after the miss
+    /// * `generation` - the generation returned by the miss
+    /// * `now` - the current time, from `context::now`
+    pub fn insert(&self, key: String, page: PantryConnection, generation: u64, now: DateTime<Utc>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut cached = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+        if cached.generation != generation {
+            return;
+        }
+
+        cached.pages.retain(|_, (cached_at, _)| self.is_fresh(*cached_at, now));
+        if cached.pages.len() >= MAX_CACHED_PAGES {
+            cached.pages.clear();
+        }
+        cached.pages.insert(key, (now, page));
+    }
+
+    /// Drops every cached page and starts a new generation
+    pub fn clear(&self) {
+        let mut cached = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+        cached.generation += 1;
+        cached.pages.clear();
+    }
+}
+
+/// Drops the cached pantry pages after a mutation wrote a pantry
+///
+/// Call it after every successful pantry write. Does nothing if the schema was built
+/// without a cache.
+pub fn invalidate_pantry_list(ctx: &Context<'_>) {
+    if let Some(cache) = ctx.data_opt::<PantryListCache>() {
+        cache.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::types::PageInfo;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn page(cursor: &str) -> PantryConnection {
+        PantryConnection {
+            nodes: Vec::new(),
+            page_info: PageInfo {
+                has_next_page: false,
+                has_previous_page: false,
+                start_cursor: None,
+                end_cursor: Some(cursor.to_string()),
+                page_size_clamped: false,
+            },
+        }
+    }
+
+    /// Gets the end cursor of a hit, 'none' on a miss
+    fn hit_cursor(lookup: CacheLookup) -> Option<String> {
+        match lookup {
+            CacheLookup::Hit(page) => page.page_info.end_cursor,
+            CacheLookup::Miss { .. } => None,
+        }
+    }
+
+    fn miss_generation(lookup: CacheLookup) -> u64 {
+        match lookup {
+            CacheLookup::Miss { generation } => generation,
+            CacheLookup::Hit(_) => panic!("expected a miss"),
+        }
+    }
+
+    #[test]
+    fn hits_within_the_ttl() {
+        let cache = PantryListCache::new(Duration::from_secs(60));
+        let generation = miss_generation(cache.get("first", at(0)));
+        cache.insert("first".to_string(), page("a"), generation, at(0));
+
+        assert_eq!(hit_cursor(cache.get("first", at(59))), Some("a".to_string()));
+        assert!(hit_cursor(cache.get("first", at(60))).is_none());
+        assert!(hit_cursor(cache.get("other", at(1))).is_none());
+    }
+
+    #[test]
+    fn misses_after_clear() {
+        let cache = PantryListCache::new(Duration::from_secs(60));
+        let generation = miss_generation(cache.get("first", at(0)));
+        cache.insert("first".to_string(), page("a"), generation, at(0));
+
+        cache.clear();
+
+        assert_eq!(miss_generation(cache.get("first", at(1))), generation + 1);
+    }
+
+    #[test]
+    fn drops_pages_read_before_a_clear() {
+        let cache = PantryListCache::new(Duration::from_secs(60));
+        let stale = miss_generation(cache.get("first", at(0)));
+
+        cache.clear();
+        cache.insert("first".to_string(), page("a"), stale, at(1));
+
+        assert!(hit_cursor(cache.get("first", at(2))).is_none());
+    }
+
+    #[test]
+    fn zero_ttl_disables_the_cache() {
+        let cache = PantryListCache::new(Duration::ZERO);
+        let generation = miss_generation(cache.get("first", at(0)));
+        cache.insert("first".to_string(), page("a"), generation, at(0));
+
+        assert!(hit_cursor(cache.get("first", at(0))).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+//! Typed access to the data attached to the schema.
+//!
+//! Caller claims are read with `auth::guard::require_claims`.
+
+use async_graphql::{ Context, Error };
+use aws_sdk_dynamodb::Client;
+use chrono::{ DateTime, Utc };
+use tracing::warn;
+
+use crate::{ clock::SharedClock, error::AppError, images::ImageStore, webhook::WebhookNotifier };
+
+/// Gets the DynamoDB client attached to the schema by `build_schema`
+///
+/// # Errors
+///
+/// Returns Internal Server Error (500) App error variant if the schema was built without a client
+pub fn db<'a>(ctx: &Context<'a>) -> Result<&'a Client, Error> {
+    ctx.data::<Client>().map_err(|e| {
+        warn!("Failed to get db_client from context: {:?}", e);
+        AppError::InternalServerError(
+            "Failed to access application db_client".to_string()
+        ).to_graphql_error()
+    })
+}
+
+/// Gets the image store attached to the schema by `build_schema`
+///
+/// # Errors
+///
+/// Returns Internal Server Error (500) App error variant if image uploads aren't configured,
+/// see `images`
+pub fn images<'a>(ctx: &Context<'a>) -> Result<&'a ImageStore, Error> {
+    ctx.data::<ImageStore>().map_err(|_| {
+        AppError::InternalServerError("Image uploads are not configured".to_string()).to_graphql_error()
+    })
+}
+
+/// Gets the webhook notifier attached to the schema by `build_schema`
+///
+/// # Returns
+///
+/// 'some' WebhookNotifier if webhooks are configured, 'none' otherwise, see `webhook`
+pub fn webhooks<'a>(ctx: &Context<'a>) -> Option<&'a WebhookNotifier> {
+    ctx.data_opt::<WebhookNotifier>()
+}
+
+/// Gets the current time from the clock attached to the schema by `build_schema`
+///
+/// Falls back to the system clock if the schema was built without one.
+pub fn now(ctx: &Context<'_>) -> DateTime<Utc> {
+    ctx.data_opt::<SharedClock>()
+        .map(|clock| clock.now())
+        .unwrap_or_else(Utc::now)
+}
@@ -0,0 +1,111 @@
+//! CSV exports for program staff.
+
+use crate::{ error::AppError, models::user::User };
+
+/// Columns of the user export, each the item attribute it is read from
+///
+/// `password_hash` is deliberately absent, it must never leave the db.
+pub const USER_EXPORT_COLUMNS: &[&str] = &[
+    "id",
+    "email",
+    "first_name",
+    "last_name",
+    "role",
+    "pantry_id",
+    "created_at",
+    "updated_at",
+    "deleted_at",
+    "is_active",
+];
+
+/// Writes users as a CSV document with a `USER_EXPORT_COLUMNS` header row
+///
+/// # Errors
+///
+/// Returns Internal Server Error (500) App error variant if the CSV can't be written
+pub fn users_to_csv(users: &[User]) -> Result<String, AppError> {
+    let csv_error = |e: csv::Error| {
+        AppError::InternalServerError(format!("Failed to write users CSV: {}", e))
+    };
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(USER_EXPORT_COLUMNS).map_err(csv_error)?;
+
+    for user in users {
+        writer
+            .write_record([
+                user.id.clone(),
+                user.email.clone(),
+                user.first_name.clone(),
+                user.last_name.clone(),
+                user.role.clone(),
+                user.pantry_id.clone().unwrap_or_default(),
+                user.created_at.to_rfc3339(),
+                user.updated_at.to_rfc3339(),
+                user.deleted_at.map(|at| at.to_rfc3339()).unwrap_or_default(),
+                user.is_active.to_string(),
+            ])
+            .map_err(csv_error)?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| {
+            AppError::InternalServerError(format!("Failed to write users CSV: {}", e))
+        })?;
+
+    String::from_utf8(bytes).map_err(|e| {
+        AppError::InternalServerError(format!("Users CSV is not valid UTF-8: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn user() -> User {
+        let created_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        User {
+            id: "user-1".to_string(),
+            email: "ann@example.com".to_string(),
+            password_hash: "$argon2id$v=19$secret-hash".to_string(),
+            first_name: "Ann".to_string(),
+            last_name: "Lee, Jr.".to_string(),
+            role: "User".to_string(),
+            pantry_id: Some("pantry-1".to_string()),
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+            is_active: true,
+            password_changed_at: None,
+            token_version: 0,
+        }
+    }
+
+    #[test]
+    fn writes_a_header_and_a_row_per_user() {
+        let csv = users_to_csv(&[user()]).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "id,email,first_name,last_name,role,pantry_id,created_at,updated_at,deleted_at,is_active"
+        );
+        assert_eq!(
+            lines[1],
+            "user-1,ann@example.com,Ann,\"Lee, Jr.\",User,pantry-1,\
+             2023-11-14T22:13:20+00:00,2023-11-14T22:13:20+00:00,,true"
+        );
+    }
+
+    #[test]
+    fn never_exports_password_hashes() {
+        let csv = users_to_csv(&[user()]).unwrap();
+
+        assert!(!csv.contains("password_hash"));
+        assert!(!csv.contains("argon2"));
+        assert!(!csv.contains("secret-hash"));
+    }
+}
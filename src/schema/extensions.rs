@@ -0,0 +1,157 @@
+//! async-graphql extensions applied to the schema.
+
+use std::sync::Arc;
+
+use async_graphql::{
+    extensions::{
+        Extension,
+        ExtensionContext,
+        ExtensionFactory,
+        NextParseQuery,
+        NextPrepareRequest,
+        NextRequest,
+        NextValidation,
+    },
+    parser::types::ExecutableDocument,
+    Request,
+    Response,
+    ServerError,
+    ServerResult,
+    ValidationResult,
+    Value,
+    Variables,
+};
+
+use crate::locale::Locale;
+
+/// Gives parse and validation errors the same `extensions` shape as `AppError::ValidationError`
+///
+/// async-graphql reports malformed queries and variables that can't be coerced to
+/// their declared types without our `code`/`status` extensions, which the frontend
+/// keys its error handling on. This tags them as `VALIDATION_ERROR` / `400`.
+///
+/// An empty or whitespace-only query is rejected up front with a plain message instead
+/// of a parse error, and an `operationName` that matches no operation of the document
+/// is tagged the same way.
+pub struct NormalizeRequestErrors;
+
+impl ExtensionFactory for NormalizeRequestErrors {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(NormalizeRequestErrorsExtension)
+    }
+}
+
+struct NormalizeRequestErrorsExtension;
+
+/// Adds the validation error code and status to an error unless it already carries a code
+fn as_validation_error(mut error: ServerError) -> ServerError {
+    let extensions = error.extensions.get_or_insert_with(Default::default);
+
+    if extensions.get("code").is_none() {
+        extensions.set("code", "VALIDATION_ERROR");
+        extensions.set("status", 400);
+    }
+
+    error
+}
+
+/// Whether an error comes from picking the operation to run, e.g. an unknown `operationName`
+///
+/// async-graphql reports these after validation, outside any hook that sees them alone,
+/// so they are recognized by their messages.
+fn is_operation_selection_error(error: &ServerError) -> bool {
+    error.message.starts_with("Unknown operation named") ||
+        error.message == "Operation name required in request."
+}
+
+#[async_trait::async_trait]
+impl Extension for NormalizeRequestErrorsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+
+        response.errors = response.errors
+            .into_iter()
+            .map(|error| {
+                if is_operation_selection_error(&error) { as_validation_error(error) } else { error }
+            })
+            .collect();
+
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>
+    ) -> ServerResult<Request> {
+        if request.query.trim().is_empty() {
+            return Err(as_validation_error(ServerError::new("Query is empty", None)));
+        }
+
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>
+    ) -> ServerResult<ExecutableDocument> {
+        next.run(ctx, query, variables).await.map_err(as_validation_error)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        next.run(ctx).await.map_err(|errors| errors.into_iter().map(as_validation_error).collect())
+    }
+}
+
+/// Translates Validation and Not Found error messages into the request's `Locale`
+///
+/// The entrypoints attach the locale picked from `Accept-Language` to the request data;
+/// without one, or for messages missing from the catalog, errors are left in English.
+/// Only the message changes, `code` and `status` stay as they are.
+pub struct LocalizeErrors;
+
+impl ExtensionFactory for LocalizeErrors {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(LocalizeErrorsExtension)
+    }
+}
+
+struct LocalizeErrorsExtension;
+
+/// Whether an error's message is meant for the user, going by its `code` extension
+fn is_user_facing(error: &ServerError) -> bool {
+    error.extensions
+        .as_ref()
+        .and_then(|extensions| extensions.get("code"))
+        .is_some_and(|code| {
+            matches!(code, Value::String(code) if code == "VALIDATION_ERROR" || code == "NOT_FOUND")
+        })
+}
+
+#[async_trait::async_trait]
+impl Extension for LocalizeErrorsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let mut response = next.run(ctx).await;
+
+        let locale = ctx.data_opt::<Locale>().copied().unwrap_or_default();
+        if locale == Locale::En {
+            return response;
+        }
+
+        for error in response.errors.iter_mut().filter(|error| is_user_facing(error)) {
+            if let Some(message) = locale.translate(&error.message) {
+                error.message = message;
+            }
+        }
+
+        response
+    }
+}
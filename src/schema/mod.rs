@@ -1,16 +1,281 @@
+pub mod allowlist;
+pub mod cache;
+pub mod context;
+pub mod export;
+pub mod extensions;
 pub mod mutation;
 pub mod query;
 pub mod types;
 
-use async_graphql::{ EmptySubscription, Schema, SchemaBuilder };
+use async_graphql::{ EmptySubscription, ObjectType, Request, Response, Schema, SchemaBuilder };
 
 use aws_sdk_dynamodb::Client;
-pub use query::QueryRoot;
+use std::sync::Arc;
+
+use crate::clock::{ SharedClock, SystemClock };
+use crate::images::ImageStore;
+use crate::webhook::WebhookNotifier;
+use allowlist::{ AllowlistedOperations, OperationAllowlist };
+use cache::PantryListCache;
+pub use query::{ DebugQuery, DebugQueryRoot, QueryRoot };
 pub use mutation::MutationRoot;
 pub use types::*;
 
-pub type AppSchema = Schema<EmptySubscription, MutationRoot, QueryRoot>;
+/// Maximum complexity a single request may have before it is rejected
+///
+/// Fields cost 1 plus their children by default, paginated fields cost their page
+/// size times their children, and table scans add `SCAN_COMPLEXITY` (see `query.rs`).
+/// The budget fits a full page of any type plus a few scan-backed counts, but not a
+/// query stacking several scans and large pages.
+pub const MAX_QUERY_COMPLEXITY: usize = 5000;
+
+/// The schema served by both entrypoints
+///
+/// The query root is picked when the schema is built, so debug-only queries are only part
+/// of the schema, its introspection and its SDL when `ENABLE_DEBUG_QUERIES` is set, see
+/// `query::debug_queries_enabled`.
+///
+/// # Variants
+///
+/// * `Standard` - the schema without debug queries
+/// * `Debug` - the schema with `DebugQuery` merged into the query root
+#[derive(Clone)]
+pub enum AppSchema {
+    Standard(Schema<QueryRoot, MutationRoot, EmptySubscription>),
+    Debug(Schema<DebugQueryRoot, MutationRoot, EmptySubscription>),
+}
+
+impl AppSchema {
+    /// Executes a request against whichever schema was built
+    pub async fn execute(&self, request: impl Into<Request>) -> Response {
+        match self {
+            AppSchema::Standard(schema) => schema.execute(request).await,
+            AppSchema::Debug(schema) => schema.execute(request).await,
+        }
+    }
+}
+
+/// Builds the schema served by both entrypoints
+///
+/// The DynamoDB client is the only state shared between requests. It is cheap to clone
+/// and safe to use concurrently, so it is stored as schema data and read by resolvers
+/// with `context::db`, there is no app state struct or lock around it. The clock read
+/// by `context::now` is attached the same way, as is the image store read by
+/// `context::images` when uploads are configured, and the `pantries` page cache, see `cache`.
+/// The webhook notifier read by `context::webhooks` is attached when webhooks are configured,
+/// so writes mark their outbox events for delivery, see `webhook`.
+///
+/// When `GRAPHQL_ALLOWLIST_FILE` is set only the operations it lists are served, see `allowlist`.
+/// Error messages are translated into the `Locale` the entrypoints attach to each request.
+/// Debug-only queries are merged in when `ENABLE_DEBUG_QUERIES` is set, see `AppSchema`.
+pub fn build_schema(
+    db_client: &Client,
+    image_store: Option<ImageStore>,
+    webhook: Option<WebhookNotifier>
+) -> AppSchema {
+    if query::debug_queries_enabled() {
+        let builder = Schema::build(
+            DebugQueryRoot(QueryRoot, DebugQuery),
+            MutationRoot,
+            EmptySubscription
+        );
+        return AppSchema::Debug(configure(builder, db_client, image_store, webhook));
+    }
+
+    let builder = Schema::build(QueryRoot, MutationRoot, EmptySubscription);
+    AppSchema::Standard(configure(builder, db_client, image_store, webhook))
+}
+
+/// Attaches the shared data and extensions to a schema, whichever its query root, see
+/// `build_schema`
+fn configure<Query: ObjectType + 'static>(
+    builder: SchemaBuilder<Query, MutationRoot, EmptySubscription>,
+    db_client: &Client,
+    image_store: Option<ImageStore>,
+    webhook: Option<WebhookNotifier>
+) -> Schema<Query, MutationRoot, EmptySubscription> {
+    let mut builder = builder
+        .data(db_client.clone())
+        .data::<SharedClock>(Arc::new(SystemClock))
+        .data(PantryListCache::from_env());
+
+    if let Some(image_store) = image_store {
+        builder = builder.data(image_store);
+    }
+
+    if let Some(webhook) = webhook {
+        builder = builder.data(webhook);
+    }
+
+    // outermost, so hash-only requests get their query before it is checked
+    if let Some(allowlist) = OperationAllowlist::from_env() {
+        builder = builder.extension(AllowlistedOperations(Arc::new(allowlist)));
+    }
+
+    builder
+        .extension(extensions::LocalizeErrors)
+        .extension(extensions::NormalizeRequestErrors)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// Writes the GraphQL schema as SDL to a file, for frontend codegen in CI
+///
+/// The schema is built without any context data, so this needs no DynamoDB connection.
+/// It is the schema served with debug queries off, `debugClaims` is never included.
+///
+/// # Arguments
+///
+/// * `path` - file to write the SDL to
+///
+/// # Errors
+///
+/// Returns an io error if the file can't be written
+pub fn emit_sdl(path: &str) -> std::io::Result<()> {
+    let sdl = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish().sdl();
+    std::fs::write(path, sdl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{ Value, Variables };
+    use aws_config::{ BehaviorVersion, Region };
+
+    fn standard_sdl() -> String {
+        Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish().sdl()
+    }
+
+    /// Builds the standard schema around a client that is never connected, for requests
+    /// rejected before any resolver runs
+    fn offline_schema() -> Schema<QueryRoot, MutationRoot, EmptySubscription> {
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build();
+        let builder = Schema::build(QueryRoot, MutationRoot, EmptySubscription);
+        configure(builder, &Client::from_conf(config), None, None)
+    }
+
+    /// Gets the `code` extension of each error of a response
+    fn error_codes(response: &Response) -> Vec<Option<Value>> {
+        response.errors
+            .iter()
+            .map(|error| {
+                error.extensions
+                    .as_ref()
+                    .and_then(|extensions| extensions.get("code"))
+                    .cloned()
+            })
+            .collect()
+    }
+
+    fn validation_error() -> Option<Value> {
+        Some(Value::from("VALIDATION_ERROR"))
+    }
+
+    #[tokio::test]
+    async fn malformed_queries_and_variables_are_validation_errors() {
+        let schema = offline_schema();
+
+        let unparsable = schema.execute("{ pantries {").await;
+        assert_eq!(error_codes(&unparsable), [validation_error()]);
+
+        let query = "query($page: PaginationInput) { pantries(page: $page) { nodes { id } } }";
+        let variables = Variables::from_json(serde_json::json!({ "page": { "first": "ten" } }));
+        let uncoercible = schema.execute(Request::new(query).variables(variables)).await;
+        assert!(!uncoercible.errors.is_empty());
+        assert!(error_codes(&uncoercible).iter().all(|code| *code == validation_error()));
+    }
+
+    // deletes return a DeletePayload, creates, updates and logins the full entity
+    #[test]
+    fn mutations_return_payloads_and_full_entities() {
+        let sdl = standard_sdl();
+
+        for signature in [
+            "deleteUser(email: String!): DeletePayload!",
+            "login(email: String!, password: String!): LoginPayload!",
+            "changePassword(currentPassword: String!, newPassword: String!): LoginPayload!",
+            "createUser(input: CreateUserInput!): User!",
+            "updateUser(id: String!, input: UpdateUserInput!): User!",
+            "updatePantry(id: String!, input: UpdatePantryInput!): Pantry!",
+            "setPantryImageUrl(pantryId: String!, imageUrl: String): Pantry!",
+            "clearInvalidPantryAssociation(userId: String!): ClearPantryAssociationPayload!",
+        ] {
+            assert!(sdl.contains(signature), "missing {}", signature);
+        }
+        assert!(sdl.contains("createPantry(input: CreatePantryInput!"));
+
+        assert!(sdl.contains("type DeletePayload {\n\tid: String!\n\tdeleted: Boolean!\n}"));
+        let clear_payload =
+            "type ClearPantryAssociationPayload {\n\tcleared: Boolean!\n\tuser: User!\n}";
+        assert!(sdl.contains(clear_payload));
+        assert!(
+            sdl.contains(
+                "type LoginPayload {\n\ttoken: String!\n\texpiresAt: DateTime!\n\tuser: User!\n}"
+            )
+        );
+    }
+
+    #[test]
+    fn debug_claims_is_only_in_the_debug_schema() {
+        let standard = Schema::build(QueryRoot, MutationRoot, EmptySubscription).finish().sdl();
+        let debug = Schema::build(
+            DebugQueryRoot(QueryRoot, DebugQuery),
+            MutationRoot,
+            EmptySubscription
+        )
+            .finish()
+            .sdl();
+
+        assert!(!standard.contains("debugClaims"));
+        assert!(debug.contains("debugClaims"));
+        assert!(debug.contains("pantries("));
+    }
+
+    #[tokio::test]
+    async fn queries_over_the_complexity_limit_are_rejected() {
+        let schema = offline_schema();
+
+        // six scan-backed counts cost more than MAX_QUERY_COMPLEXITY
+        let query = "{
+            a: usersCount b: usersCount c: usersCount
+            d: pantriesCount e: pantriesCount f: pantriesCount
+        }";
+        let expensive = schema.execute(query).await;
+        assert_eq!(error_codes(&expensive), [validation_error()]);
+        assert!(expensive.errors[0].message.contains("too complex"));
+
+        let cheap = schema.execute("{ __typename }").await;
+        assert!(cheap.errors.is_empty());
+        assert_eq!(cheap.data.to_string(), r#"{__typename: "QueryRoot"}"#);
+    }
+
+    #[tokio::test]
+    async fn empty_queries_and_unknown_operations_are_validation_errors() {
+        let schema = offline_schema();
+
+        for query in ["", "   \n"] {
+            let empty = schema.execute(query).await;
+            assert_eq!(error_codes(&empty), [validation_error()]);
+            assert_eq!(empty.errors[0].message, "Query is empty");
+        }
+
+        let request = Request::new("query Known { __typename }").operation_name("Unknown");
+        let unknown = schema.execute(request).await;
+        assert_eq!(error_codes(&unknown), [validation_error()]);
+        assert!(unknown.errors[0].message.starts_with("Unknown operation named"));
+    }
+
+    #[test]
+    fn emitted_sdl_parses_back() {
+        let path = std::env::temp_dir().join(format!("schema-{}.graphql", std::process::id()));
+        emit_sdl(path.to_str().unwrap()).unwrap();
+        let sdl = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
 
-pub fn build_schema(db_client: &Client) -> Schema<QueryRoot, MutationRoot, EmptySubscription> {
-    Schema::build(QueryRoot, MutationRoot, EmptySubscription).data(db_client.clone()).finish()
+        assert_eq!(sdl, standard_sdl());
+        async_graphql::parser::parse_schema(&sdl).expect("the emitted SDL should parse");
+    }
 }
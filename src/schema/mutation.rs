@@ -1,131 +1,1962 @@
+use std::collections::{ HashMap, HashSet };
+
 use async_graphql::{ Context, Object, Error };
-use aws_sdk_dynamodb::{ types::AttributeValue, Client };
-use tracing::{ info, warn };
-use crate::models::user::User;
+use aws_sdk_dynamodb::types::{
+    AttributeValue,
+    Delete,
+    Put,
+    TransactWriteItem,
+    Update,
+};
+use chrono::{ DateTime, Utc };
+use tokio::task::JoinSet;
+use tracing::{ debug, info, warn };
+use crate::{
+    auth::{
+        guard::{ is_admin, require_admin, require_claims, require_pantry_access },
+        jwt::create_token,
+    },
+    images::PresignedUpload,
+    db::{
+        batch::batch_get_items,
+        integrity::{ run_backfill, Backfill, BackfillReport },
+        outbox::outbox_put,
+        pantries::{
+            create_pantry,
+            get_pantry,
+            name_guard_move,
+            next_pantry_code,
+            set_opt_status,
+            update_pantry_with_event,
+        },
+        pantry_access::{ list_pantry_access, list_user_access },
+        throttle::acquire_bulk_write_permit,
+        transaction::any_condition_failed,
+        update_builder::{ FieldUpdate, UpdateBuilder },
+        users::{ create_user, delete_user, find_user_by_email, get_user },
+    },
+    models::{
+        image_url::{ parse_booking_url, parse_image_url },
+        language::parse_languages,
+        outbox::OutboxEvent,
+        pantry::{ name_search_bucket, name_search_key, OptStatus, OptStatusChange, Pantry },
+        pantry_access::{ AccessLevel, PantryAccess },
+        timezone::parse_timezone,
+        user::User,
+        zipcode::check_zipcode_state,
+    },
+};
 
 use uuid::Uuid;
 
 use crate::error::AppError;
 
+use super::cache::invalidate_pantry_list;
+use super::context::{ db, images, now, webhooks };
+
+use super::types::{
+    AccessGrantInput,
+    BatchResult,
+    ClearPantryAssociationPayload,
+    CreatePantryInput,
+    CreateUserInput,
+    DeletePayload,
+    FailedItem,
+    LoginPayload,
+    MergeUsersPayload,
+    UpdatePantryInput,
+    UpdateUserInput,
+};
+
+/// Maximum number of actions DynamoDB accepts in one `TransactWriteItems` request
+const TRANSACT_WRITE_LIMIT: usize = 100;
+
+/// Most pantries one `bulkSetOptStatus` call may change
+const BULK_OPT_STATUS_LIMIT: usize = 500;
+
+/// Pantries read and updated together in a `bulkSetOptStatus` call, the updates run concurrently
+const BULK_OPT_STATUS_CHUNK: usize = 25;
+
+/// Gets the pantry a user is associated with when that pantry no longer exists
+///
+/// # Arguments
+///
+/// * `user` - the user to check
+/// * `pantry_exists` - whether the pantry `user.pantry_id` names was found
+///
+/// # Returns
+///
+/// 'some' pantry ID if the association is dangling, 'none' otherwise
+fn dangling_pantry_id(user: &User, pantry_exists: bool) -> Option<String> {
+    user.pantry_id.clone().filter(|_| !pantry_exists)
+}
+
 // Mutation root
+//
+//  A resolver can be dropped at any await, e.g. when the client disconnects. No resolver
+//  holds a lock, and each DynamoDB request is either applied whole or not at all, so
+//  writes that must land together are sent as one `transact_write_items`. Mutations
+//  that send several requests can stop between them, leaving what was already sent:
+//  - createPantry: a code taken from the counter is never used, leaving a gap
+//  - deleteUser: a user whose email claim is held elsewhere may be left in place
+//  - bulkSetOptStatus: pantries already updated keep the new status, dropping the
+//    resolver aborts the pending per-pantry updates
+//  - setPantryAccess: with more than one transaction of grants, earlier ones stay applied
+//  - mergeUsers: with more than one transaction of access rows, moved rows stay moved;
+//    the users are updated in the last transaction, so the merge can be run again
 #[derive(Debug)]
 pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    // Creates new user in database
-    async fn create_user(
-        &self,
-        ctx: &Context<'_>,
-        email: String,
-        password: String,
-        pantry_name: String,
-        first_name: String,
-        last_name: String
-    ) -> Result<User, Error> {
-        // Transform context error into our AppError, then into GraphQL error
-        info!("creating new user: {}", email);
-        let db_client = ctx.data::<Client>().map_err(|e| {
-            warn!("Failed to get db_client from context: {:?}", e);
-            AppError::InternalServerError(
-                "Failed to access application db_client".to_string()
-            ).to_graphql_error()
-        })?;
+    /// Creates new user in database with the Viewer role
+    ///
+    /// Breaking change: the fields used to be positional arguments, which made it easy to
+    /// swap `firstName` and `lastName`. The unused `pantryName` argument was dropped, a user
+    /// is tied to a pantry with `updateUser`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client
+    ///
+    /// * `input` - fields of the new user
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the new user
+    ///
+    /// # Errors
+    ///
+    /// Returns Conflict Error (409) App error variant if a user with the email already exists
+    ///
+    /// Returns Database Error (500) App error variant if the password can't be hashed or the user saved
+    async fn create_user(&self, ctx: &Context<'_>, input: CreateUserInput) -> Result<User, Error> {
+        let input = input.normalized();
 
-        info!("successfully created db_client: {:?}", &db_client);
+        info!("creating new user: {}", input.email);
+        let db_client = db(ctx)?;
 
         let id = Uuid::new_v4().to_string();
 
         // Generate User struct instance from params
-        let user = User::new(id, email, &password, first_name, last_name, pantry_name).map_err(|e|
-            AppError::DatabaseError(e)
-        )?;
+        let user = User::new(
+            id,
+            input.email,
+            &input.password,
+            input.first_name,
+            AccessLevel::Viewer.to_str().to_string(),
+            input.last_name
+        ).map_err(|e| AppError::DatabaseError(e).to_graphql_error())?;
 
-        // Turn User struct into DynamoDB Item
-        let item = user.to_item();
-
-        let put_item_output = db_client
-            .put_item()
-            .table_name("Users")
-            .set_item(Some(item))
-            .send().await
-            .map_err(|err| {
-                warn!("Database error while creating user: {}", err);
-                AppError::DatabaseError(
-                    format!("Failed to create user: {}", err)
-                ).to_graphql_error()
-            });
-        info!("put_item_output: {:?}", &put_item_output);
+        // Save the user and claim its email in one transaction
+        create_user(db_client, &user).await.map_err(|e| e.to_graphql_error())?;
+
+        info!("created user: {}", user.id);
         Ok(user)
     }
 
     // login user using email and password
-    // async fn login(
-    //     &self,
-    //     ctx: &Context<'_>,
-    //     email: String,
-    //     password: String
-    // ) -> Result<String, Error> {
-    //     let user = self.user_by_email(ctx, email);
-    //     map_err(|e| {
-    //         return e;
-    //     })?;
-
-        
-    // }
-
-    // Remove user from database by email
-
-    /// Removes user from database using email and logged in status
-    /// 
-    /// # Arguments
-    /// 
-    /// * `ctx` - async-graphql Context object, contains dynamoDB client
-    /// 
-    /// * `email` - String representing email address of user to delete 
-    /// 
-    /// # Returns 
-    /// 
-    /// OK Result containing email address
-    /// 
-    /// # Errors
-    /// 
-    /// Returns an Internal Server Error (500) App error variant if db connection fails
-    /// 
-    /// Returns Database Error (500) App error variant if db.delete_item() fails 
-    
+    async fn login(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+        password: String
+    ) -> Result<LoginPayload, Error> {
+        let db_client = db(ctx)?;
+
+        // Same error for unknown email and wrong password so accounts can't be enumerated
+        let invalid_credentials = || {
+            AppError::Unauthorized("Invalid email or password".to_string()).to_graphql_error()
+        };
+
+        let user = find_user_by_email(db_client, &email).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(invalid_credentials)?;
+
+        // soft deleted users, e.g. merged into another account, can't log in
+        if user.deleted_at.is_some() || !user.verify_password(&password) {
+            return Err(invalid_credentials());
+        }
+
+        // only told after the password matched, so the flag can't be probed by email
+        if !user.is_active {
+            return Err(
+                AppError::Forbidden("This account has been deactivated".to_string()).to_graphql_error()
+            );
+        }
+
+        let issued = create_token(
+            &user.id,
+            &user.email,
+            &user.role,
+            user.password_changed_at,
+            user.token_version,
+            now(ctx)
+        ).map_err(|e| e.to_graphql_error())?;
+
+        info!("user logged in: {}", user.id);
+
+        Ok(LoginPayload {
+            token: issued.token,
+            expires_at: issued.expires_at,
+            user,
+        })
+    }
+
+    /// Changes the caller's own password and ends every other session
+    ///
+    /// Tokens issued before the change are rejected from then on, including the one used
+    /// for this call, so a fresh token is returned in its place.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `current_password` - the caller's password before the change
+    ///
+    /// * `new_password` - the password to change to
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing a new token for the caller and the updated user
+    ///
+    /// # Errors
+    ///
+    /// Returns Unauthorized (401) App error variant if the caller isn't a logged in user or
+    /// `currentPassword` is wrong
+    ///
+    /// Returns Validation Error (400) App error variant if the new password is empty
+    ///
+    /// Returns Database Error (500) App error variant if the password can't be hashed or saved
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(secret)] current_password: String,
+        #[graphql(secret)] new_password: String
+    ) -> Result<LoginPayload, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_claims(ctx)?;
+
+        let wrong_password = || {
+            AppError::Unauthorized("Current password is incorrect".to_string()).to_graphql_error()
+        };
+
+        let mut user = get_user(db_client, &claims.sub).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(wrong_password)?;
+
+        if !user.verify_password(&current_password) {
+            return Err(wrong_password());
+        }
+
+        if new_password.is_empty() {
+            return Err(
+                AppError::ValidationError("New password can't be empty".to_string()).to_graphql_error()
+            );
+        }
+
+        user.update_password(&new_password).map_err(|e|
+            AppError::DatabaseError(e).to_graphql_error()
+        )?;
+
+        let password_changed_at = user.password_changed_at.unwrap_or(user.updated_at);
+        let update = UpdateBuilder::new()
+            .set("password_hash", AttributeValue::S(user.password_hash.clone()))
+            .set("password_changed_at", AttributeValue::S(password_changed_at.to_string()))
+            .set("updated_at", AttributeValue::S(user.updated_at.to_string()))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+            })?;
+
+        let user = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Users")
+                    .key("id", AttributeValue::S(user.id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "user",
+                User::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        let issued = create_token(
+            &user.id,
+            &user.email,
+            &user.role,
+            user.password_changed_at,
+            user.token_version,
+            now(ctx)
+        ).map_err(|e| e.to_graphql_error())?;
+
+        info!("user changed password: {}", user.id);
+
+        Ok(LoginPayload {
+            token: issued.token,
+            expires_at: issued.expires_at,
+            user,
+        })
+    }
+
+    /// Removes a user from the database by email
+    ///
+    /// Admins can delete any user, everyone else only their own account.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `email` - String representing email address of user to delete
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the ID of the deleted user
+    ///
+    /// # Errors
+    ///
+    /// Returns Unauthorized (401) App error variant if the request isn't authenticated
+    ///
+    /// Returns Forbidden (403) App error variant if a non-Admin caller tries to delete another user
+    ///
+    /// Returns Not Found (404) App error variant if no user has that email
+    ///
+    /// Returns Database Error (500) App error variant if deleting the user fails
     async fn delete_user(
         &self,
         ctx: &Context<'_>,
         email: String,
-    ) -> Result<String, Error> {
-        let table_name = "Users";
-
-        info!("Removing user: {}", email);
-        let db_client = ctx.data::<Client>().map_err(|e| {
-            warn!("Failed to get db_client from context: {:?}", e);
-            AppError::InternalServerError(
-                "Failed to access application db_client".to_string()
-            ).to_graphql_error()
-        })?;
+    ) -> Result<DeletePayload, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_claims(ctx)?;
+
+        let user = find_user_by_email(db_client, &email).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that email".to_string()).to_graphql_error()
+            })?;
+
+        if claims.sub != user.id && !is_admin(claims) {
+            return Err(
+                AppError::Forbidden("You can only delete your own account".to_string()).to_graphql_error()
+            );
+        }
+
+        info!("user {} removing user: {}", claims.sub, user.id);
+
+        // also releases the email for new registrations
+        delete_user(db_client, &user).await.map_err(|e| e.to_graphql_error())?;
+        debug!("removed user successfully: {}", user.id);
+        Ok(DeletePayload { id: user.id, deleted: true })
+    }
+
+    /// Creates a new pantry
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `input` - fields of the new pantry
+    ///
+    /// * `reject_duplicate_name` - fail if a pantry with the same name, ignoring case, already
+    ///   exists in the same zipcode; off by default since some legitimate duplicates exist
+    ///
+    /// * `strict_address` - fail if the address's zipcode belongs to another state, see
+    ///   `check_zipcode_state`; off by default, a mismatch is only logged
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the created pantry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Conflict Error (409) App error variant if `reject_duplicate_name` is set and the
+    /// name is taken in the zipcode
+    ///
+    /// Returns Validation Error (400) App error variant if the operating hours, timezone or languages are invalid,
+    /// the pantry would exceed the item size limit, or `strict_address` is set and the zipcode
+    /// is in another state
+    ///
+    /// Returns Database Error (500) App error variant if the pantry can't be saved
+    async fn create_pantry(
+        &self,
+        ctx: &Context<'_>,
+        input: CreatePantryInput,
+        #[graphql(default)] reject_duplicate_name: bool,
+        #[graphql(default)] strict_address: bool
+    ) -> Result<Pantry, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        let input = input.normalized();
+
+        if let Err(e) = check_zipcode_state(&input.address.zipcode, &input.address.state) {
+            if strict_address {
+                return Err(e.to_graphql_error());
+            }
+            warn!("Creating pantry {:?} with a mismatched address: {}", input.name, e);
+        }
+
+        if let Some(hours) = &input.hours {
+            hours.validate().map_err(|e| e.to_graphql_error())?;
+        }
+
+        let timezone = input.timezone
+            .as_deref()
+            .map(parse_timezone)
+            .transpose()
+            .map_err(|e| e.to_graphql_error())?;
+
+        let languages = parse_languages(&input.languages).map_err(|e| e.to_graphql_error())?;
+
+        let mut pantry = Pantry::new(
+            Uuid::new_v4().to_string(),
+            input.name,
+            input.opt_status,
+            input.address,
+            input.is_self_managed,
+            input.phone,
+            input.email
+        ).map_err(AppError::ValidationError)?;
+
+        pantry.hours = input.hours;
+        pantry.languages = languages;
+        if let Some(timezone) = timezone {
+            pantry.timezone = timezone;
+        }
+        pantry.code = Some(next_pantry_code(db_client).await.map_err(|e| e.to_graphql_error())?);
+
+        create_pantry(db_client, &pantry, reject_duplicate_name).await.map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        info!("created pantry: {}", pantry.id);
+        invalidate_pantry_list(ctx);
+        Ok(pantry)
+    }
+
+    /// Updates the fields of an existing pantry
+    ///
+    /// A change of opt status is appended to the pantry's `optStatusHistory` with the caller as actor.
+    /// A new name or zipcode moves the guard claiming the pantry's name, see `create_pantry`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `id` - ID of the pantry to update
+    ///
+    /// * `input` - fields to change, omitted fields are kept
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated pantry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Not Found (404) App error variant if no pantry has that id
+    ///
+    /// Returns Conflict Error (409) App error variant if the opt status changed concurrently,
+    /// or the new name is already taken in the pantry's zipcode
+    ///
+    /// Returns Validation Error (400) App error variant if the operating hours, timezone or languages are invalid,
+    /// the opt status change isn't allowed, or the updated pantry would exceed the item size limit
+    ///
+    /// Returns Database Error (500) App error variant if the pantry can't be read or saved
+    async fn update_pantry(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        input: UpdatePantryInput
+    ) -> Result<Pantry, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_pantry_access(ctx, db_client, &id, AccessLevel::Manager).await?;
+
+        let input = input.normalized();
+
+        // an opt status change is appended to the pantry's history, and a new name or zipcode
+        // moves the pantry's name guard, both need the pantry as it is now
+        let current = if
+            input.opt_status.is_some() ||
+            input.name.is_some() ||
+            input.address.is_some()
+        {
+            let current = get_pantry(db_client, &id).await
+                .map_err(|e| e.to_graphql_error())?
+                .ok_or_else(|| {
+                    AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+                })?;
+            Some(current)
+        } else {
+            None
+        };
+
+        let mut expected_opt_status = None;
+        let mut opt_status_history = None;
+        let mut outbox_event = None;
+        let mut name_guard = None;
+        if let Some(current) = &current {
+            let name = input.name.as_deref().unwrap_or(&current.name);
+            let zipcode = input.address
+                .as_ref()
+                .map_or(current.address.zipcode.as_str(), |address| address.zipcode.as_str());
+            name_guard = name_guard_move(db_client, current, name, zipcode).await.map_err(|e|
+                e.to_graphql_error()
+            )?;
+        }
+
+        if let (Some(opt_status), Some(current)) = (input.opt_status, current) {
+            if current.opt_status != opt_status {
+                current.opt_status.check_transition(opt_status).map_err(|e| e.to_graphql_error())?;
+                let change = OptStatusChange {
+                    changed_at: now(ctx),
+                    from: current.opt_status,
+                    to: opt_status,
+                    actor: claims.sub.clone(),
+                };
+                let name = input.name.as_deref().unwrap_or(&current.name);
+                outbox_event = Some(
+                    OutboxEvent::opt_status_changed(&id, name, &change).delivered_by_webhook(
+                        webhooks(ctx).is_some()
+                    )
+                );
+                expected_opt_status = Some(current.opt_status);
+                opt_status_history = Some(change.append_to(current.opt_status_history));
+            }
+        }
+
+        let hours = FieldUpdate::from(input.hours);
+        if let FieldUpdate::Set(hours) = &hours {
+            hours.validate().map_err(|e| e.to_graphql_error())?;
+        }
+
+        let timezone = input.timezone
+            .as_deref()
+            .map(parse_timezone)
+            .transpose()
+            .map_err(|e| e.to_graphql_error())?;
+
+        // an empty list removes the attribute, DynamoDB rejects empty sets
+        let languages = match input.languages {
+            Some(languages) => {
+                let languages = parse_languages(&languages).map_err(|e| e.to_graphql_error())?;
+                if languages.is_empty() { FieldUpdate::Clear } else { FieldUpdate::Set(languages) }
+            }
+            None => FieldUpdate::Unchanged,
+        };
+
+        // NameIndex keys move with the name, a blank name takes the pantry out of the index
+        let (name_lower, name_bucket) = match input.name.as_deref().map(name_search_key) {
+            Some(key) if key.is_empty() => (FieldUpdate::Clear, FieldUpdate::Clear),
+            Some(key) => {
+                let bucket = name_search_bucket(&key);
+                (FieldUpdate::Set(key), FieldUpdate::Set(bucket))
+            }
+            None => (FieldUpdate::Unchanged, FieldUpdate::Unchanged),
+        };
+
+        let update = UpdateBuilder::new()
+            .field("name", input.name.into(), AttributeValue::S)
+            .field("name_lower", name_lower, AttributeValue::S)
+            .field("name_bucket", name_bucket, AttributeValue::S)
+            .field("opt_status", input.opt_status.into(), |opt_status|
+                AttributeValue::S(opt_status.to_str().to_string())
+            )
+            .field("opt_status_history", opt_status_history.into(), |history: Vec<_>| {
+                OptStatusChange::history_to_attribute(&history)
+            })
+            .field("address", input.address.into(), |address| address.to_attribute())
+            .field("is_self_managed", input.is_self_managed.into(), |is_self_managed: bool|
+                AttributeValue::S(is_self_managed.to_string())
+            )
+            .field("phone", input.phone.into(), AttributeValue::S)
+            .field("email", input.email.into(), AttributeValue::S)
+            .field("hours", hours, |hours| hours.to_item())
+            .field("timezone", timezone.into(), |timezone| {
+                AttributeValue::S(timezone.name().to_string())
+            })
+            .field("languages", languages, AttributeValue::Ss)
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty pantry update".to_string()).to_graphql_error()
+            })?;
+
+        // the history must not be written over a status someone else changed since it was read,
+        // and the change is recorded in the outbox and the name guard moved in the same transaction
+        if outbox_event.is_some() || name_guard.is_some() {
+            let mut update = update;
+            let mut condition = "attribute_exists(id)";
+            if let Some(expected) = expected_opt_status {
+                update.values.get_or_insert_default().insert(
+                    ":expected_opt_status".to_string(),
+                    AttributeValue::S(expected.to_str().to_string())
+                );
+                condition = "attribute_exists(id) AND opt_status = :expected_opt_status";
+            }
+
+            let pantry = update_pantry_with_event(
+                db_client,
+                &id,
+                update,
+                condition,
+                outbox_event.as_ref(),
+                name_guard.as_ref()
+            ).await.map_err(|e| e.to_graphql_error())?;
+
+            info!("updated pantry: {}", id);
+            invalidate_pantry_list(ctx);
+            return Ok(pantry);
+        }
+
+        let pantry = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Pantries")
+                    .key("id", AttributeValue::S(id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "pantry",
+                Pantry::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("updated pantry: {}", id);
+        invalidate_pantry_list(ctx);
+        Ok(pantry)
+    }
+
+    /// Sets or clears the URL of a pantry's logo or photo
+    ///
+    /// Only the URL is stored, the image itself is uploaded to S3 separately.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry to change
+    ///
+    /// * `image_url` - http(s) URL of the image, null removes it
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated pantry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if the URL isn't a valid http(s) URL, see `parse_image_url`
+    ///
+    /// Returns Not Found (404) App error variant if no pantry has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn set_pantry_image_url(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        image_url: Option<String>
+    ) -> Result<Pantry, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        let image_url = match image_url {
+            Some(url) => FieldUpdate::Set(parse_image_url(&url).map_err(|e| e.to_graphql_error())?),
+            None => FieldUpdate::Clear,
+        };
+
+        let update = UpdateBuilder::new()
+            .field("image_url", image_url, AttributeValue::S)
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty pantry update".to_string()).to_graphql_error()
+            })?;
+
+        let pantry = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Pantries")
+                    .key("id", AttributeValue::S(pantry_id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "pantry",
+                Pantry::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("set image url of pantry: {}", pantry_id);
+        invalidate_pantry_list(ctx);
+        Ok(pantry)
+    }
+
+    /// Sets whether a pantry runs by appointment and where visits are booked
+    ///
+    /// Both are set together so they can't disagree: a booking URL is only kept on a pantry
+    /// that requires appointments, and turning appointments off removes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry to change
+    ///
+    /// * `appointment_required` - whether clients must book a visit instead of walking in
+    ///
+    /// * `booking_url` - http(s) URL to book a visit at, null for none, e.g. booking by phone
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated pantry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if a booking URL is given without
+    /// `appointment_required`, or isn't a valid http(s) URL, see `parse_booking_url`
+    ///
+    /// Returns Not Found (404) App error variant if no pantry has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn set_pantry_appointment(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        appointment_required: bool,
+        booking_url: Option<String>
+    ) -> Result<Pantry, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        let booking_url = match booking_url {
+            Some(_) if !appointment_required => {
+                return Err(
+                    AppError::ValidationError(
+                        "A booking URL can only be set on a pantry that requires appointments".to_string()
+                    ).to_graphql_error()
+                );
+            }
+            Some(url) =>
+                FieldUpdate::Set(parse_booking_url(&url).map_err(|e| e.to_graphql_error())?),
+            None => FieldUpdate::Clear,
+        };
+
+        let update = UpdateBuilder::new()
+            .set("appointment_required", AttributeValue::S(appointment_required.to_string()))
+            .field("booking_url", booking_url, AttributeValue::S)
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty pantry update".to_string()).to_graphql_error()
+            })?;
+
+        let pantry = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Pantries")
+                    .key("id", AttributeValue::S(pantry_id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "pantry",
+                Pantry::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("set appointment of pantry {}: {}", pantry_id, appointment_required);
+        invalidate_pantry_list(ctx);
+        Ok(pantry)
+    }
+
+    /// Closes a pantry temporarily, or reopens it
+    ///
+    /// A closure with `reopens_at` ends on its own once that time passes, one without stays
+    /// until the pantry is reopened here. Reopening clears the reason and reopening time.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry to change
+    ///
+    /// * `closed` - whether the pantry is temporarily closed
+    ///
+    /// * `reason` - why the pantry is closed, e.g. "Closed for Thanksgiving", null for none
+    ///
+    /// * `reopens_at` - when the closure ends, null to keep it until reopened by hand
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated pantry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if a reason or reopening time is
+    /// given while reopening, or the reopening time isn't in the future
+    ///
+    /// Returns Not Found (404) App error variant if no pantry has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn set_pantry_closure(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        closed: bool,
+        reason: Option<String>,
+        reopens_at: Option<DateTime<Utc>>
+    ) -> Result<Pantry, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        if !closed && (reason.is_some() || reopens_at.is_some()) {
+            return Err(
+                AppError::ValidationError(
+                    "A closure reason or reopening time can only be set on a closed pantry".to_string()
+                ).to_graphql_error()
+            );
+        }
+
+        if reopens_at.is_some_and(|reopens_at| reopens_at <= now(ctx)) {
+            return Err(
+                AppError::ValidationError("reopens_at must be in the future".to_string()).to_graphql_error()
+            );
+        }
+
+        let reason = match reason.as_deref().map(str::trim) {
+            Some(reason) if !reason.is_empty() => FieldUpdate::Set(reason.to_string()),
+            _ => FieldUpdate::Clear,
+        };
+
+        let reopens_at = match reopens_at {
+            Some(reopens_at) => FieldUpdate::Set(reopens_at.to_string()),
+            None => FieldUpdate::Clear,
+        };
+
+        let update = UpdateBuilder::new()
+            .set("is_temporarily_closed", AttributeValue::S(closed.to_string()))
+            .field("closed_reason", reason, AttributeValue::S)
+            .field("reopens_at", reopens_at, AttributeValue::S)
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty pantry update".to_string()).to_graphql_error()
+            })?;
+
+        let pantry = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Pantries")
+                    .key("id", AttributeValue::S(pantry_id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "pantry",
+                Pantry::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("set closure of pantry {}: {}", pantry_id, closed);
+        invalidate_pantry_list(ctx);
+        Ok(pantry)
+    }
+
+    /// Creates a presigned S3 URL the client uploads a pantry's logo to
+    ///
+    /// The image is PUT straight to S3 with the returned URL and the same `Content-Type`,
+    /// then saved on the pantry by passing `publicUrl` to `setPantryImageUrl`. The URL
+    /// expires after `UPLOAD_URL_LIFETIME_SECS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client, image store and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry the logo belongs to
+    ///
+    /// * `content_type` - type of the image, one of `ALLOWED_IMAGE_TYPES`
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the upload URL, the image's eventual public URL and the upload URL's expiry
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if the content type isn't allowed
+    ///
+    /// Returns Not Found (404) App error variant if no pantry has that id
+    ///
+    /// Returns Internal Server Error (500) App error variant if image uploads aren't configured
+    ///
+    /// Returns External Service Error (502) App error variant if the URL can't be signed
+    async fn presign_pantry_image_upload(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        content_type: String
+    ) -> Result<PresignedUpload, Error> {
+        let db_client = db(ctx)?;
+        let image_store = images(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        // don't hand out upload URLs for keys of pantries that don't exist
+        get_pantry(db_client, &pantry_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No pantry found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        image_store
+            .presign_logo_upload(&pantry_id, &content_type, now(ctx)).await
+            .map_err(|e| e.to_graphql_error())
+    }
+
+    /// Changes the opt status of many pantries at once, e.g. when a program rolls out
+    ///
+    /// Each pantry is updated on its own, with the change appended to its `optStatusHistory`,
+    /// so one failing pantry doesn't stop the others. A pantry can't skip from T1 to T3, see
+    /// `OptStatus::can_transition_to`, such pantries are reported as failed. Pantries are processed in chunks of
+    /// `BULK_OPT_STATUS_CHUNK`, a pantry already at `optStatus` is left unchanged. Updates
+    /// share the bulk write limit of `db::throttle`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_ids` - IDs of the pantries to change, at most `BULK_OPT_STATUS_LIMIT`
+    ///
+    /// * `opt_status` - opt status to give every pantry
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the pantries after the change and the ids that failed, each id
+    /// reported once at its first position in `pantry_ids`
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Validation Error (400) App error variant if more than `BULK_OPT_STATUS_LIMIT` ids are given
+    ///
+    /// Returns Database Error (500) App error variant if the pantries can't be read
+    async fn bulk_set_opt_status(
+        &self,
+        ctx: &Context<'_>,
+        pantry_ids: Vec<String>,
+        opt_status: OptStatus
+    ) -> Result<BatchResult<Pantry>, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_admin(ctx)?;
+
+        if pantry_ids.len() > BULK_OPT_STATUS_LIMIT {
+            return Err(
+                AppError::ValidationError(
+                    format!("At most {} pantries can be updated at once", BULK_OPT_STATUS_LIMIT)
+                ).to_graphql_error()
+            );
+        }
+
+        // BatchGetItem rejects duplicate keys, so only handle each id once
+        let mut unique_ids = pantry_ids.clone();
+        unique_ids.sort();
+        unique_ids.dedup();
 
-        info!("successfully created db_client: {:?}", &db_client);
-
-        let remove_item_output = db_client
-            .delete_item()
-            .table_name(table_name)
-            .key("email", AttributeValue::S(email.clone().into()))
-            .send().await
-            .map_err(|e| {
-                warn!("Failed to delete user: {:?}", e);
-                AppError::DatabaseError(
-                    "Failed to delete user by email from db".to_string()
+        // a transition that isn't allowed fails for that pantry alone, see `set_opt_status`; opt
+        // status carries no flags or inventory data yet, so nothing else is cleared on a change
+        let mut outcomes: HashMap<String, Result<Pantry, AppError>> = HashMap::new();
+        for chunk in unique_ids.chunks(BULK_OPT_STATUS_CHUNK) {
+            let keys = chunk
+                .iter()
+                .map(|id| HashMap::from([("id".to_string(), AttributeValue::S(id.clone()))]))
+                .collect::<Vec<_>>();
+
+            let items = batch_get_items(db_client, "Pantries", keys).await.map_err(|e|
+                e.to_graphql_error()
+            )?;
+
+            let mut pantries = items
+                .iter()
+                .filter_map(Pantry::from_item)
+                .map(|pantry| (pantry.id.clone(), pantry))
+                .collect::<HashMap<String, Pantry>>();
+
+            let mut updates = JoinSet::new();
+            for id in chunk {
+                match pantries.remove(id) {
+                    Some(pantry) => {
+                        let client = db_client.clone();
+                        let actor = claims.sub.clone();
+                        let id = id.clone();
+                        let deliver_event = webhooks(ctx).is_some();
+                        let changed_at = now(ctx);
+                        updates.spawn(async move {
+                            let _permit = acquire_bulk_write_permit().await;
+                            let outcome = set_opt_status(
+                                &client,
+                                pantry,
+                                opt_status,
+                                &actor,
+                                deliver_event,
+                                changed_at
+                            ).await;
+                            (id, outcome)
+                        });
+                    }
+                    None => {
+                        let error = AppError::NotFound("No pantry found with that ID".to_string());
+                        outcomes.insert(id.clone(), Err(error));
+                    }
+                }
+            }
+
+            while let Some(joined) = updates.join_next().await {
+                let (id, outcome) = joined.map_err(|e| {
+                    AppError::InternalServerError(
+                        format!("Opt status update task failed: {}", e)
+                    ).to_graphql_error()
+                })?;
+                outcomes.insert(id, outcome);
+            }
+        }
+
+        info!(
+            "bulk set opt status {} on {} pantries",
+            opt_status.to_str(),
+            outcomes.values().filter(|outcome| outcome.is_ok()).count()
+        );
+
+        let mut result = BatchResult { succeeded: Vec::new(), failed: Vec::new() };
+        for (index, pantry_id) in pantry_ids.into_iter().enumerate() {
+            match outcomes.remove(&pantry_id) {
+                Some(Ok(pantry)) => result.succeeded.push(pantry),
+                Some(Err(error)) =>
+                    result.failed.push(FailedItem::from_error(index, Some(pantry_id), &error)),
+                // a repeated id, already reported at its first position
+                None => {}
+            }
+        }
+
+        if !result.succeeded.is_empty() {
+            invalidate_pantry_list(ctx);
+        }
+
+        Ok(result)
+    }
+
+    /// Updates a user's profile
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `id` - ID of the user to update
+    ///
+    /// * `input` - fields to change, omitted fields are kept and a null `pantryId` is removed
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated user
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller is neither the user nor an Admin
+    ///
+    /// Returns Validation Error (400) App error variant if `pantryId` names a pantry that doesn't exist
+    ///
+    /// Returns Not Found (404) App error variant if no user has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        input: UpdateUserInput
+    ) -> Result<User, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_claims(ctx)?;
+        if claims.sub != id && !is_admin(claims) {
+            return Err(
+                AppError::Forbidden("You can only update your own profile".to_string()).to_graphql_error()
+            );
+        }
+
+        let input = input.normalized();
+
+        let pantry_id = FieldUpdate::from(input.pantry_id);
+
+        // don't let a user point at a pantry that doesn't exist
+        if let FieldUpdate::Set(pantry_id) = &pantry_id {
+            if get_pantry(db_client, pantry_id).await.map_err(|e| e.to_graphql_error())?.is_none() {
+                return Err(
+                    AppError::ValidationError(
+                        format!("No pantry found with id {}", pantry_id)
+                    ).to_graphql_error()
+                );
+            }
+        }
+
+        let update = UpdateBuilder::new()
+            .field("first_name", input.first_name.into(), AttributeValue::S)
+            .field("last_name", input.last_name.into(), AttributeValue::S)
+            .field("pantry_id", pantry_id, AttributeValue::S)
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+            })?;
+
+        let user = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Users")
+                    .key("id", AttributeValue::S(id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "user",
+                User::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("updated user: {}", id);
+        Ok(user)
+    }
+
+    /// Deactivates or reactivates a user
+    ///
+    /// A deactivated user can't log in but keeps its profile, pantry access and email, unlike
+    /// a soft deleted one, and can be reactivated at any time. Deactivating also bumps the
+    /// user's token version, so tokens issued before it stay revoked after a reactivation,
+    /// see `auth::middleware::ensure_token_current`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `user_id` - ID of the user to change
+    ///
+    /// * `active` - false to deactivate the user, true to reactivate it
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated user
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Validation Error (400) App error variant if the caller tries to deactivate itself
+    ///
+    /// Returns Not Found (404) App error variant if no user has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn set_user_active(
+        &self,
+        ctx: &Context<'_>,
+        user_id: String,
+        active: bool
+    ) -> Result<User, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_admin(ctx)?;
+
+        // an Admin locking itself out would need another Admin to get back in
+        if !active && claims.sub == user_id {
+            return Err(
+                AppError::ValidationError("You can't deactivate yourself".to_string()).to_graphql_error()
+            );
+        }
+
+        let mut update = UpdateBuilder::new().set(
+            "is_active",
+            AttributeValue::S(active.to_string())
+        );
+        if !active {
+            update = update.add("token_version", AttributeValue::N("1".to_string()));
+        }
+
+        let update = update
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+            })?;
+
+        let user = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Users")
+                    .key("id", AttributeValue::S(user_id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "user",
+                User::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("set user {} active: {}", user_id, active);
+        Ok(user)
+    }
+
+    /// Signs a user out everywhere by invalidating every token issued to them so far
+    ///
+    /// Bumps the user's token version, so tokens issued before it are rejected on their next
+    /// request, see `auth::middleware::ensure_token_current`. The password is left as is and the
+    /// user can log in again right away, deactivate them with `setUserActive` to prevent that.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `user_id` - ID of the user whose sessions to revoke
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated user
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Not Found (404) App error variant if no user has that id
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn revoke_all_sessions(&self, ctx: &Context<'_>, user_id: String) -> Result<User, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_admin(ctx)?;
+
+        let update = UpdateBuilder::new()
+            .add("token_version", AttributeValue::N("1".to_string()))
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+            })?;
+
+        let user = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Users")
+                    .key("id", AttributeValue::S(user_id.clone()))
+                    .condition_expression("attribute_exists(id)"),
+                "user",
+                User::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        info!("user {} revoked all sessions of user {}", claims.sub, user_id);
+        Ok(user)
+    }
+
+    /// Removes a user's pantry association when the pantry it points at no longer exists
+    ///
+    /// The association is only removed if it still points at the same pantry when the
+    /// update is applied, so a concurrent reassignment is never undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `user_id` - ID of the user to check
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the user and whether its association was dangling and removed
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Not Found (404) App error variant if no user has that id
+    ///
+    /// Returns Database Error (500) App error variant if a read or the update fails
+    async fn clear_invalid_pantry_association(
+        &self,
+        ctx: &Context<'_>,
+        user_id: String
+    ) -> Result<ClearPantryAssociationPayload, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        let user = get_user(db_client, &user_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with that ID".to_string()).to_graphql_error()
+            })?;
+
+        let pantry_exists = match &user.pantry_id {
+            Some(pantry_id) =>
+                get_pantry(db_client, pantry_id).await.map_err(|e| e.to_graphql_error())?.is_some(),
+            None => false,
+        };
+
+        let pantry_id = match dangling_pantry_id(&user, pantry_exists) {
+            Some(pantry_id) => pantry_id,
+            None => {
+                return Ok(ClearPantryAssociationPayload { cleared: false, user });
+            }
+        };
+
+        let update = UpdateBuilder::new()
+            .remove("pantry_id")
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+            })?;
+
+        let updated = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("Users")
+                    .key("id", AttributeValue::S(user_id.clone()))
+                    .condition_expression("pantry_id = :pantry_id")
+                    .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.clone())),
+                "user",
+                User::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?;
+
+        match updated {
+            Some(user) => {
+                info!("removed dangling pantry {} from user {}", pantry_id, user_id);
+                Ok(ClearPantryAssociationPayload { cleared: true, user })
+            }
+            // the association changed since it was read, leave the new one alone
+            None => {
+                let user = get_user(db_client, &user_id).await
+                    .map_err(|e| e.to_graphql_error())?
+                    .ok_or_else(|| {
+                        AppError::NotFound(
+                            "No user found with that ID".to_string()
+                        ).to_graphql_error()
+                    })?;
+                Ok(ClearPantryAssociationPayload { cleared: false, user })
+            }
+        }
+    }
+
+    /// Marks or unmarks a user as a contact agent of a pantry
+    ///
+    /// Only users who already have access to the pantry can be contact agents.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry
+    ///
+    /// * `user_id` - ID of the user
+    ///
+    /// * `is_contact_agent` - whether the user is a contact agent for the pantry
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated access row
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if the user has no access to the pantry
+    ///
+    /// Returns Database Error (500) App error variant if the update fails
+    async fn set_contact_agent(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        user_id: String,
+        is_contact_agent: bool
+    ) -> Result<PantryAccess, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        let update = UpdateBuilder::new()
+            .set("is_contact_agent", AttributeValue::S(is_contact_agent.to_string()))
+            .touch(now(ctx))
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty access update".to_string()).to_graphql_error()
+            })?;
+
+        // only update an existing row, never create access as a side effect
+        let access = update
+            .send_returning(
+                db_client
+                    .update_item()
+                    .table_name("PantryAccess")
+                    .key("pantry_id", AttributeValue::S(pantry_id.clone()))
+                    .key("user_id", AttributeValue::S(user_id.clone()))
+                    .condition_expression("attribute_exists(user_id)"),
+                "access",
+                PantryAccess::from_item
+            ).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::ValidationError(
+                    "Only users with access to the pantry can be contact agents".to_string()
+                ).to_graphql_error()
+            })?;
+
+        info!(
+            "set contact agent of pantry {} for user {} to {}",
+            pantry_id,
+            user_id,
+            is_contact_agent
+        );
+        Ok(access)
+    }
+
+    /// Sets the access level of several users to a pantry at once
+    ///
+    /// New grants create access rows, grants for users who already have access
+    /// replace their level. Up to 99 grants are applied in a single transaction, with
+    /// the outbox event recording them, so they all succeed or none do. Larger requests
+    /// are split into transactions of 99, each of which is atomic on its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `pantry_id` - ID of the pantry whose team is being changed
+    ///
+    /// * `grants` - users and the access levels to give them
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing every access row of the pantry after the update
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't a Manager of the pantry,
+    /// or grants Admin or changes an Admin's level without being an Admin of the pantry
+    ///
+    /// Returns Validation Error (400) App error variant if a user is listed twice or doesn't exist
+    ///
+    /// Returns Conflict Error (409) App error variant if a Manager's grant targets a user made
+    /// Admin while the grants were applied
+    ///
+    /// Returns Database Error (500) App error variant if the transaction fails
+    async fn set_pantry_access(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        grants: Vec<AccessGrantInput>
+    ) -> Result<Vec<PantryAccess>, Error> {
+        let table_name = "PantryAccess";
+
+        let db_client = db(ctx)?;
+
+        let claims = require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        if grants.is_empty() {
+            return Err(
+                AppError::ValidationError("At least one grant is required".to_string()).to_graphql_error()
+            );
+        }
+
+        // a transaction can't touch the same row twice
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = grants.iter().find(|grant| !seen.insert(grant.user_id.as_str())) {
+            return Err(
+                AppError::ValidationError(
+                    format!("User {} is listed more than once", duplicate.user_id)
+                ).to_graphql_error()
+            );
+        }
+
+        // every grant must point at an existing user
+        let keys = grants
+            .iter()
+            .map(|grant| HashMap::from([("id".to_string(), AttributeValue::S(grant.user_id.clone()))]))
+            .collect::<Vec<_>>();
+
+        let existing_ids = batch_get_items(db_client, "Users", keys).await
+            .map_err(|e| e.to_graphql_error())?
+            .iter()
+            .filter_map(|item| item.get("id").and_then(|v| v.as_s().ok()).cloned())
+            .collect::<HashSet<String>>();
+
+        if let Some(missing) = grants.iter().find(|grant| !existing_ids.contains(&grant.user_id)) {
+            return Err(
+                AppError::ValidationError(
+                    format!("No user found with id {}", missing.user_id)
+                ).to_graphql_error()
+            );
+        }
+
+        // only pantry admins may hand out admin access or change the level of an admin
+        let admins = list_pantry_access(db_client, &pantry_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .into_iter()
+            .filter(|access| access.access_level == AccessLevel::Admin)
+            .map(|access| access.user_id)
+            .collect::<HashSet<String>>();
+
+        let changes_admin = grants
+            .iter()
+            .any(|grant| grant.access_level == AccessLevel::Admin || admins.contains(&grant.user_id));
+        if changes_admin {
+            require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Admin).await?;
+        }
+
+        let changed_at = now(ctx);
+        let now = changed_at.to_string();
+
+        // each transaction also records its grants in the outbox, leaving room for the event
+        let deliver_event = webhooks(ctx).is_some();
+
+        for chunk in grants.chunks(TRANSACT_WRITE_LIMIT - 1) {
+            let mut transact_items = Vec::with_capacity(chunk.len() + 1);
+
+            for grant in chunk {
+                // keep created_at and the contact agent flag of rows that already exist
+                let expression = UpdateBuilder::new()
+                    .set("access_level", AttributeValue::S(grant.access_level.to_str().to_string()))
+                    .set("updated_at", AttributeValue::S(now.clone()))
+                    .set_if_not_exists("created_at", AttributeValue::S(now.clone()))
+                    .set_if_not_exists("is_contact_agent", AttributeValue::S("false".to_string()))
+                    .build()
+                    .ok_or_else(|| {
+                        AppError::InternalServerError(
+                            "Empty access update".to_string()
+                        ).to_graphql_error()
+                    })?;
+
+                let mut update = Update::builder()
+                    .table_name(table_name)
+                    .key("pantry_id", AttributeValue::S(pantry_id.clone()))
+                    .key("user_id", AttributeValue::S(grant.user_id.clone()))
+                    .update_expression(expression.expression)
+                    .set_expression_attribute_names(Some(expression.names))
+                    .set_expression_attribute_values(expression.values);
+
+                // a Manager's grant must not land on a user made Admin since the rows were read
+                if !changes_admin {
+                    update = update
+                        .condition_expression(
+                            "attribute_not_exists(access_level) OR access_level <> :admin"
+                        )
+                        .expression_attribute_values(
+                            ":admin",
+                            AttributeValue::S(AccessLevel::Admin.to_str().to_string())
+                        );
+                }
+
+                let update = update
+                    .build()
+                    .map_err(|e|
+                        AppError::DatabaseError(
+                            format!("Failed to build access update: {}", e)
+                        ).to_graphql_error()
+                    )?;
+
+                transact_items.push(TransactWriteItem::builder().update(update).build());
+            }
+
+            let granted = chunk
+                .iter()
+                .map(|grant| (grant.user_id.clone(), grant.access_level))
+                .collect::<Vec<_>>();
+            let event = OutboxEvent::access_changed(&pantry_id, &granted, &claims.sub, changed_at)
+                .delivered_by_webhook(deliver_event);
+            transact_items.push(outbox_put(&event).map_err(|e| e.to_graphql_error())?);
+
+            db_client
+                .transact_write_items()
+                .set_transact_items(Some(transact_items))
+                .send().await
+                .map_err(|e| {
+                    if any_condition_failed(&e) {
+                        return AppError::ConflictError(
+                            "A user was made Admin while access was granted".to_string()
+                        ).to_graphql_error();
+                    }
+                    warn!("Failed to apply pantry access grants: {:?}", e);
+                    AppError::DatabaseError(
+                        "Failed to update pantry access".to_string()
+                    ).to_graphql_error()
+                })?;
+        }
+
+        info!("updated access of {} users to pantry {}", grants.len(), pantry_id);
+
+        list_pantry_access(db_client, &pantry_id).await.map_err(|e| e.to_graphql_error())
+    }
+
+    /// Merges a duplicate account into another user
+    ///
+    /// The source's pantry access rows are moved to the target; where both users have
+    /// access to the same pantry the higher level is kept on the target's row. The
+    /// source's pantry association moves to the target when the target has none, and the
+    /// source is then soft deleted by setting `deleted_at`, which also blocks its logins.
+    /// The source's token version is bumped in the same update, revoking its sessions.
+    ///
+    /// Everything is written in one transaction, unless the source has more access rows
+    /// than fit in one, in which case the rows are moved in several transactions and the
+    /// users are updated in the last one. A merge that fails part way can be run again.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `source_id` - ID of the user to merge and soft delete
+    ///
+    /// * `target_id` - ID of the user to keep
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing the updated target and a summary of what was moved
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Validation Error (400) App error variant if both ids are the same or either
+    /// user is already deleted
+    ///
+    /// Returns Not Found (404) App error variant if either user doesn't exist
+    ///
+    /// Returns Conflict Error (409) App error variant if either user changed during the merge
+    ///
+    /// Returns Database Error (500) App error variant if a read or a transaction fails
+    async fn merge_users(
+        &self,
+        ctx: &Context<'_>,
+        source_id: String,
+        target_id: String
+    ) -> Result<MergeUsersPayload, Error> {
+        let table_name = "PantryAccess";
+
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        if source_id == target_id {
+            return Err(
+                AppError::ValidationError(
+                    "Cannot merge a user into itself".to_string()
+                ).to_graphql_error()
+            );
+        }
+
+        let source = get_user(db_client, &source_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with the source ID".to_string()).to_graphql_error()
+            })?;
+
+        let target = get_user(db_client, &target_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with the target ID".to_string()).to_graphql_error()
+            })?;
+
+        if source.deleted_at.is_some() || target.deleted_at.is_some() {
+            return Err(
+                AppError::ValidationError(
+                    "Cannot merge a user that has been deleted".to_string()
                 ).to_graphql_error()
+            );
+        }
+
+        let source_access = list_user_access(db_client, &source_id).await.map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        let target_access = list_user_access(db_client, &target_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .into_iter()
+            .map(|access| (access.pantry_id.clone(), access))
+            .collect::<HashMap<String, PantryAccess>>();
+
+        let now = now(ctx);
+        let mut access_moved = 0;
+        let mut access_merged = 0;
+
+        // each source row becomes a write to the target's row plus a delete of its own
+        let mut row_actions = Vec::with_capacity(source_access.len());
+
+        for access in source_access {
+            let write = match target_access.get(&access.pantry_id) {
+                Some(existing) => {
+                    access_merged += 1;
+
+                    let expression = UpdateBuilder::new()
+                        .set(
+                            "access_level",
+                            AttributeValue::S(
+                                existing.access_level.max(access.access_level).to_str().to_string()
+                            )
+                        )
+                        .set(
+                            "is_contact_agent",
+                            AttributeValue::S(
+                                (existing.is_contact_agent || access.is_contact_agent).to_string()
+                            )
+                        )
+                        .touch(now)
+                        .build()
+                        .ok_or_else(|| {
+                            AppError::InternalServerError(
+                                "Empty access update".to_string()
+                            ).to_graphql_error()
+                        })?;
+
+                    let update = Update::builder()
+                        .table_name(table_name)
+                        .key("pantry_id", AttributeValue::S(access.pantry_id.clone()))
+                        .key("user_id", AttributeValue::S(target_id.clone()))
+                        .update_expression(expression.expression)
+                        .set_expression_attribute_names(Some(expression.names))
+                        .set_expression_attribute_values(expression.values)
+                        .build()
+                        .map_err(|e|
+                            AppError::DatabaseError(
+                                format!("Failed to build access update: {}", e)
+                            ).to_graphql_error()
+                        )?;
+
+                    TransactWriteItem::builder().update(update).build()
+                }
+                None => {
+                    access_moved += 1;
+
+                    let moved = PantryAccess {
+                        user_id: target_id.clone(),
+                        updated_at: now,
+                        ..access.clone()
+                    };
+
+                    // the target must not have gained access since it was read
+                    let put = Put::builder()
+                        .table_name(table_name)
+                        .set_item(Some(moved.to_item()))
+                        .condition_expression("attribute_not_exists(user_id)")
+                        .build()
+                        .map_err(|e|
+                            AppError::DatabaseError(
+                                format!("Failed to build access put: {}", e)
+                            ).to_graphql_error()
+                        )?;
+
+                    TransactWriteItem::builder().put(put).build()
+                }
+            };
+
+            let delete = Delete::builder()
+                .table_name(table_name)
+                .key("pantry_id", AttributeValue::S(access.pantry_id.clone()))
+                .key("user_id", AttributeValue::S(source_id.clone()))
+                .build()
+                .map_err(|e|
+                    AppError::DatabaseError(
+                        format!("Failed to build access delete: {}", e)
+                    ).to_graphql_error()
+                )?;
+
+            row_actions.push(vec![write, TransactWriteItem::builder().delete(delete).build()]);
+        }
+
+        let pantry_moved = source.pantry_id.is_some() && target.pantry_id.is_none();
+
+        // both users must still exist and not be deleted when the merge is applied
+        let source_update = UpdateBuilder::new()
+            .set("deleted_at", AttributeValue::S(now.to_string()))
+            .remove("pantry_id")
+            .add("token_version", AttributeValue::N("1".to_string()))
+            .touch(now)
+            .build()
+            .ok_or_else(|| {
+                AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
             })?;
-        info!("removed item successfully, output: {:?}", &remove_item_output);
-        Ok(email)
+
+        let mut target_update = UpdateBuilder::new().touch(now);
+        if let (true, Some(pantry_id)) = (pantry_moved, &source.pantry_id) {
+            target_update = target_update.set("pantry_id", AttributeValue::S(pantry_id.clone()));
+        }
+        let target_update = target_update.build().ok_or_else(|| {
+            AppError::InternalServerError("Empty user update".to_string()).to_graphql_error()
+        })?;
+
+        let mut user_actions = Vec::with_capacity(2);
+        for (id, expression) in [(&source_id, source_update), (&target_id, target_update)] {
+            let update = Update::builder()
+                .table_name("Users")
+                .key("id", AttributeValue::S(id.clone()))
+                .update_expression(expression.expression)
+                .condition_expression("attribute_exists(id) AND attribute_not_exists(deleted_at)")
+                .set_expression_attribute_names(Some(expression.names))
+                .set_expression_attribute_values(expression.values)
+                .build()
+                .map_err(|e|
+                    AppError::DatabaseError(
+                        format!("Failed to build user update: {}", e)
+                    ).to_graphql_error()
+                )?;
+
+            user_actions.push(TransactWriteItem::builder().update(update).build());
+        }
+
+        // leave room for the user updates in every transaction, they go in the last one
+        let rows_per_transaction = (TRANSACT_WRITE_LIMIT - user_actions.len()) / 2;
+        let mut transactions = row_actions
+            .chunks(rows_per_transaction)
+            .map(|rows| rows.concat())
+            .collect::<Vec<Vec<TransactWriteItem>>>();
+
+        match transactions.last_mut() {
+            Some(last) => last.extend(user_actions),
+            None => transactions.push(user_actions),
+        }
+
+        for transact_items in transactions {
+            db_client
+                .transact_write_items()
+                .set_transact_items(Some(transact_items))
+                .send().await
+                .map_err(|e| {
+                    if
+                        e
+                            .as_service_error()
+                            .is_some_and(|e| e.is_transaction_canceled_exception())
+                    {
+                        return AppError::ConflictError(
+                            "The users changed during the merge, try again".to_string()
+                        ).to_graphql_error();
+                    }
+                    warn!("Failed to merge users: {:?}", e);
+                    AppError::DatabaseError("Failed to merge users".to_string()).to_graphql_error()
+                })?;
+        }
+
+        info!(
+            "merged user {} into {}: {} access rows moved, {} merged",
+            source_id,
+            target_id,
+            access_moved,
+            access_merged
+        );
+
+        let target = get_user(db_client, &target_id).await
+            .map_err(|e| e.to_graphql_error())?
+            .ok_or_else(|| {
+                AppError::NotFound("No user found with the target ID".to_string()).to_graphql_error()
+            })?;
+
+        Ok(MergeUsersPayload {
+            target,
+            access_moved,
+            access_merged,
+            pantry_moved,
+        })
+    }
+
+    /// Runs a backfill bringing items saved before a change to how they are stored up to date
+    ///
+    /// Scans the backfill's whole table, so it is billed as a full table read. Items
+    /// already up to date are left alone, so it is safe to run again, e.g. after a failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - async-graphql Context object, contains dynamoDB client and caller claims
+    ///
+    /// * `backfill` - the backfill to run, see `db::integrity::Backfill`
+    ///
+    /// # Returns
+    ///
+    /// OK Result containing what the backfill read, wrote and left for an Admin to resolve
+    ///
+    /// # Errors
+    ///
+    /// Returns Forbidden (403) App error variant if the caller isn't an Admin
+    ///
+    /// Returns Database Error (500) App error variant if a scan page or a write fails
+    async fn run_backfill(
+        &self,
+        ctx: &Context<'_>,
+        backfill: Backfill
+    ) -> Result<BackfillReport, Error> {
+        let db_client = db(ctx)?;
+
+        let claims = require_admin(ctx)?;
+
+        info!("user {} running backfill {:?}", claims.sub, backfill);
+
+        run_backfill(db_client, backfill).await.map_err(|e| e.to_graphql_error())
     }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    
+    fn user(pantry_id: Option<&str>) -> User {
+        let created_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        User {
+            id: "user-1".to_string(),
+            email: "ann@example.com".to_string(),
+            password_hash: String::new(),
+            first_name: "Ann".to_string(),
+            last_name: "Lee".to_string(),
+            role: "User".to_string(),
+            pantry_id: pantry_id.map(str::to_string),
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+            is_active: true,
+            password_changed_at: None,
+            token_version: 0,
+        }
+    }
+
+    #[test]
+    fn only_associations_with_missing_pantries_are_dangling() {
+        assert_eq!(dangling_pantry_id(&user(Some("gone")), false), Some("gone".to_string()));
+        assert_eq!(dangling_pantry_id(&user(Some("pantry-1")), true), None);
+        assert_eq!(dangling_pantry_id(&user(None), false), None);
+    }
 }
@@ -1,14 +1,231 @@
-use std::collections::HashMap;
+use std::{ collections::HashMap, env, sync::{ Arc, LazyLock } };
 
-use async_graphql::{ Context, Object, Error };
-use aws_sdk_dynamodb::{ types::AttributeValue, Client };
-use tracing::{ info, warn };
-use crate::models::user::User;
+use async_graphql::{ Context, MergedObject, Object, Error };
+use aws_sdk_dynamodb::types::AttributeValue;
+use chrono::{ DateTime, Utc };
+use tracing::{ debug, warn };
+use crate::models::{
+    language::parse_language,
+    outbox::{ outbox_timestamp, OutboxEvent, OUTBOX_ENTITY_TYPE },
+    pantry::{ name_search_key, GeoPoint, Pantry },
+    pantry_access::{ AccessLevel, PantryAccess },
+    user::{ User, EMAIL_OWNER_PREFIX, USER_ENTITY_TYPE, USER_FIELD_ATTRIBUTES },
+};
 
+use crate::auth::{
+    guard::{ require_admin, require_claims, require_pantry_access, require_role },
+    jwt::Claims,
+};
+use crate::db::{
+    batch::batch_get_items,
+    logging::redact_item,
+    parse::parse_items,
+    projection::projection_for,
+    count::{ count_matching_items, count_partition_items },
+    health::{ db_latency_ms, schema_health },
+    integrity::{ scan_data_integrity, IntegrityReport, IntegrityTable },
+    outbox::OUTBOX_TABLE,
+    pantries::{
+        exclude_name_guards,
+        find_pantries_by_name_prefix,
+        find_pantry_by_code,
+        scan_pantry_groups,
+        NAME_GUARD_PREFIX,
+        NOT_NAME_GUARD_FILTER,
+    },
+    pantry_access::list_contact_agents,
+    scan::scan_all_items,
+    single_flight::SingleFlight,
+    users::{ exclude_email_owners, find_user_by_email, scan_users, NOT_EMAIL_OWNER_FILTER },
+};
 use crate::error::AppError;
 
+use super::cache::{ CacheLookup, PantryListCache };
+use super::context::{ db, now };
+use super::export::{ users_to_csv, USER_EXPORT_COLUMNS };
+
+use super::types::{
+    ChangeEvent,
+    ChangelogConnection,
+    Paginate,
+    PaginationInput,
+    PantryConnection,
+    PantryStats,
+    PantrySuggestion,
+    PantryTeamConnection,
+    SchemaHealth,
+    SortInput,
+    TeamMember,
+    UserConnection,
+};
+
+/// Maximum number of users returned by the deprecated `users` field
+///
+/// `users` predates pagination and used to return the whole table. It is kept
+/// working for existing clients, but capped so a large table can't blow up a
+/// single request. Removal plan: once the frontend has shipped on
+/// `usersConnection` for one release, delete `users` and this constant.
+const DEPRECATED_USERS_CAP: i32 = 100;
+
+/// Suggestions returned by `pantrySuggestions` when `limit` is not given
+const DEFAULT_SUGGESTION_LIMIT: i32 = 10;
+
+/// Most suggestions `pantrySuggestions` returns, larger limits are clamped
+const MAX_SUGGESTION_LIMIT: i32 = 25;
+
+/// Most users `exportUsersCsv` exports before refusing
+///
+/// The export reads the whole Users table into memory and into one response, roughly
+/// 200 bytes of CSV per user, and takes one scan request per 1MB of items.
+const USER_EXPORT_CAP: usize = 10_000;
+
+/// Complexity charged for resolvers that scan a whole table, on top of their children
+///
+/// A plain field costs 1, so this makes each scan-backed field count for as much as a
+/// large page of items, see `MAX_QUERY_COMPLEXITY` for the per-request budget.
+const SCAN_COMPLEXITY: usize = 1000;
+
+/// Items of a whole table scan, shared between the callers of one in-flight scan
+type SharedItems = Arc<Vec<HashMap<String, AttributeValue>>>;
+
+/// Full scans of the Pantries table in flight for `pantriesWithinRadius`, keyed by table
+///
+/// The scan doesn't depend on the search point, so concurrent radius searches share one.
+static PANTRY_SCANS: LazyLock<SingleFlight<SharedItems>> = LazyLock::new(SingleFlight::new);
+
+/// `(opt_status, is_self_managed)` pairs of a group scan, shared like `SharedItems`
+type SharedGroups = Arc<Vec<(String, String)>>;
+
+/// Group scans in flight for `pantryStats`, see `scan_pantry_groups`
+static PANTRY_GROUP_SCANS: LazyLock<SingleFlight<SharedGroups>> = LazyLock::new(SingleFlight::new);
+
+/// Whether debug-only queries such as `debugClaims` are part of the schema, see `DebugQuery`
+///
+/// Controlled by the `ENABLE_DEBUG_QUERIES` env var, leave it unset outside of dev and staging
+pub fn debug_queries_enabled() -> bool {
+    env::var("ENABLE_DEBUG_QUERIES").is_ok()
+}
+
+/// Gets each of `ids` once, since BatchGetItem rejects duplicate keys
+fn unique_ids(ids: &[String]) -> Vec<String> {
+    let mut unique_ids = ids.to_vec();
+    unique_ids.sort();
+    unique_ids.dedup();
+    unique_ids
+}
+
+/// Lines records found by a batch get up with the ids they were requested for
+///
+/// # Returns
+///
+/// One entry per id, in the order of `ids` and repeated for repeated ids; 'some' record if
+/// one was found for the id, 'none' otherwise
+fn align_to_ids<T: Clone>(ids: &[String], found: &HashMap<String, T>) -> Vec<Option<T>> {
+    ids.iter()
+        .map(|id| found.get(id).cloned())
+        .collect()
+}
+
+/// Gets the pantries within `radius_km` of a point, nearest first, with `search_origin` set
+///
+/// Backs `pantriesWithinRadius` and `pantriesOpenNow`.
+///
+/// # Errors
+///
+/// Returns Validation Error (400) App error variant if the point or radius is out of range
+///
+/// Returns Database Error (500) App error variant if the scan fails
+async fn pantries_near(
+    ctx: &Context<'_>,
+    lat: f64,
+    lng: f64,
+    radius_km: f64
+) -> Result<Vec<Pantry>, Error> {
+    let table_name = "Pantries";
+
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lng) {
+        return Err(
+            AppError::ValidationError(
+                "lat must be between -90 and 90 and lng between -180 and 180".to_string()
+            ).to_graphql_error()
+        );
+    }
+
+    if radius_km <= 0.0 {
+        return Err(
+            AppError::ValidationError("radius_km must be greater than 0".to_string()).to_graphql_error()
+        );
+    }
+
+    let db_client = db(ctx)?;
+
+    // pantries aren't indexed by location, so this reads the whole table, once for all
+    // concurrent searches
+    let items = PANTRY_SCANS.run(table_name, || async {
+        scan_all_items(db_client, table_name).await.map(Arc::new)
+    }).await.map_err(|e| e.to_graphql_error())?;
+
+    let origin = GeoPoint { lat, lng };
+
+    // the full scan includes name guard items, which aren't pantries
+    let items = items
+        .iter()
+        .filter(|item| {
+            !item
+                .get("id")
+                .and_then(|v| v.as_s().ok())
+                .is_some_and(|id| id.starts_with(NAME_GUARD_PREFIX))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut pantries = parse_items(&items, table_name, Pantry::from_item)
+        .map_err(|e| e.to_graphql_error())?
+        .into_iter()
+        .filter_map(|mut pantry| {
+            let distance = origin.distance_km(&pantry.address.geo?);
+            if distance > radius_km {
+                return None;
+            }
+            // thread the origin through so `distanceKm` can resolve for this pantry
+            pantry.search_origin = Some(origin);
+            Some((distance, pantry))
+        })
+        .collect::<Vec<(f64, Pantry)>>();
+
+    pantries.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    Ok(
+        pantries
+            .into_iter()
+            .map(|(_, pantry)| pantry)
+            .collect()
+    )
+}
+
+// Debug-only queries, merged into the query root only when `debug_queries_enabled`, see
+// `build_schema`, so they are absent from the schema otherwise
+#[derive(Debug)]
+pub struct DebugQuery;
+
+#[Object]
+impl DebugQuery {
+    // Returns the decoded jwt claims of the current request, for diagnosing auth issues
+    async fn debug_claims(&self, ctx: &Context<'_>) -> Result<Claims, Error> {
+        require_claims(ctx).cloned()
+    }
+}
+
+/// Query root served when debug queries are enabled, `QueryRoot` with `DebugQuery` merged in
+#[derive(MergedObject)]
+pub struct DebugQueryRoot(pub QueryRoot, pub DebugQuery);
+
 // GraphQL Schema
 //  Query root
+//
+//  Single-entity lookups (`userById`, `userByEmail`, ...) return null when nothing
+//  matches; "not found" is an expected answer, not an error. Errors are reserved
+//  for failures such as a db call erroring or the caller lacking access.
 #[derive(Debug)]
 pub struct QueryRoot;
 
@@ -17,20 +234,20 @@ impl QueryRoot {
     async fn sup(&self) -> String {
         "sup, crabs?".to_string()
     }
+
+    #[graphql(
+        deprecation = "use usersConnection",
+        complexity = "SCAN_COMPLEXITY + child_complexity"
+    )]
     async fn users(&self, ctx: &Context<'_>) -> Result<Vec<User>, Error> {
         let table_name = "Users";
-        // get db instance from context
-        let db_client = ctx.data::<Client>().map_err(|e| {
-            warn!("Failed to get db_client from context: {:?}", e);
-            AppError::InternalServerError(
-                "Failed to access application db_client".to_string()
-            ).to_graphql_error()
-        })?;
+        let db_client = db(ctx)?;
 
-        // scan table for all users
-        let response = db_client
-            .scan()
-            .table_name(table_name)
+        require_claims(ctx)?;
+
+        // scan table for users, capped until this field is removed
+        let response = exclude_email_owners(db_client.scan().table_name(table_name))
+            .limit(DEPRECATED_USERS_CAP)
             .send().await
             .map_err(|e| {
                 warn!("Failed to get db_client from context: {:?}", e);
@@ -39,100 +256,663 @@ impl QueryRoot {
                 ).to_graphql_error()
             })?;
 
-        info!("get all users response: {:?}", response);
+        debug!(
+            "get all users response: {:?}",
+            response.items().iter().map(redact_item).collect::<Vec<_>>()
+        );
 
-        let users = response
-            .items()
-            .iter()
-            .filter_map(|item| User::from_item(item))
-            .collect::<Vec<User>>();
+        let users = parse_items(response.items(), table_name, User::from_item).map_err(|e|
+            e.to_graphql_error()
+        )?;
 
-        info!("users from response items: {:?}", users);
+        debug!("users from response items: {:?}", users);
 
         Ok(users)
     }
 
-    // Get user by ID
-    async fn user_by_id(&self, ctx: &Context<'_>, user_id: String) -> Result<User, Error> {
+    // Get a page of users, pass `pageInfo.endCursor` from the previous page as `page.after`
+    // `sort` orders the users within the page only, see `SortInput`
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn users_connection(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] page: PaginationInput,
+        sort: Option<SortInput>
+    ) -> Result<UserConnection, Error> {
         let table_name = "Users";
 
-        // get db instance from context
-        let db_client = ctx.data::<Client>().map_err(|e| {
-            warn!("Failed to get db_client from context: {:?}", e);
-            AppError::InternalServerError(
-                "Failed to access application db_client".to_string()
-            ).to_graphql_error()
-        })?;
+        let db_client = db(ctx)?;
 
-        let mut key = HashMap::new();
-        key.insert("id".to_string(), AttributeValue::S(user_id.to_string()));
+        require_claims(ctx)?;
+
+        // only read the attributes of the selected user fields, plus the id for cursors
+        // and whatever the sort compares
+        let mut always = vec!["id"];
+        if let Some(sort) = &sort {
+            always.extend_from_slice(sort.user_attributes());
+        }
+        let nodes_selection = ctx.look_ahead().field("nodes");
+        let projection = projection_for(&nodes_selection, USER_FIELD_ATTRIBUTES, &always);
+
+        let response = exclude_email_owners(db_client.scan().table_name(table_name))
+            .set_projection_expression(projection.as_ref().map(|p| p.expression.clone()))
+            .set_expression_attribute_names(projection.as_ref().map(|p| p.names.clone()))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan users page: {:?}", e);
+                AppError::DatabaseError(
+                    "Failed to get users page from db".to_string()
+                ).to_graphql_error()
+            })?;
+
+        let parse = match projection {
+            Some(_) => User::from_projected_item,
+            None => User::from_item,
+        };
+        let mut nodes = parse_items(response.items(), table_name, parse).map_err(|e|
+            e.to_graphql_error()
+        )?;
 
+        if let Some(sort) = &sort {
+            sort.sort_users(&mut nodes);
+        }
+
+        Ok(UserConnection {
+            nodes,
+            page_info: page.page_info(
+                response.items(),
+                &["id"],
+                response.last_evaluated_key()
+            ),
+        })
+    }
+
+    // Get a page of users created between `start` and `end` inclusive, oldest first
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn users_created_between(
+        &self,
+        ctx: &Context<'_>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        #[graphql(default)] page: PaginationInput
+    ) -> Result<UserConnection, Error> {
+        let table_name = "Users";
+
+        if start > end {
+            return Err(
+                AppError::ValidationError("start must not be after end".to_string()).to_graphql_error()
+            );
+        }
+
+        let db_client = db(ctx)?;
+
+        require_claims(ctx)?;
+
+        // created_at is stored in the same to_string() format, which sorts chronologically
         let response = db_client
-            .get_item()
+            .query()
             .table_name(table_name)
-            .set_key(Some(key))
+            .index_name("CreatedAtIndex")
+            .key_condition_expression(
+                "entity_type = :entity_type AND created_at BETWEEN :start AND :end"
+            )
+            .expression_attribute_values(
+                ":entity_type",
+                AttributeValue::S(USER_ENTITY_TYPE.to_string())
+            )
+            .expression_attribute_values(":start", AttributeValue::S(start.to_string()))
+            .expression_attribute_values(":end", AttributeValue::S(end.to_string()))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
             .send().await
             .map_err(|e| {
-                warn!("Failed to get db_client from context: {:?}", e);
+                warn!("Failed to query users by created_at: {:?}", e);
                 AppError::DatabaseError(
-                    "Failed to get user by id from db".to_string()
+                    "Failed to get users created in range from db".to_string()
                 ).to_graphql_error()
             })?;
 
-        // Check for Some item from db
-        let item = response.item.ok_or_else(||
-            AppError::DatabaseError("No user found with that ID".to_string())
+        let nodes = parse_items(response.items(), table_name, User::from_item).map_err(|e|
+            e.to_graphql_error()
         )?;
 
-        // Return Some user converted from item or error
-        User::from_item(&item).ok_or_else(||
-            AppError::DatabaseError("No user found with that ID".to_string()).to_graphql_error()
-        )
+        Ok(UserConnection {
+            nodes,
+            page_info: page.page_info(
+                response.items(),
+                &["id", "entity_type", "created_at"],
+                response.last_evaluated_key()
+            ),
+        })
     }
 
-    // Get user by email
-    async fn user_by_email(&self, ctx: &Context<'_>, email: String) -> Result<User, Error> {
-        let table_name = "Users";
-        let index_name = "EmailIndex";
-        let key_condition_expression = "email = :email";
-
-        // get db instance from context
-        let db_client = ctx.data::<Client>().map_err(|e| {
-            warn!("Failed to get db_client from context: {:?}", e);
-            AppError::InternalServerError(
-                "Failed to access application db_client".to_string()
-            ).to_graphql_error()
-        })?;
+    // Get a page of changes recorded after `since`, oldest first, for Admins and partner
+    // systems syncing changes; pass `pageInfo.endCursor` as `after` to continue where a sync
+    // stopped, or the newest `createdAt` seen as `since` for the next sync
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn changelog(
+        &self,
+        ctx: &Context<'_>,
+        since: DateTime<Utc>,
+        #[graphql(default)] page: PaginationInput
+    ) -> Result<ChangelogConnection, Error> {
+        let table_name = OUTBOX_TABLE;
 
-        let mut key = HashMap::new();
-        key.insert("email".to_string(), AttributeValue::S(email.to_string()));
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
 
         let response = db_client
             .query()
             .table_name(table_name)
-            .index_name(index_name)
-            .key_condition_expression(key_condition_expression)
-            .expression_attribute_values(":email", AttributeValue::S(email))
+            .index_name("CreatedAtIndex")
+            .key_condition_expression("entity_type = :entity_type AND created_at > :since")
+            .expression_attribute_values(
+                ":entity_type",
+                AttributeValue::S(OUTBOX_ENTITY_TYPE.to_string())
+            )
+            .expression_attribute_values(":since", AttributeValue::S(outbox_timestamp(since)))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
             .send().await
             .map_err(|e| {
-                warn!("Failed to get db_client from context: {:?}", e);
+                warn!("Failed to query the changelog: {:?}", e);
+                AppError::DatabaseError("Failed to get changes from db".to_string()).to_graphql_error()
+            })?;
+
+        let nodes = parse_items(response.items(), table_name, OutboxEvent::from_item)
+            .map_err(|e| e.to_graphql_error())?
+            .into_iter()
+            .map(ChangeEvent::from)
+            .collect();
+
+        Ok(ChangelogConnection {
+            nodes,
+            page_info: page.page_info(
+                response.items(),
+                &["id", "entity_type", "created_at"],
+                response.last_evaluated_key()
+            ),
+        })
+    }
+
+    // Every user as a CSV document, without password hashes, for Admins exporting the roster
+    // Fails with a Validation error past `USER_EXPORT_CAP` users
+    #[graphql(complexity = "SCAN_COMPLEXITY")]
+    async fn export_users_csv(&self, ctx: &Context<'_>) -> Result<String, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        let users = scan_users(db_client, USER_EXPORT_COLUMNS, USER_EXPORT_CAP).await.map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        users_to_csv(&users).map_err(|e| e.to_graphql_error())
+    }
+
+    // Count all users without fetching them, still billed as a full table read
+    #[graphql(complexity = "SCAN_COMPLEXITY")]
+    async fn users_count(&self, ctx: &Context<'_>) -> Result<i64, Error> {
+        let db_client = db(ctx)?;
+
+        let values = HashMap::from([
+            (":email_owner_prefix".to_string(), AttributeValue::S(EMAIL_OWNER_PREFIX.to_string())),
+        ]);
+
+        count_matching_items(db_client, "Users", NOT_EMAIL_OWNER_FILTER, values).await.map_err(|e|
+            e.to_graphql_error()
+        )
+    }
+
+    // Get a page of pantries, pass `pageInfo.endCursor` from the previous page as `page.after`
+    // `sort` orders the pantries within the page only, see `SortInput`
+    // Pages are cached for a short while, see `cache::PantryListCache`
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn pantries(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] page: PaginationInput,
+        sort: Option<SortInput>
+    ) -> Result<PantryConnection, Error> {
+        let table_name = "Pantries";
+
+        let db_client = db(ctx)?;
+
+        // the page is cached unsorted, the sort is applied to each copy
+        let cache = ctx.data_opt::<PantryListCache>();
+        let cache_key = format!("{}:{}", page.limit(), page.after.as_deref().unwrap_or_default());
+        let generation = match cache.map(|cache| cache.get(&cache_key, now(ctx))) {
+            Some(CacheLookup::Hit(mut cached)) => {
+                // pages are shared by every `first` clamped to the same limit
+                cached.page_info.page_size_clamped = page.page_size_clamped();
+                if let Some(sort) = &sort {
+                    sort.sort_pantries(&mut cached.nodes);
+                }
+                return Ok(cached);
+            }
+            Some(CacheLookup::Miss { generation }) => generation,
+            None => 0,
+        };
+
+        let response = exclude_name_guards(db_client.scan().table_name(table_name))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan pantries page: {:?}", e);
                 AppError::DatabaseError(
-                    "Failed to get user by email from db".to_string()
+                    "Failed to get pantries page from db".to_string()
                 ).to_graphql_error()
             })?;
-        let items = response.items();
-        let first_item = items
-            .first()
-            .ok_or_else(||
+
+        let mut connection = PantryConnection {
+            nodes: parse_items(response.items(), table_name, Pantry::from_item).map_err(|e|
+                e.to_graphql_error()
+            )?,
+            page_info: page.page_info(
+                response.items(),
+                &["id"],
+                response.last_evaluated_key()
+            ),
+        };
+
+        if let Some(cache) = cache {
+            cache.insert(cache_key, connection.clone(), generation, now(ctx));
+        }
+
+        if let Some(sort) = &sort {
+            sort.sort_pantries(&mut connection.nodes);
+        }
+
+        Ok(connection)
+    }
+
+    // Get a page of pantries where a language is spoken, by ISO 639-1 code, e.g. "es"
+    // The filter runs after the read, so a page may hold fewer pantries than `page.first`
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn pantries_by_language(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+        #[graphql(default)] page: PaginationInput
+    ) -> Result<PantryConnection, Error> {
+        let table_name = "Pantries";
+
+        let db_client = db(ctx)?;
+
+        let code = parse_language(&code).map_err(|e| e.to_graphql_error())?;
+
+        let response = db_client
+            .scan()
+            .table_name(table_name)
+            .filter_expression(
+                format!("{} AND contains(languages, :language)", NOT_NAME_GUARD_FILTER)
+            )
+            .expression_attribute_values(
+                ":name_guard_prefix",
+                AttributeValue::S(NAME_GUARD_PREFIX.to_string())
+            )
+            .expression_attribute_values(":language", AttributeValue::S(code))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to scan pantries by language: {:?}", e);
                 AppError::DatabaseError(
-                    "No user found with that email address".to_string()
+                    "Failed to get pantries by language from db".to_string()
                 ).to_graphql_error()
-            )?;
+            })?;
 
-        User::from_item(first_item).ok_or_else(||
-            AppError::DatabaseError(
-                "No user found with that email address".to_string()
-            ).to_graphql_error()
+        let nodes = parse_items(response.items(), table_name, Pantry::from_item).map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        Ok(PantryConnection {
+            nodes,
+            page_info: page.page_info(
+                response.items(),
+                &["id"],
+                response.last_evaluated_key()
+            ),
+        })
+    }
+
+    // Count all pantries without fetching them, still billed as a full table read
+    #[graphql(complexity = "SCAN_COMPLEXITY")]
+    async fn pantries_count(&self, ctx: &Context<'_>) -> Result<i64, Error> {
+        let db_client = db(ctx)?;
+
+        let values = HashMap::from([
+            (":name_guard_prefix".to_string(), AttributeValue::S(NAME_GUARD_PREFIX.to_string())),
+        ]);
+
+        count_matching_items(db_client, "Pantries", NOT_NAME_GUARD_FILTER, values).await.map_err(|e|
+            e.to_graphql_error()
         )
     }
+
+    // Pantry counts per opt status and per self managed flag, for Managers and Admins
+    // Tallied from one projected scan, still billed as a full table read
+    #[graphql(complexity = "SCAN_COMPLEXITY")]
+    async fn pantry_stats(&self, ctx: &Context<'_>) -> Result<PantryStats, Error> {
+        let db_client = db(ctx)?;
+
+        require_role(ctx, AccessLevel::Manager)?;
+
+        // concurrent dashboard loads share one scan
+        let groups = PANTRY_GROUP_SCANS.run("Pantries", || async {
+            scan_pantry_groups(db_client).await.map(Arc::new)
+        }).await.map_err(|e| e.to_graphql_error())?;
+
+        Ok(PantryStats::tally(&groups))
+    }
+
+    // Get pantries within `radius_km` of a point, nearest first, with `distanceKm` populated
+    #[graphql(complexity = "SCAN_COMPLEXITY + child_complexity")]
+    async fn pantries_within_radius(
+        &self,
+        ctx: &Context<'_>,
+        lat: f64,
+        lng: f64,
+        radius_km: f64
+    ) -> Result<Vec<Pantry>, Error> {
+        pantries_near(ctx, lat, lng, radius_km).await
+    }
+
+    // Get pantries within `radius_km` of a point that are open right now, nearest first
+    // Pantries without hours are left out, as are temporarily closed ones
+    #[graphql(complexity = "SCAN_COMPLEXITY + child_complexity")]
+    async fn pantries_open_now(
+        &self,
+        ctx: &Context<'_>,
+        lat: f64,
+        lng: f64,
+        radius_km: f64
+    ) -> Result<Vec<Pantry>, Error> {
+        let now = now(ctx);
+
+        // hours aren't indexable, so they are checked on the pantries the radius leaves
+        Ok(
+            pantries_near(ctx, lat, lng, radius_km).await?
+                .into_iter()
+                .filter(|pantry| pantry.is_open_at(now) == Some(true))
+                .collect()
+        )
+    }
+
+    // Get a pantry by its short code, e.g. `PAN-00042`, null if no pantry has that code
+    async fn pantry_by_code(&self, ctx: &Context<'_>, code: String) -> Result<Option<Pantry>, Error> {
+        let db_client = db(ctx)?;
+
+        find_pantry_by_code(db_client, &code).await.map_err(|e| e.to_graphql_error())
+    }
+
+    // Get up to `limit` pantries whose name starts with `prefix`, ignoring case, in name order
+    // Meant for type-ahead, an empty prefix returns no suggestions
+    async fn pantry_suggestions(
+        &self,
+        ctx: &Context<'_>,
+        prefix: String,
+        limit: Option<i32>
+    ) -> Result<Vec<PantrySuggestion>, Error> {
+        let prefix = name_search_key(&prefix);
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let db_client = db(ctx)?;
+
+        let limit = limit.unwrap_or(DEFAULT_SUGGESTION_LIMIT).clamp(1, MAX_SUGGESTION_LIMIT);
+        let matches = find_pantries_by_name_prefix(db_client, &prefix, limit).await.map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        Ok(
+            matches
+                .into_iter()
+                .map(|(id, name)| PantrySuggestion { id, name })
+                .collect()
+        )
+    }
+
+    // Get a page of a pantry's team with each member's user record, for pantry Managers and Admins
+    #[graphql(complexity = "page.limit() as usize * child_complexity")]
+    async fn pantry_team(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String,
+        #[graphql(default)] page: PaginationInput
+    ) -> Result<PantryTeamConnection, Error> {
+        let table_name = "PantryAccess";
+
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        let response = db_client
+            .query()
+            .table_name(table_name)
+            .key_condition_expression("pantry_id = :pantry_id")
+            .expression_attribute_values(":pantry_id", AttributeValue::S(pantry_id.clone()))
+            .paginate(&page)
+            .map_err(|e| e.to_graphql_error())?
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to query pantry team: {:?}", e);
+                AppError::DatabaseError(
+                    "Failed to get pantry team from db".to_string()
+                ).to_graphql_error()
+            })?;
+
+        let rows = parse_items(response.items(), table_name, PantryAccess::from_item).map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        // resolve the page's users in one batch instead of a get per member, and only when
+        // the client selected them; look_ahead matches field names, so aliases don't hide it
+        let user_selected = ctx.look_ahead().field("nodes").field("user").exists();
+
+        let mut users_by_id = HashMap::new();
+        if user_selected {
+            let keys = rows
+                .iter()
+                .map(|row| HashMap::from([("id".to_string(), AttributeValue::S(row.user_id.clone()))]))
+                .collect::<Vec<_>>();
+
+            users_by_id = batch_get_items(db_client, "Users", keys).await
+                .map_err(|e| e.to_graphql_error())?
+                .iter()
+                .filter_map(User::from_item)
+                .map(|user| (user.id.clone(), user))
+                .collect::<HashMap<String, User>>();
+        }
+
+        let nodes = rows
+            .into_iter()
+            .map(|access| {
+                let user = users_by_id.remove(&access.user_id);
+                TeamMember { access, user }
+            })
+            .collect();
+
+        Ok(PantryTeamConnection {
+            nodes,
+            page_info: page.page_info(
+                response.items(),
+                &["pantry_id", "user_id"],
+                response.last_evaluated_key()
+            ),
+        })
+    }
+
+    // Get the contact agents of a pantry, for anyone with access to the pantry
+    async fn contact_agents_for_pantry(
+        &self,
+        ctx: &Context<'_>,
+        pantry_id: String
+    ) -> Result<Vec<PantryAccess>, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Viewer).await?;
+
+        list_contact_agents(db_client, &pantry_id).await.map_err(|e| e.to_graphql_error())
+    }
+
+    // Count a pantry's team without fetching it, for pantry Managers and Admins
+    async fn pantry_team_count(&self, ctx: &Context<'_>, pantry_id: String) -> Result<i64, Error> {
+        let db_client = db(ctx)?;
+
+        require_pantry_access(ctx, db_client, &pantry_id, AccessLevel::Manager).await?;
+
+        count_partition_items(db_client, "PantryAccess", "pantry_id", &pantry_id).await.map_err(|e|
+            e.to_graphql_error()
+        )
+    }
+
+    // Get user by ID, null if no user has that ID
+    async fn user_by_id(&self, ctx: &Context<'_>, user_id: String) -> Result<Option<User>, Error> {
+        let table_name = "Users";
+
+        let db_client = db(ctx)?;
+
+        require_claims(ctx)?;
+
+        let mut key = HashMap::new();
+        key.insert("id".to_string(), AttributeValue::S(user_id.to_string()));
+
+        // only read the attributes of the selected user fields
+        let projection = projection_for(&ctx.look_ahead(), USER_FIELD_ATTRIBUTES, &["id"]);
+
+        let response = db_client
+            .get_item()
+            .table_name(table_name)
+            .set_key(Some(key))
+            .set_projection_expression(projection.as_ref().map(|p| p.expression.clone()))
+            .set_expression_attribute_names(projection.as_ref().map(|p| p.names.clone()))
+            .send().await
+            .map_err(|e| {
+                warn!("Failed to get user by id: {:?}", e);
+                AppError::DatabaseError(
+                    "Failed to get user by id from db".to_string()
+                ).to_graphql_error()
+            })?;
+
+        let item = response.item.as_ref();
+
+        Ok(match projection {
+            Some(_) => item.and_then(User::from_projected_item),
+            None => item.and_then(User::from_item),
+        })
+    }
+
+    // Get users for a list of ids, results line up with `ids` and are null where no user exists
+    // or the user was deleted, e.g. merged into another
+    #[graphql(complexity = "ids.len().max(1) * child_complexity")]
+    async fn users_by_ids(
+        &self,
+        ctx: &Context<'_>,
+        ids: Vec<String>
+    ) -> Result<Vec<Option<User>>, Error> {
+        let table_name = "Users";
+
+        let db_client = db(ctx)?;
+
+        require_claims(ctx)?;
+
+        let keys = unique_ids(&ids)
+            .into_iter()
+            .map(|id| HashMap::from([("id".to_string(), AttributeValue::S(id))]))
+            .collect::<Vec<_>>();
+
+        let items = batch_get_items(db_client, table_name, keys).await.map_err(|e|
+            e.to_graphql_error()
+        )?;
+
+        let users_by_id = items
+            .iter()
+            .filter_map(User::from_item)
+            .filter(|user| user.deleted_at.is_none())
+            .map(|user| (user.id.clone(), user))
+            .collect::<HashMap<String, User>>();
+
+        Ok(align_to_ids(&ids, &users_by_id))
+    }
+
+    // Get user by email, null if no user has that email address
+    async fn user_by_email(&self, ctx: &Context<'_>, email: String) -> Result<Option<User>, Error> {
+        let db_client = db(ctx)?;
+
+        require_claims(ctx)?;
+
+        find_user_by_email(db_client, &email).await.map_err(|e| e.to_graphql_error())
+    }
+
+    // Status of every expected table and index, for Admins checking a deploy
+    async fn schema_health(&self, ctx: &Context<'_>) -> Result<SchemaHealth, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        let tables = schema_health(db_client).await.map_err(|e| e.to_graphql_error())?;
+
+        Ok(SchemaHealth {
+            healthy: tables.iter().all(|table| table.healthy),
+            tables,
+        })
+    }
+
+    // Round trip of a trivial db call in milliseconds, for Admins telling db latency from app latency
+    async fn db_latency_ms(&self, ctx: &Context<'_>) -> Result<i64, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        db_latency_ms(db_client).await.map_err(|e| e.to_graphql_error())
+    }
+
+    // Items of a table that fail to parse into their model and so vanish from listings,
+    // for Admins hunting bad rows after a schema change; billed as a full table read
+    #[graphql(complexity = "SCAN_COMPLEXITY")]
+    async fn scan_data_integrity(
+        &self,
+        ctx: &Context<'_>,
+        table: IntegrityTable
+    ) -> Result<IntegrityReport, Error> {
+        let db_client = db(ctx)?;
+
+        require_admin(ctx)?;
+
+        scan_data_integrity(db_client, table).await.map_err(|e| e.to_graphql_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn asks_for_each_id_once() {
+        assert_eq!(unique_ids(&ids(&["b", "a", "b", "c", "a"])), ids(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn lines_results_up_with_the_requested_ids() {
+        let found = HashMap::from([
+            ("a".to_string(), "Ana"),
+            ("c".to_string(), "Cy"),
+        ]);
+
+        assert_eq!(
+            align_to_ids(&ids(&["c", "missing", "a", "c"]), &found),
+            [Some("Cy"), None, Some("Ana"), Some("Cy")]
+        );
+        assert!(align_to_ids(&[], &found).is_empty());
+    }
 }
@@ -1 +1,854 @@
-// probably worth moving all the GQL IO types into this file
\ No newline at end of file
+// probably worth moving all the GQL IO types into this file
+
+use std::{ cmp::Ordering, collections::HashMap };
+
+use async_graphql::{ Enum, InputObject, MaybeUndefined, OutputType, SimpleObject };
+use aws_sdk_dynamodb::{
+    operation::{ query::builders::QueryFluentBuilder, scan::builders::ScanFluentBuilder },
+    types::AttributeValue,
+};
+use chrono::{ DateTime, Utc };
+
+use crate::db::{
+    health::TableHealth,
+    pagination::{ decode_cursor, encode_cursor, key_of, max_page_size, page_size },
+};
+use crate::error::AppError;
+use crate::models::{
+    normalize::{ normalize_email, normalize_name, normalize_text },
+    operating_hours::OperatingHours,
+    outbox::OutboxEvent,
+    pantry::{ Address, OptStatus, Pantry },
+    pantry_access::{ AccessLevel, PantryAccess },
+    user::User,
+};
+
+/// Pagination arguments shared by every paginated field
+///
+/// # Fields
+///
+/// * `first` - page size, defaults to 25 and is clamped to 1..=`MAX_PAGE_SIZE` (100 when unset)
+/// * `after` - `pageInfo.endCursor` of the previous page, omit for the first page
+#[derive(Debug, Default, InputObject)]
+pub struct PaginationInput {
+    pub first: Option<i32>,
+    pub after: Option<String>,
+}
+
+impl PaginationInput {
+    /// Page size to request, clamped to `MAX_PAGE_SIZE`
+    pub fn limit(&self) -> i32 {
+        page_size(self.first)
+    }
+
+    /// Whether `first` asked for more than `MAX_PAGE_SIZE` items and was lowered
+    pub fn page_size_clamped(&self) -> bool {
+        self.first.is_some_and(|first| first > max_page_size())
+    }
+
+    /// Decodes `after` into the `ExclusiveStartKey` of the page
+    ///
+    /// # Errors
+    ///
+    /// Returns a ValidationError (400) App error variant if the cursor is malformed
+    pub fn exclusive_start_key(&self) -> Result<Option<HashMap<String, AttributeValue>>, AppError> {
+        self.after.as_deref().map(decode_cursor).transpose()
+    }
+
+    /// Builds the page info of a page read with these arguments
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - raw items of the page
+    /// * `key_attributes` - names of the key attributes of the table or index read
+    /// * `last_evaluated_key` - last evaluated key returned with the page
+    pub fn page_info(
+        &self,
+        items: &[HashMap<String, AttributeValue>],
+        key_attributes: &[&str],
+        last_evaluated_key: Option<&HashMap<String, AttributeValue>>
+    ) -> PageInfo {
+        PageInfo {
+            page_size_clamped: self.page_size_clamped(),
+            ..PageInfo::from_page(items, key_attributes, self.after.as_deref(), last_evaluated_key)
+        }
+    }
+}
+
+/// Field a list can be sorted by
+///
+/// # Variants
+///
+/// * `Name` - users by last then first name, pantries by name, ignoring case
+/// * `CreatedAt` - date and time of creation
+/// * `UpdatedAt` - date and time of the last update
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum SortField {
+    Name,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Direction of a sort
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Enum)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Sort arguments shared by list fields
+///
+/// Scans return items in no useful order, so the sort is applied after the page is read
+/// and only orders the items within that page, not the whole list. Cursors still follow
+/// the scan, so paging through a sorted list is not globally sorted.
+///
+/// # Fields
+///
+/// * `field` - field to sort by
+/// * `direction` - ascending by default
+#[derive(Debug, InputObject)]
+pub struct SortInput {
+    pub field: SortField,
+    #[graphql(default)]
+    pub direction: SortDirection,
+}
+
+impl SortInput {
+    /// Sorts nodes by a comparison of the sort field, reversed for a descending sort
+    fn apply<T>(&self, nodes: &mut [T], compare: impl Fn(&T, &T) -> Ordering) {
+        match self.direction {
+            SortDirection::Asc => nodes.sort_by(|a, b| compare(a, b)),
+            SortDirection::Desc => nodes.sort_by(|a, b| compare(b, a)),
+        }
+    }
+
+    /// Attributes a user item needs for this sort, read even when the field isn't selected
+    pub fn user_attributes(&self) -> &'static [&'static str] {
+        match self.field {
+            SortField::Name => &["last_name", "first_name"],
+            SortField::CreatedAt => &["created_at"],
+            SortField::UpdatedAt => &["updated_at"],
+        }
+    }
+
+    /// Sorts a page of users
+    pub fn sort_users(&self, users: &mut [User]) {
+        match self.field {
+            SortField::Name =>
+                self.apply(users, |a, b| {
+                    a.last_name
+                        .to_lowercase()
+                        .cmp(&b.last_name.to_lowercase())
+                        .then_with(|| a.first_name.to_lowercase().cmp(&b.first_name.to_lowercase()))
+                }),
+            SortField::CreatedAt => self.apply(users, |a, b| a.created_at.cmp(&b.created_at)),
+            SortField::UpdatedAt => self.apply(users, |a, b| a.updated_at.cmp(&b.updated_at)),
+        }
+    }
+
+    /// Sorts a page of pantries
+    pub fn sort_pantries(&self, pantries: &mut [Pantry]) {
+        match self.field {
+            SortField::Name =>
+                self.apply(pantries, |a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase())),
+            SortField::CreatedAt => self.apply(pantries, |a, b| a.created_at.cmp(&b.created_at)),
+            SortField::UpdatedAt => self.apply(pantries, |a, b| a.updated_at.cmp(&b.updated_at)),
+        }
+    }
+}
+
+/// Applies `PaginationInput` to a DynamoDB scan or query request
+pub trait Paginate: Sized {
+    /// Sets the request's `Limit` and `ExclusiveStartKey` from the pagination arguments
+    ///
+    /// # Errors
+    ///
+    /// Returns a ValidationError (400) App error variant if the cursor is malformed
+    fn paginate(self, page: &PaginationInput) -> Result<Self, AppError>;
+}
+
+impl Paginate for ScanFluentBuilder {
+    fn paginate(self, page: &PaginationInput) -> Result<Self, AppError> {
+        Ok(self.limit(page.limit()).set_exclusive_start_key(page.exclusive_start_key()?))
+    }
+}
+
+impl Paginate for QueryFluentBuilder {
+    fn paginate(self, page: &PaginationInput) -> Result<Self, AppError> {
+        Ok(self.limit(page.limit()).set_exclusive_start_key(page.exclusive_start_key()?))
+    }
+}
+
+/// Relay style pagination metadata for a connection
+///
+/// # Fields
+///
+/// * `has_next_page` - true when DynamoDB returned a `LastEvaluatedKey`
+/// * `has_previous_page` - true when the page was requested with an `after` cursor
+/// * `start_cursor` - cursor of the first item on the page
+/// * `end_cursor` - cursor of the last item on the page, pass as `after` for the next page
+/// * `page_size_clamped` - true when `first` was above `MAX_PAGE_SIZE` and the page was cut to it
+#[derive(Clone, Debug, SimpleObject)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+    pub page_size_clamped: bool,
+}
+
+impl PageInfo {
+    /// Builds page info from the raw items of a query or scan page
+    ///
+    /// DynamoDB can return a `LastEvaluatedKey` when the page ends exactly on the
+    /// last item, so `has_next_page` may be true for a following page that is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - raw items of the page
+    /// * `key_attributes` - names of the table key attributes used to build cursors
+    /// * `after` - cursor the page was requested with, if any
+    /// * `last_evaluated_key` - last evaluated key returned with the page
+    ///
+    /// # Returns
+    ///
+    /// PageInfo for the page
+    pub fn from_page(
+        items: &[HashMap<String, AttributeValue>],
+        key_attributes: &[&str],
+        after: Option<&str>,
+        last_evaluated_key: Option<&HashMap<String, AttributeValue>>
+    ) -> Self {
+        let start_cursor = items.first().map(|item| encode_cursor(&key_of(item, key_attributes)));
+
+        let end_cursor = match last_evaluated_key {
+            Some(key) => Some(encode_cursor(key)),
+            None => items.last().map(|item| encode_cursor(&key_of(item, key_attributes))),
+        };
+
+        Self {
+            has_next_page: last_evaluated_key.is_some(),
+            has_previous_page: after.is_some(),
+            start_cursor,
+            end_cursor,
+            page_size_clamped: false,
+        }
+    }
+}
+
+/// A single page of users returned by `usersConnection`
+///
+/// # Fields
+///
+/// * `nodes` - users on this page
+/// * `page_info` - pagination metadata for the page
+#[derive(Debug, SimpleObject)]
+pub struct UserConnection {
+    pub nodes: Vec<User>,
+    pub page_info: PageInfo,
+}
+
+/// A single page of pantries returned by `pantries`
+///
+/// # Fields
+///
+/// * `nodes` - pantries on this page
+/// * `page_info` - pagination metadata for the page
+#[derive(Clone, Debug, SimpleObject)]
+pub struct PantryConnection {
+    pub nodes: Vec<Pantry>,
+    pub page_info: PageInfo,
+}
+
+/// A change recorded in the outbox, returned by `changelog`
+///
+/// # Fields
+///
+/// * `id` - ID of the event, the same one webhooks deliver it with
+/// * `event` - event name, e.g. `pantry.opt_status_changed`
+/// * `created_at` - when the change happened
+/// * `payload` - the event as a JSON document, the body webhooks deliver
+#[derive(Debug, SimpleObject)]
+pub struct ChangeEvent {
+    pub id: String,
+    pub event: String,
+    pub created_at: DateTime<Utc>,
+    pub payload: String,
+}
+
+impl From<OutboxEvent> for ChangeEvent {
+    fn from(event: OutboxEvent) -> Self {
+        Self {
+            id: event.id,
+            event: event.event,
+            created_at: event.created_at,
+            payload: event.payload,
+        }
+    }
+}
+
+/// A single page of changes returned by `changelog`
+///
+/// # Fields
+///
+/// * `nodes` - changes on this page, oldest first
+/// * `page_info` - pagination metadata for the page, `endCursor` resumes the sync
+#[derive(Debug, SimpleObject)]
+pub struct ChangelogConnection {
+    pub nodes: Vec<ChangeEvent>,
+    pub page_info: PageInfo,
+}
+
+/// A pantry matching the text typed into a search box, returned by `pantrySuggestions`
+///
+/// # Fields
+///
+/// * `id` - ID of the pantry
+/// * `name` - name of the pantry as saved
+#[derive(Debug, SimpleObject)]
+pub struct PantrySuggestion {
+    pub id: String,
+    pub name: String,
+}
+
+/// Status of the application's tables for `schemaHealth`
+///
+/// # Fields
+///
+/// * `healthy` - whether every table and index is `ACTIVE`
+/// * `tables` - status of each expected table and its indexes
+#[derive(Debug, SimpleObject)]
+pub struct SchemaHealth {
+    pub healthy: bool,
+    pub tables: Vec<TableHealth>,
+}
+
+/// Number of pantries sharing one value of an attribute
+///
+/// # Fields
+///
+/// * `key` - the attribute value, e.g. `T1` or `true`
+/// * `count` - number of pantries with that value
+#[derive(Debug, SimpleObject)]
+pub struct GroupCount {
+    pub key: String,
+    pub count: i64,
+}
+
+/// Pantry totals for dashboards, returned by `pantryStats`
+///
+/// # Fields
+///
+/// * `total` - number of pantries
+/// * `by_opt_status` - pantries per opt status, every status listed even at 0
+/// * `by_self_managed` - pantries per `isSelfManaged` value, `true` and `false` listed even at 0
+#[derive(Debug, SimpleObject)]
+pub struct PantryStats {
+    pub total: i64,
+    pub by_opt_status: Vec<GroupCount>,
+    pub by_self_managed: Vec<GroupCount>,
+}
+
+impl PantryStats {
+    /// Tallies `(opt_status, is_self_managed)` pairs as read by `scan_pantry_groups`
+    ///
+    /// Values outside the known ones get their own group rather than being dropped.
+    pub fn tally(groups: &[(String, String)]) -> Self {
+        let count = |keys: Vec<&str>, value: fn(&(String, String)) -> &str| {
+            let mut counts = keys
+                .into_iter()
+                .map(|key| GroupCount { key: key.to_string(), count: 0 })
+                .collect::<Vec<GroupCount>>();
+
+            for group in groups {
+                let key = value(group);
+                match counts.iter_mut().find(|c| c.key == key) {
+                    Some(c) => {
+                        c.count += 1;
+                    }
+                    None => counts.push(GroupCount { key: key.to_string(), count: 1 }),
+                }
+            }
+
+            counts
+        };
+
+        Self {
+            total: groups.len() as i64,
+            by_opt_status: count(
+                [OptStatus::T1, OptStatus::T2, OptStatus::T3]
+                    .into_iter()
+                    .map(OptStatus::to_str)
+                    .collect(),
+                |(opt_status, _)| opt_status
+            ),
+            by_self_managed: count(vec!["true", "false"], |(_, is_self_managed)| is_self_managed),
+        }
+    }
+}
+
+/// Result of a successful login
+///
+/// # Fields
+///
+/// * `token` - signed jwt to send as a bearer token
+/// * `expires_at` - when the token stops being accepted
+/// * `user` - the user that logged in, so the client needs no follow-up `me` call
+#[derive(Debug, SimpleObject)]
+pub struct LoginPayload {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+    pub user: User,
+}
+
+/// Result of a delete mutation
+///
+/// # Fields
+///
+/// * `id` - ID of the deleted record
+/// * `deleted` - true once the record is gone
+#[derive(Debug, SimpleObject)]
+pub struct DeletePayload {
+    pub id: String,
+    pub deleted: bool,
+}
+
+/// Result of `clearInvalidPantryAssociation`
+///
+/// # Fields
+///
+/// * `cleared` - true if the user's pantry no longer existed and the association was removed
+/// * `user` - the user, without the association if it was cleared
+#[derive(Debug, SimpleObject)]
+pub struct ClearPantryAssociationPayload {
+    pub cleared: bool,
+    pub user: User,
+}
+
+/// Result of merging one user into another
+///
+/// # Fields
+///
+/// * `target` - the user that was kept, after the merge
+/// * `access_moved` - access rows moved to the target for pantries it had no access to
+/// * `access_merged` - access rows folded into an existing row of the target, keeping the higher level
+/// * `pantry_moved` - whether the source's pantry association moved to the target
+#[derive(Debug, SimpleObject)]
+pub struct MergeUsersPayload {
+    pub target: User,
+    pub access_moved: i32,
+    pub access_merged: i32,
+    pub pantry_moved: bool,
+}
+
+/// An entry of a batch mutation that could not be applied
+///
+/// # Fields
+///
+/// * `index` - position of the entry in the mutation's input list
+/// * `id` - ID the entry referred to, if it had one
+/// * `reason` - why the entry failed
+/// * `code` - error code, the same as a GraphQL error's `extensions.code`
+#[derive(Debug, SimpleObject)]
+pub struct FailedItem {
+    pub index: i32,
+    pub id: Option<String>,
+    pub reason: String,
+    pub code: String,
+}
+
+impl FailedItem {
+    /// Reports an entry that failed with an App error
+    pub fn from_error(index: usize, id: Option<String>, error: &AppError) -> Self {
+        Self {
+            index: index as i32,
+            id,
+            reason: error.message(),
+            code: error.code().to_string(),
+        }
+    }
+}
+
+/// Outcome of a batch mutation that applies each entry on its own
+///
+/// Entries don't depend on each other, so some can succeed while others fail. Every
+/// batch mutation with partial success returns this shape, each item type needs a
+/// `concrete` name below.
+///
+/// # Fields
+///
+/// * `succeeded` - results of the entries that were applied, in input order
+/// * `failed` - entries that were not applied, in input order
+#[derive(Debug, SimpleObject)]
+#[graphql(concrete(name = "PantryBatchResult", params(Pantry)))]
+pub struct BatchResult<T: OutputType> {
+    pub succeeded: Vec<T>,
+    pub failed: Vec<FailedItem>,
+}
+
+/// A member of a pantry's team
+///
+/// # Fields
+///
+/// * `access` - the member's access row for the pantry
+/// * `user` - the member's user record, null if the user no longer exists
+#[derive(Debug, SimpleObject)]
+pub struct TeamMember {
+    pub access: PantryAccess,
+    pub user: Option<User>,
+}
+
+/// A single page of team members returned by `pantryTeam`
+///
+/// # Fields
+///
+/// * `nodes` - team members on this page
+/// * `page_info` - pagination metadata for the page
+#[derive(Debug, SimpleObject)]
+pub struct PantryTeamConnection {
+    pub nodes: Vec<TeamMember>,
+    pub page_info: PageInfo,
+}
+
+/// One user's access level to apply in `setPantryAccess`
+///
+/// # Fields
+///
+/// * `user_id` - ID of the user to grant access to
+/// * `access_level` - level of access to grant, replacing any existing level
+#[derive(Debug, InputObject)]
+pub struct AccessGrantInput {
+    pub user_id: String,
+    pub access_level: AccessLevel,
+}
+
+/// Fields of a new pantry for `createPantry`
+///
+/// # Fields
+///
+/// * `name` - name of the pantry
+/// * `opt_status` - involvement level in the program
+/// * `address` - physical address of the pantry
+/// * `is_self_managed` - whether the pantry's own staff manage it on this platform
+/// * `phone` - phone number of the pantry
+/// * `email` - email address of the pantry
+/// * `hours` - optional operating schedule
+/// * `timezone` - IANA timezone name, derived from the address state if omitted
+/// * `languages` - ISO 639-1 codes of the languages spoken, e.g. `["en", "es"]`
+#[derive(Debug, InputObject)]
+pub struct CreatePantryInput {
+    pub name: String,
+    pub opt_status: OptStatus,
+    pub address: Address,
+    pub is_self_managed: bool,
+    pub phone: String,
+    pub email: String,
+    pub hours: Option<OperatingHours>,
+    pub timezone: Option<String>,
+    #[graphql(default)]
+    pub languages: Vec<String>,
+}
+
+impl CreatePantryInput {
+    /// Trims the text fields and lowercases the email, see `models::normalize`
+    pub fn normalized(self) -> Self {
+        Self {
+            name: normalize_name(&self.name),
+            address: self.address.normalized(),
+            phone: normalize_text(&self.phone),
+            email: normalize_email(&self.email),
+            ..self
+        }
+    }
+}
+
+/// Changes to a pantry for `updatePantry`, omitted fields are left as they are
+///
+/// # Fields
+///
+/// * `name` - new name of the pantry
+/// * `opt_status` - new involvement level in the program
+/// * `address` - new physical address, replaces the whole address
+/// * `is_self_managed` - whether the pantry's own staff manage it on this platform
+/// * `phone` - new phone number
+/// * `email` - new email address
+/// * `hours` - new operating schedule, replaces the whole schedule; null removes it
+/// * `timezone` - new IANA timezone name
+/// * `languages` - ISO 639-1 codes of the languages spoken, replaces the whole list
+#[derive(Debug, InputObject)]
+pub struct UpdatePantryInput {
+    pub name: Option<String>,
+    pub opt_status: Option<OptStatus>,
+    pub address: Option<Address>,
+    pub is_self_managed: Option<bool>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub hours: MaybeUndefined<OperatingHours>,
+    pub timezone: Option<String>,
+    pub languages: Option<Vec<String>>,
+}
+
+impl UpdatePantryInput {
+    /// Trims the text fields and lowercases the email, see `models::normalize`
+    pub fn normalized(self) -> Self {
+        Self {
+            name: self.name.as_deref().map(normalize_name),
+            address: self.address.map(Address::normalized),
+            phone: self.phone.as_deref().map(normalize_text),
+            email: self.email.as_deref().map(normalize_email),
+            ..self
+        }
+    }
+}
+
+/// Fields of a new user for `createUser`
+///
+/// # Fields
+///
+/// * `email` - email address, also the login
+/// * `password` - plain text password, only its hash is stored
+/// * `first_name` - user's first name
+/// * `last_name` - user's last name
+#[derive(InputObject)]
+pub struct CreateUserInput {
+    pub email: String,
+    #[graphql(secret)]
+    pub password: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+impl CreateUserInput {
+    /// Lowercases the email and collapses whitespace in the names, see `models::normalize`
+    pub fn normalized(self) -> Self {
+        Self {
+            email: normalize_email(&self.email),
+            first_name: normalize_name(&self.first_name),
+            last_name: normalize_name(&self.last_name),
+            ..self
+        }
+    }
+}
+
+/// Changes to a user for `updateUser`, omitted fields are left as they are
+///
+/// # Fields
+///
+/// * `first_name` - new first name
+/// * `last_name` - new last name
+/// * `pantry_id` - ID of the pantry the user is agent for; null removes the association
+#[derive(Debug, InputObject)]
+pub struct UpdateUserInput {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub pantry_id: MaybeUndefined<String>,
+}
+
+impl UpdateUserInput {
+    /// Trims the names and collapses their internal whitespace, see `models::normalize`
+    pub fn normalized(self) -> Self {
+        Self {
+            first_name: self.first_name.as_deref().map(normalize_name),
+            last_name: self.last_name.as_deref().map(normalize_name),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{ EmptyMutation, EmptySubscription, Object, Schema };
+
+    use super::*;
+
+    fn item(id: &str) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            ("id".to_string(), AttributeValue::S(id.to_string())),
+            ("name".to_string(), AttributeValue::S(format!("Pantry {}", id))),
+        ])
+    }
+
+    fn cursor(id: &str) -> String {
+        encode_cursor(&HashMap::from([("id".to_string(), AttributeValue::S(id.to_string()))]))
+    }
+
+    #[test]
+    fn cursors_point_at_the_first_and_last_items() {
+        let items = [item("a"), item("b")];
+        let info = PageInfo::from_page(&items, &["id"], None, None);
+
+        assert_eq!(info.start_cursor, Some(cursor("a")));
+        assert_eq!(info.end_cursor, Some(cursor("b")));
+        assert!(!info.has_next_page);
+        assert!(!info.has_previous_page);
+    }
+
+    #[test]
+    fn end_cursor_follows_the_last_evaluated_key() {
+        let items = [item("a")];
+        let last_key = HashMap::from([("id".to_string(), AttributeValue::S("z".to_string()))]);
+        let info = PageInfo::from_page(&items, &["id"], Some("previous"), Some(&last_key));
+
+        assert_eq!(info.end_cursor, Some(cursor("z")));
+        assert!(info.has_next_page);
+        assert!(info.has_previous_page);
+    }
+
+    #[test]
+    fn empty_pages_have_no_cursors() {
+        let info = PageInfo::from_page(&[], &["id"], None, None);
+
+        assert_eq!(info.start_cursor, None);
+        assert_eq!(info.end_cursor, None);
+    }
+
+    #[test]
+    fn reports_clamped_page_sizes() {
+        let input = PaginationInput { first: Some(max_page_size() + 1), after: None };
+
+        assert_eq!(input.limit(), max_page_size());
+        assert!(input.page_info(&[], &["id"], None).page_size_clamped);
+        assert!(!(PaginationInput { first: Some(1), after: None }).page_size_clamped());
+    }
+
+    fn user(id: &str, first_name: &str, last_name: &str, created_secs: i64) -> User {
+        let created_at = DateTime::from_timestamp(1_700_000_000 + created_secs, 0).unwrap();
+        User {
+            id: id.to_string(),
+            email: format!("{}@example.com", id),
+            password_hash: String::new(),
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            role: "User".to_string(),
+            pantry_id: None,
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+            is_active: true,
+            password_changed_at: None,
+            token_version: 0,
+        }
+    }
+
+    fn ids(users: &[User]) -> Vec<&str> {
+        users.iter().map(|user| user.id.as_str()).collect()
+    }
+
+    fn sort(field: SortField, direction: SortDirection) -> SortInput {
+        SortInput { field, direction }
+    }
+
+    #[test]
+    fn sorts_users_by_last_then_first_name_ignoring_case() {
+        let mut users = vec![
+            user("c", "bob", "smith", 0),
+            user("a", "Ann", "Smith", 1),
+            user("b", "Zed", "adams", 2)
+        ];
+
+        sort(SortField::Name, SortDirection::Asc).sort_users(&mut users);
+        assert_eq!(ids(&users), ["b", "a", "c"]);
+
+        sort(SortField::Name, SortDirection::Desc).sort_users(&mut users);
+        assert_eq!(ids(&users), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn sorts_users_by_creation_time() {
+        let mut users = vec![user("b", "B", "B", 20), user("c", "C", "C", 30), user("a", "A", "A", 10)];
+
+        sort(SortField::CreatedAt, SortDirection::Asc).sort_users(&mut users);
+        assert_eq!(ids(&users), ["a", "b", "c"]);
+
+        sort(SortField::CreatedAt, SortDirection::Desc).sort_users(&mut users);
+        assert_eq!(ids(&users), ["c", "b", "a"]);
+    }
+
+    fn counts(groups: &[GroupCount]) -> Vec<(&str, i64)> {
+        groups.iter().map(|group| (group.key.as_str(), group.count)).collect()
+    }
+
+    #[test]
+    fn tallies_pantries_by_opt_status_and_self_management() {
+        let groups = [("T1", "true"), ("T2", "false"), ("T1", "false"), ("T4", "maybe")]
+            .map(|(opt_status, is_self_managed)| {
+                (opt_status.to_string(), is_self_managed.to_string())
+            });
+
+        let stats = PantryStats::tally(&groups);
+
+        assert_eq!(stats.total, 4);
+        assert_eq!(counts(&stats.by_opt_status), [("T1", 2), ("T2", 1), ("T3", 0), ("T4", 1)]);
+        assert_eq!(counts(&stats.by_self_managed), [("true", 1), ("false", 2), ("maybe", 1)]);
+    }
+
+    #[test]
+    fn empty_tallies_list_every_known_group() {
+        let stats = PantryStats::tally(&[]);
+
+        assert_eq!(stats.total, 0);
+        assert_eq!(counts(&stats.by_opt_status), [("T1", 0), ("T2", 0), ("T3", 0)]);
+        assert_eq!(counts(&stats.by_self_managed), [("true", 0), ("false", 0)]);
+    }
+
+    struct BatchQuery;
+
+    #[Object]
+    impl BatchQuery {
+        async fn result(&self) -> BatchResult<Pantry> {
+            let address = Address {
+                street: "1 Main St".to_string(),
+                unit: None,
+                city: "Madison".to_string(),
+                state: "WI".to_string(),
+                zipcode: "53703".to_string(),
+                geo: None,
+            };
+            let pantry = Pantry::new(
+                "pantry-1".to_string(),
+                "Food Shelf".to_string(),
+                OptStatus::T2,
+                address,
+                false,
+                "555-0100".to_string(),
+                "pantry@example.com".to_string()
+            ).unwrap();
+            let error = AppError::NotFound("No pantry found with that ID".to_string());
+
+            BatchResult {
+                succeeded: vec![pantry],
+                failed: vec![FailedItem::from_error(1, Some("pantry-2".to_string()), &error)],
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_results_serialize_successes_and_failures() {
+        let schema = Schema::new(BatchQuery, EmptyMutation, EmptySubscription);
+        let query = "{ result {
+            __typename
+            succeeded { id optStatus }
+            failed { index id reason code }
+        } }";
+
+        let response = schema.execute(query).await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({
+                "result": {
+                    "__typename": "PantryBatchResult",
+                    "succeeded": [{ "id": "pantry-1", "optStatus": "T2" }],
+                    "failed": [{
+                        "index": 1,
+                        "id": "pantry-2",
+                        "reason": "No pantry found with that ID",
+                        "code": "NOT_FOUND",
+                    }],
+                },
+            })
+        );
+    }
+}
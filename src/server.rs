@@ -0,0 +1,242 @@
+//! Local development server.
+//!
+//! Serves the GraphQL API and the GraphiQL playground over axum on port 3000, and a
+//! readiness probe at `GET /ready`, see `db::health::readiness`.
+//! Only compiled with the `local-server` feature.
+//!
+//! The playground is controlled by the `ENABLE_PLAYGROUND` env var:
+//! * unset or `true` - served to anyone, the default for local development
+//! * `protected` - served only to callers authenticated as an Admin
+//! * `false` - not served, `GET /graphql` returns 404
+
+use std::{ env, sync::Arc };
+
+use async_graphql_axum::{ GraphQLRequest, GraphQLResponse };
+use aws_sdk_dynamodb::Client;
+use axum::{
+    extract::Extension,
+    http::{ HeaderMap, Method, StatusCode },
+    response::{ Html, IntoResponse },
+    routing::get,
+    Json,
+    Router,
+};
+use chrono::Utc;
+use tracing::{ error, info, warn };
+use tower::builder::ServiceBuilder;
+use tower_http::{ compression::CompressionLayer, cors::{ Any, CorsLayer } };
+
+use crate::{
+    auth::{ self, credentials::SharedCredentials, guard::is_admin },
+    db::health::{ readiness, ReadinessStatus },
+    error::AppError,
+    locale::Locale,
+    schema::AppSchema,
+};
+
+// Handler for graphql requests, attaches claims to the request data when a bearer token or api key is sent
+// and the locale error messages are translated into
+async fn graphql_handler(
+    Extension(schema): Extension<AppSchema>,
+    Extension(credentials): Extension<SharedCredentials>,
+    headers: HeaderMap,
+    req: GraphQLRequest
+) -> Result<GraphQLResponse, AppError> {
+    let mut req = req.into_inner().data(Locale::from_headers(&headers));
+
+    let now = Utc::now();
+    if let Some(claims) = auth::middleware::request_claims(&headers, &*credentials, now).await? {
+        req = req.data(claims);
+    }
+
+    Ok(schema.execute(req).await.into())
+}
+
+/// Who the GraphiQL playground is served to, see the module docs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PlaygroundMode {
+    Open,
+    Protected,
+    Off,
+}
+
+impl PlaygroundMode {
+    /// Reads the mode from the `ENABLE_PLAYGROUND` env var
+    pub(crate) fn from_env() -> Self {
+        match env::var("ENABLE_PLAYGROUND") {
+            Err(_) => Self::Open,
+            Ok(value) =>
+                match value.trim().to_ascii_lowercase().as_str() {
+                    "" | "true" => Self::Open,
+                    "protected" => Self::Protected,
+                    "false" => Self::Off,
+                    other => {
+                        warn!("Unknown ENABLE_PLAYGROUND value {:?}, disabling the playground", other);
+                        Self::Off
+                    }
+                }
+        }
+    }
+}
+
+// Handler for readiness probes, 200 while the app can serve requests, even degraded, 503 when it is down
+async fn readiness_handler(Extension(db_client): Extension<Client>) -> impl IntoResponse {
+    let readiness = readiness(&db_client).await;
+    let status = match readiness.status {
+        ReadinessStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        ReadinessStatus::Ok | ReadinessStatus::Degraded => StatusCode::OK,
+    };
+
+    (status, Json(readiness.to_json()))
+}
+
+// Handler for graphql playground
+async fn graphql_playground() -> impl IntoResponse {
+    Html(async_graphql::http::GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+// Handler for graphql playground when it is protected, only Admins may view it
+async fn protected_graphql_playground(
+    Extension(credentials): Extension<SharedCredentials>,
+    headers: HeaderMap
+) -> Result<impl IntoResponse, AppError> {
+    let claims = auth::middleware
+        ::request_claims(&headers, &*credentials, Utc::now()).await?
+        .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+
+    if !is_admin(&claims) {
+        return Err(AppError::Forbidden("Admin role is required".to_string()));
+    }
+
+    Ok(graphql_playground().await)
+}
+
+// Handler for GET requests when the playground is disabled
+async fn playground_disabled() -> AppError {
+    AppError::NotFound("Not found".to_string())
+}
+
+/// Builds the app's routes, without binding a port
+///
+/// # Arguments
+///
+/// * `schema` - the built GraphQL schema
+/// * `db_client` - DynamoDB client, shared with handlers through an extension
+/// * `credentials` - where request credentials are looked up, the DynamoDB client outside of tests
+/// * `playground` - who `GET /graphql` serves the playground to, see `PlaygroundMode::from_env`
+pub(crate) fn router(
+    schema: AppSchema,
+    db_client: Client,
+    credentials: SharedCredentials,
+    playground: PlaygroundMode
+) -> Router {
+    // Configure cors
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers(Any);
+
+    // Initialize axum router and add route endpoints
+    let playground = match playground {
+        PlaygroundMode::Open => get(graphql_playground),
+        PlaygroundMode::Protected => get(protected_graphql_playground),
+        PlaygroundMode::Off => get(playground_disabled),
+    };
+    let app = Router::new()
+        .route("/graphql", playground.post(graphql_handler))
+        .route("/ready", get(readiness_handler));
+    // .layer(from_fn(auth::middleware::auth_middleware));
+
+    app.layer(
+        ServiceBuilder::new()
+            .layer(CompressionLayer::new().gzip(true).deflate(true).br(true))
+            .layer(Extension(db_client))
+            .layer(Extension(credentials))
+            .layer(Extension(schema))
+            .layer(cors)
+    )
+}
+
+/// Runs the local axum server until it is shut down
+///
+/// # Arguments
+///
+/// * `schema` - the built GraphQL schema
+/// * `db_client` - DynamoDB client, shared with handlers through an extension
+pub async fn run(schema: AppSchema, db_client: Client) {
+    let credentials: SharedCredentials = Arc::new(db_client.clone());
+    let app = router(schema, db_client, credentials, PlaygroundMode::from_env());
+
+    // Run app with hyper, listen globally on port 3000
+    let listener = match tokio::net::TcpListener::bind(&"0.0.0.0:3000").await {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Fatal error during startup: {}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("Server running on http://localhost:3000");
+    axum::serve(listener, app).await.unwrap_or_else(|e| {
+        error!("Fatal error during startup: {}", e);
+        std::process::exit(1);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_config::{ BehaviorVersion, Region };
+    use axum::{ body::Body, http::{ header::AUTHORIZATION, Request } };
+    use tower::ServiceExt;
+
+    use crate::auth::{ api_key::API_KEY_HEADER, credentials::StubCredentials, jwt::create_token };
+    use crate::schema::build_schema;
+
+    use super::*;
+
+    fn app(playground: PlaygroundMode) -> Router {
+        // never connected, the routes tested here don't reach the db
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build();
+        let db_client = Client::from_conf(config);
+        let credentials = StubCredentials::default()
+            .with_api_key("admin-key", "ops", "Admin", false)
+            .with_user("user-1", 0);
+
+        router(build_schema(&db_client, None, None), db_client, Arc::new(credentials), playground)
+    }
+
+    async fn get_playground(
+        playground: PlaygroundMode,
+        header: Option<(&str, String)>
+    ) -> StatusCode {
+        let mut request = Request::get("/graphql");
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+
+        app(playground).oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn playground_is_not_found_when_off() {
+        assert_eq!(get_playground(PlaygroundMode::Off, None).await, StatusCode::NOT_FOUND);
+        assert_eq!(get_playground(PlaygroundMode::Open, None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn protected_playground_is_only_served_to_admins() {
+        let protected = PlaygroundMode::Protected;
+        assert_eq!(get_playground(protected, None).await, StatusCode::UNAUTHORIZED);
+
+        // the same secret as the jwt tests, so they can run in parallel
+        env::set_var("JWT_SECRET", "test-secret");
+        let token = create_token("user-1", "ana@example.com", "User", None, 0, Utc::now()).unwrap();
+        let user = (AUTHORIZATION.as_str(), format!("Bearer {}", token.token));
+        assert_eq!(get_playground(protected, Some(user)).await, StatusCode::FORBIDDEN);
+
+        let admin = (API_KEY_HEADER, "admin-key".to_string());
+        assert_eq!(get_playground(protected, Some(admin)).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,195 @@
+//! Outbound webhook notifications of pantry changes.
+//!
+//! Setting `WEBHOOK_URL` and `WEBHOOK_SECRET` turns them on. Opt status changes, from
+//! `updatePantry` or `bulkSetOptStatus`, and access grants from `setPantryAccess` are
+//! recorded in the outbox in the same transaction as the change, see `models::outbox`,
+//! and are then written pending. `run_outbox_poller` POSTs each pending event's JSON
+//! payload to the URL, e.g.
+//!
+//! `{ "id": "...", "event": "pantry.opt_status_changed", "pantryId": "...",
+//!    "pantryName": "...", "from": "T1", "to": "T3", "changedAt": "...", "actor": "..." }`
+//!
+//! with an `X-Webhook-Signature: sha256=<hex>` header holding the HMAC-SHA256 of the body
+//! under the shared secret, so the receiver can check where it came from.
+//!
+//! An event stays pending until the receiver answers with a 2xx, failed deliveries are
+//! retried with backoff, see `db::outbox::retry_delay`. Delivery is at least once: an
+//! event whose delivery succeeded but couldn't be marked, or that two pollers picked up
+//! at once, is sent again. On Lambda the poller only runs while the execution environment
+//! is thawed, so events wait for the next invocation.
+
+use std::{ env, time::Duration };
+
+use aws_sdk_dynamodb::Client;
+use chrono::Utc;
+use hmac::{ Hmac, Mac };
+use sha2::Sha256;
+use tracing::{ debug, info, warn };
+
+use crate::{
+    db::outbox::{ due_events, mark_delivered, schedule_retry },
+    models::outbox::OutboxEvent,
+};
+
+/// How long a single delivery may take before it counts as failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the poller looks for due events
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most events delivered per poll
+const POLL_BATCH_SIZE: i32 = 25;
+
+/// Header carrying the body's HMAC-SHA256, as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Header carrying the event name, also sent in the body
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+
+/// Signs a webhook body with the shared secret
+///
+/// # Returns
+///
+/// The `X-Webhook-Signature` value, `sha256=` and the hex HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>
+        ::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Where webhook notifications are sent and what they are signed with
+#[derive(Clone, Debug)]
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+}
+
+impl WebhookNotifier {
+    /// Creates the notifier from `WEBHOOK_URL` and `WEBHOOK_SECRET`
+    ///
+    /// # Returns
+    ///
+    /// 'some' WebhookNotifier if both are set, 'none' when webhooks are off or the secret
+    /// is missing, unsigned notifications are never sent
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("WEBHOOK_URL").ok()?.trim().to_string();
+        if url.is_empty() {
+            return None;
+        }
+
+        let secret = env::var("WEBHOOK_SECRET").unwrap_or_default();
+        if secret.trim().is_empty() {
+            warn!("WEBHOOK_URL is set without WEBHOOK_SECRET, webhooks are disabled");
+            return None;
+        }
+
+        let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Failed to build the webhook http client, webhooks are disabled: {}", e);
+                return None;
+            }
+        };
+
+        info!(url, "Webhook notifications enabled");
+        Some(Self { client, url, secret })
+    }
+
+    /// POSTs an event's signed payload to the webhook once
+    ///
+    /// # Errors
+    ///
+    /// Returns why the delivery failed, a network error or a non 2xx status
+    async fn send(&self, event: &OutboxEvent) -> Result<(), String> {
+        let response = self.client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&self.secret, event.payload.as_bytes()))
+            .header(EVENT_HEADER, &event.event)
+            .body(event.payload.clone())
+            .send().await
+            .map_err(|e| e.to_string())?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(format!("refused with {}", status)),
+        }
+    }
+
+    /// Delivers the events due now, marking each delivered or scheduling its retry
+    async fn deliver_due(&self, client: &Client) {
+        let events = match due_events(client, Utc::now(), POLL_BATCH_SIZE).await {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to read the outbox: {}", e);
+                return;
+            }
+        };
+
+        for event in events {
+            let outcome = match self.send(&event).await {
+                Ok(()) => {
+                    debug!("Delivered {} webhook {}", event.event, event.id);
+                    mark_delivered(client, &event.id, Utc::now()).await
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to deliver {} webhook {}, attempt {}: {}",
+                        event.event,
+                        event.id,
+                        event.attempts + 1,
+                        e
+                    );
+                    schedule_retry(client, &event, Utc::now()).await
+                }
+            };
+
+            if let Err(e) = outcome {
+                warn!("Failed to update outbox event {}: {}", event.id, e);
+            }
+        }
+    }
+}
+
+/// Delivers outbox events to the webhook until the process exits
+///
+/// # Arguments
+///
+/// * `client` - DynamoDB client the outbox is read through
+/// * `notifier` - the configured webhook
+pub async fn run_outbox_poller(client: Client, notifier: WebhookNotifier) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        notifier.deliver_due(&client).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn signature_depends_on_the_secret() {
+        assert_ne!(sign("secret", b"{}"), sign("other", b"{}"));
+        assert!(sign("", b"{}").starts_with("sha256="));
+    }
+}